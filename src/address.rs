@@ -0,0 +1,266 @@
+// DECODES BITCOIN ADDRESSES INTO THE SCRIPT PUBKEY THEY PAY TO
+//
+//   BASE58CHECK   P2PKH AND P2SH (MAINNET, TESTNET AND REGTEST VERSION BYTES)
+//   BECH32        SEGWIT VERSION 0 (BIP173)
+//   BECH32M       SEGWIT VERSION 1 AND UP (BIP350)
+use std::fmt;
+
+use sha2::{Digest, Sha256}; // Importing functions for SHA-256 hashing
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BECH32_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressError {
+    InvalidCharacter,
+    InvalidChecksum,
+    InvalidLength,
+    MixedCase,
+    UnknownVersion(u8),
+    UnknownPrefix,
+    InvalidWitnessProgram,
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressError::InvalidCharacter => write!(f, "ADDRESS: INVALID CHARACTER"),
+            AddressError::InvalidChecksum => write!(f, "ADDRESS: INVALID CHECKSUM"),
+            AddressError::InvalidLength => write!(f, "ADDRESS: INVALID LENGTH"),
+            AddressError::MixedCase => write!(f, "ADDRESS: MIXED CASE"),
+            AddressError::UnknownVersion(version) => write!(f, "ADDRESS: UNKNOWN VERSION {}", version),
+            AddressError::UnknownPrefix => write!(f, "ADDRESS: UNKNOWN HUMAN READABLE PART"),
+            AddressError::InvalidWitnessProgram => write!(f, "ADDRESS: INVALID WITNESS PROGRAM"),
+        }
+    }
+}
+
+impl std::error::Error for AddressError {}
+
+pub fn address_to_script_pubkey(address: &str) -> Result<Vec<u8>, AddressError> {
+    let lower = address.to_ascii_lowercase();
+    if ["bc1", "tb1", "bcrt1"].iter().any(|prefix| lower.starts_with(prefix)) {
+        segwit_script_pubkey(address)
+    } else {
+        base58_script_pubkey(address)
+    }
+}
+
+fn base58_script_pubkey(address: &str) -> Result<Vec<u8>, AddressError> {
+    let payload = base58check_decode(address)?;
+    if payload.len() != 21 {
+        return Err(AddressError::InvalidLength);
+    }
+
+    let hash = &payload[1..];
+    match payload[0] {
+        // OP_DUP OP_HASH160 <20 BYTES> OP_EQUALVERIFY OP_CHECKSIG
+        0x00 | 0x6f => Ok([&[0x76, 0xa9, 0x14], hash, &[0x88, 0xac]].concat()),
+
+        // OP_HASH160 <20 BYTES> OP_EQUAL
+        0x05 | 0xc4 => Ok([&[0xa9, 0x14], hash, &[0x87]].concat()),
+
+        version => Err(AddressError::UnknownVersion(version)),
+    }
+}
+
+fn base58check_decode(address: &str) -> Result<Vec<u8>, AddressError> {
+    // BIG ENDIAN BASE 256 NUMBER, MULTIPLIED BY 58 AND ADDED TO ONE DIGIT AT A TIME
+    let mut number: Vec<u8> = Vec::new();
+    for character in address.bytes() {
+        let mut carry = BASE58_ALPHABET
+            .iter()
+            .position(|symbol| *symbol == character)
+            .ok_or(AddressError::InvalidCharacter)? as u32;
+        for byte in number.iter_mut().rev() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            number.insert(0, carry as u8);
+            carry >>= 8;
+        }
+    }
+
+    // EVERY LEADING '1' IS A LEADING ZERO BYTE
+    let zeros = address.bytes().take_while(|character| *character == b'1').count();
+    let mut bytes = vec![0u8; zeros];
+    bytes.extend(number);
+
+    if bytes.len() < 4 {
+        return Err(AddressError::InvalidLength);
+    }
+    let (payload, checksum) = bytes.split_at(bytes.len() - 4);
+    if Sha256::digest(Sha256::digest(payload))[..4] != *checksum {
+        return Err(AddressError::InvalidChecksum);
+    }
+
+    Ok(payload.to_vec())
+}
+
+fn segwit_script_pubkey(address: &str) -> Result<Vec<u8>, AddressError> {
+    if address.len() > 90 {
+        return Err(AddressError::InvalidLength);
+    }
+    if address.bytes().any(|c| c.is_ascii_lowercase()) && address.bytes().any(|c| c.is_ascii_uppercase()) {
+        return Err(AddressError::MixedCase);
+    }
+    let address = address.to_ascii_lowercase();
+
+    // THE HUMAN READABLE PART ENDS AT THE LAST '1'
+    let separator = address.rfind('1').ok_or(AddressError::UnknownPrefix)?;
+    let (hrp, data) = (&address[..separator], &address[separator + 1..]);
+    if !["bc", "tb", "bcrt"].contains(&hrp) {
+        return Err(AddressError::UnknownPrefix);
+    }
+
+    let values: Vec<u8> = data
+        .bytes()
+        .map(|character| BECH32_CHARSET.iter().position(|symbol| *symbol == character).map(|value| value as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or(AddressError::InvalidCharacter)?;
+    if values.len() < 7 {
+        return Err(AddressError::InvalidLength);
+    }
+
+    let version = values[0];
+    if version > 16 {
+        return Err(AddressError::UnknownVersion(version));
+    }
+
+    // VERSION 0 USES THE ORIGINAL BECH32 CHECKSUM, EVERYTHING LATER BECH32M
+    let expected = if version == 0 { BECH32_CONST } else { BECH32M_CONST };
+    if bech32_polymod(hrp, &values) != expected {
+        return Err(AddressError::InvalidChecksum);
+    }
+
+    let program = convert_bits(&values[1..values.len() - 6])?;
+    match (version, program.len()) {
+        (0, 20) | (0, 32) => {}
+        (0, _) => return Err(AddressError::InvalidWitnessProgram),
+        (_, 2..=40) => {}
+        _ => return Err(AddressError::InvalidWitnessProgram),
+    }
+
+    // OP_0 OR OP_1..OP_16, THEN THE PROGRAM PUSH
+    let version_opcode = if version == 0 { 0x00 } else { 0x50 + version };
+    Ok([&[version_opcode, program.len() as u8], program.as_slice()].concat())
+}
+
+fn bech32_polymod(hrp: &str, values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a_57b2, 0x2650_8e6d, 0x1ea1_19fa, 0x3d42_33dd, 0x2a14_62b3];
+
+    let expanded = hrp
+        .bytes()
+        .map(|character| character >> 5)
+        .chain(std::iter::once(0))
+        .chain(hrp.bytes().map(|character| character & 31))
+        .chain(values.iter().copied());
+
+    let mut checksum: u32 = 1;
+    for value in expanded {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x01ff_ffff) << 5) ^ value as u32;
+        for (bit, generator) in GENERATOR.iter().enumerate() {
+            if (top >> bit) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+// REGROUPS 5 BIT VALUES INTO BYTES, LEFTOVER PADDING MUST BE FEWER THAN 5 ZERO BITS
+fn convert_bits(values: &[u8]) -> Result<Vec<u8>, AddressError> {
+    let mut accumulator: u32 = 0;
+    let mut bits = 0;
+    let mut bytes: Vec<u8> = Vec::new();
+    for value in values {
+        accumulator = (accumulator << 5) | *value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((accumulator >> bits) as u8);
+        }
+    }
+
+    if bits >= 5 || (accumulator & ((1 << bits) - 1)) != 0 {
+        return Err(AddressError::InvalidWitnessProgram);
+    }
+    Ok(bytes)
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+    use crate::error::Result;
+    use crate::transaction::Transaction;
+
+    #[test]
+    fn test_known_addresses() {
+        for (address, script_pubkey) in [
+            ("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa", "76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac"),
+            ("3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy", "a914b472a266d0bd89c13706a4132ccfb16f7c3b9fcb87"),
+            ("BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4", "0014751e76e8199196d454941c45d1b3a323f1433bd6"),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+                "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            ),
+        ] {
+            assert_eq!(hex::encode(address_to_script_pubkey(address).unwrap()), script_pubkey);
+        }
+    }
+
+    #[test]
+    fn test_invalid_addresses() {
+        // ONE CHARACTER CHANGED
+        assert_eq!(
+            address_to_script_pubkey("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb"),
+            Err(AddressError::InvalidChecksum)
+        );
+        assert_eq!(
+            address_to_script_pubkey("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5"),
+            Err(AddressError::InvalidChecksum)
+        );
+
+        // '0' IS NOT IN THE BASE58 ALPHABET
+        assert_eq!(address_to_script_pubkey("10A"), Err(AddressError::InvalidCharacter));
+
+        assert_eq!(
+            address_to_script_pubkey("bc1qW508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"),
+            Err(AddressError::MixedCase)
+        );
+
+        // BIP350: A VERSION 1 PROGRAM WITH A BECH32 CHECKSUM IS INVALID
+        assert_eq!(
+            address_to_script_pubkey("bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd"),
+            Err(AddressError::InvalidChecksum)
+        );
+    }
+
+    #[test]
+    fn test_mempool_addresses() -> Result<()> {
+        let mut count = 0;
+
+        // EVERY PREVOUT IN THE MEMPOOL CARRIES BOTH ITS ADDRESS AND ITS SCRIPT PUBKEY
+        for entry in fs::read_dir("./mempool")? {
+            let transaction: Transaction = serde_json::from_str(&fs::read_to_string(entry?.path())?)?;
+            for input in transaction.vin.iter() {
+                let script_pubkey = address_to_script_pubkey(&input.prevout.scriptpubkey_address)?;
+                assert_eq!(hex::encode(script_pubkey), input.prevout.scriptpubkey);
+                count += 1;
+            }
+        }
+
+        assert!(count > 0);
+
+        Ok(())
+    }
+}
@@ -1,15 +1,40 @@
+use std::collections::HashSet; // Importing necessary modules
+use std::path::Path; // Importing necessary modules
 use std::time::{SystemTime, UNIX_EPOCH}; // Importing necessary modules
 use std::{fs::File, io::Write}; // Importing necessary modules
 
-use num_bigint::BigUint; // Importing necessary modules
-use num_traits::Num; // Importing necessary modules
+use crate::error::Result; // Importing necessary modules
+use crate::utxo::{Coin, UtxoSet}; // Importing the confirmed coins and when they were mined
+use crate::validation_checks::rejection::Rejection; // Importing the reasons a transaction is held out
+use crate::validation_checks::sigops::{transaction_sigop_cost, MAX_BLOCK_SIGOPS_COST}; // Importing the sigop cost counting
+use crate::validation_checks::timelock::{check_final, ChainTip, CoinAge}; // Importing the finality checks
 
-use crate::{block_mine::serialise_tx::double_sha256, error::Result}; // Importing necessary modules
+use super::knapsack::{assemble_block, FillMode, FillReport}; // Importing necessary modules
+use super::coinbase::{coinbase_prevout, CoinbaseBuilder}; // Importing necessary modules
+use super::package::{mempool_parents, BlockLimits}; // Importing necessary modules
+use super::pow::{bits_to_target, mine, BlockHeader}; // Importing necessary modules
+use super::subsidy::Network; // Importing necessary modules
+use super::merkle_root::generate_roots; // Importing necessary modules
+use super::serialise_tx::{tx_entry, TxEntry}; // Importing necessary modules
+use super::deserialise_tx::deserialise_tx; // Importing the raw transaction decoder
 
-use super::{merkle_root::generate_roots, serialise_tx::create_txid_tx_map}; // Importing necessary modules
+// DEFAULT HEIGHT OF THE BLOCK BEING MINED, ENCODED INTO THE COINBASE PER BIP34
+pub const BLOCK_HEIGHT: u32 = 900000;
+
+// SHOWS UP IN THE COINBASE SCRIPT SIG AFTER THE HEIGHT AND EXTRANONCE
+const COINBASE_TAG: &[u8] = b"code-challenge-2024";
+
+// ADDRESS THE BLOCK REWARD IS PAID TO
+const PAYOUT_ADDRESS: &str = "1Nh7uHdvY6fNwtQtM1G5EZAFPLC33B59rB";
+
+// WEIGHT KEPT FREE FOR THE HEADER AND COINBASE WHEN FILLING THE BLOCK
+const COINBASE_RESERVED_WEIGHT: usize = 7000;
+
+// SIGOP COST KEPT FREE FOR THE COINBASE, THE SAME AS BITCOIN CORE
+const COINBASE_RESERVED_SIGOPS_COST: usize = 400;
 
 // Convert hexadecimal representation to compact form
-fn target_to_compact(target_hex: &str) -> u32 {
+pub fn target_to_compact(target_hex: &str) -> u32 {
     // Parse the target from a hex string to a big number
     let target_bytes = hex::decode(target_hex).expect("Invalid hex string"); // Convert hexadecimal string to bytes
     let mut target_bytes = target_bytes.as_slice(); // Get a mutable reference to the byte slice
@@ -48,89 +73,246 @@ fn target_to_compact(target_hex: &str) -> u32 {
     };
 
     // Adjust for Bitcoin's compact format specification
-    let compact = if significant & 0x00800000 != 0 { // Check if the most significant bit is set
+    if significant & 0x00800000 != 0 { // Check if the most significant bit is set
         (significant >> 8) | ((exp + 1) << 24) // Adjust the compact format
     } else {
         significant | (exp << 24) // Adjust the compact format
-    };
+    }
+}
+
+// EVERYTHING THE BLOCK BUILDER NEEDS TO KNOW ABOUT THE RUN
+pub struct BlockConfig {
+    pub target: [u8; 32],        // Big endian target, rounded down to what the bits field can hold
+    pub weight_limit: usize,     // Weight of the whole block, header and coinbase included
+    pub mode: FillMode,
+    pub network: Network,
+    pub tip: ChainTip,           // Height and median time past the finality checks run against
+    pub prev_block_hash: [u8; 32], // Internal byte order, all zero when there is no chain to build on
+}
 
-    compact
+// A FILLED BLOCK WITH ITS COINBASE, READY TO BE MINED OR WRITTEN OUT AS IS
+pub struct BlockTemplate {
+    pub header: BlockHeader,
+    pub coinbase_tx: String,
+    pub txids: Vec<String>,
+    pub held_out: Vec<(String, Rejection)>, // Valid, but not final at this height and time
+    pub report: FillReport, // How close the block gets to the best possible fees
+    block: Vec<TxEntry>,
+    coinbase: CoinbaseBuilder,
+    network: Network,
 }
 
-// Create a valid block header using proof of work
-pub fn valid_block_header() -> Result<()> {
-    // VERSION
-    let version_int: u32 = 4; // Define the version number as 4
-    let version = hex::encode(version_int.to_le_bytes()); // Encode the version number in little endian hexadecimal
+// A TRANSACTION WHOSE LOCKS HAVE NOT EXPIRED CANNOT BE MINED YET, AND NEITHER CAN ANYTHING SPENDING IT
+fn hold_out_non_final(map: Vec<TxEntry>, tip: &ChainTip, utxos: Option<&UtxoSet>) -> (Vec<TxEntry>, Vec<(String, Rejection)>) {
+    let txids: HashSet<&str> = map.iter().map(|entry| entry.0.as_str()).collect();
+
+    // IN-MEMPOOL PARENTS CONFIRM IN THIS BLOCK AT THE EARLIEST, A CONFIRMED PREVOUT IS AS OLD AS THE
+    // UTXO SET SAYS. WITHOUT A SET THE MEMPOOL FILES DON'T SAY WHEN IT WAS MINED, SO ITS LOCK IS NOT CHECKED.
+    let mut held_out: Vec<Option<Rejection>> = map
+        .iter()
+        .map(|(_, transaction, _, _, _)| {
+            let coins: Vec<Option<CoinAge>> = transaction
+                .vin
+                .iter()
+                .map(|input| match txids.contains(input.txid.as_str()) {
+                    true => Some(CoinAge::unconfirmed(tip)),
+                    false => utxos.and_then(|utxos| utxos.get(&input.outpoint())).map(Coin::age),
+                })
+                .collect();
+            check_final(transaction, tip, &coins).err()
+        })
+        .collect();
+
+    // UNTIL NO MORE DESCENDANTS OF A HELD OUT TRANSACTION ARE FOUND
+    let parents = mempool_parents(&map);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in 0..map.len() {
+            if held_out[index].is_some() {
+                continue;
+            }
+            if let Some(parent) = parents[index].iter().find(|parent| held_out[**parent].is_some()) {
+                held_out[index] = Some(Rejection::ParentHeldOut { parent: map[*parent].0.clone() });
+                changed = true;
+            }
+        }
+    }
+
+    let mut kept: Vec<TxEntry> = Vec::new();
+    let mut rejections: Vec<(String, Rejection)> = Vec::new();
+    for (entry, rejection) in map.into_iter().zip(held_out) {
+        match rejection {
+            Some(rejection) => rejections.push((entry.0.clone(), rejection)),
+            None => kept.push(entry),
+        }
+    }
+    (kept, rejections)
+}
+
+// Select from the validated transactions and build the coinbase and header, with the nonce still at zero.
+// The UTXO set, when there is one, says when the confirmed coins the transactions spend were mined.
+pub fn block_template(map: Vec<TxEntry>, config: &BlockConfig, utxos: Option<&UtxoSet>) -> Result<BlockTemplate> {
+    // ONLY FINAL TRANSACTIONS CAN BE MINED AT THIS HEIGHT AND TIME
+    let (map, held_out) = hold_out_non_final(map, &config.tip, utxos);
+
+    // SIGOP COST IS THE SECOND BUDGET NEXT TO THE WEIGHT
+    let sigops: Vec<usize> = map.iter().map(|(_, transaction, _, _, _)| transaction_sigop_cost(transaction)).collect();
+    let limits = BlockLimits {
+        weight: config.weight_limit.saturating_sub(COINBASE_RESERVED_WEIGHT), // Leave room for the header and coinbase
+        sigops: MAX_BLOCK_SIGOPS_COST - COINBASE_RESERVED_SIGOPS_COST,
+    };
 
-    // PREVIOUS BLOCK HASH
-    let prev_block_hash =
-        "0000000000000000000000000000000000000000000000000000000000000000".to_string(); // Define the previous block hash
+    let (block, report) = assemble_block(map, &sigops, &limits, config.mode); // Parents always come before their children
 
-    let map = create_txid_tx_map()?; // Create a map of transaction IDs to transactions
-    let (merkel_root, coinbase_tx, _, txids) = generate_roots(map.clone())?; // Generate the Merkle root, coinbase transaction, and transaction IDs
+    // COINBASE
+    let coinbase = CoinbaseBuilder::new(config.tip.height)
+        .tag(COINBASE_TAG)
+        .payout_address(PAYOUT_ADDRESS, 1)?; // Pay the whole reward to a single address
+
+    let (merkel_root, coinbase_tx, _, txids) = generate_roots(&block, &coinbase, config.network)?; // Generate the Merkle root, coinbase transaction, and transaction IDs
 
     // TIME STAMP
     let current_time = SystemTime::now(); // Get the current system time
     let since_epoch = current_time.duration_since(UNIX_EPOCH).unwrap(); // Get the duration since the Unix epoch
-    let time_stamp_int = since_epoch.as_secs() as u32; // Convert the duration to seconds as u32
-    let time_stamp = hex::encode(time_stamp_int.to_le_bytes()); // Encode the timestamp in little endian hexadecimal
+    let time_stamp = (since_epoch.as_secs() as u32).max(config.tip.median_time_past + 1); // Must be after the median time past
 
     // TARGET BITS
-    let target = "0000ffff00000000000000000000000000000000000000000000000000000000"; // Define the target bits
-    let target_int = BigUint::from_str_radix(target, 16).expect("INVALID HEX IN THE BLOCK"); // Parse the target bits as a big integer
-    let bits = target_to_compact(target); // Convert the target bits to compact format
-    let bits_hex = format!("{:08x}", bits); // Format the compact format as hexadecimal string
-    let mut bits_in_bytes = hex::decode(&bits_hex)?; // Decode the compact format from hexadecimal
-    bits_in_bytes.reverse(); // Reverse the bytes
-    let bits_le = hex::encode(bits_in_bytes); // Encode the bytes in little endian hexadecimal
-
-    // NONCE
-    let mut nonce: u32 = 0; // Initialize the nonce to 0
+    let bits = target_to_compact(&hex::encode(config.target)); // Convert the target to compact format
 
-    let valid_block_header: String; // Declare a variable to store the valid block header
+    let header = BlockHeader {
+        version: 4,
+        prev_block_hash: config.prev_block_hash,
+        merkle_root: hex::decode(&merkel_root)?.try_into().expect("MERKLE ROOT IS 32 BYTES"),
+        time: time_stamp,
+        bits,
+        nonce: 0,
+    };
 
-    // POW LOGIC
-    loop { // Start a loop for proof of work logic
-        let nonce_hex = hex::encode(nonce.to_le_bytes()); // Convert the nonce to little endian hexadecimal
+    Ok(BlockTemplate { header, coinbase_tx, txids, held_out, report, block, coinbase, network: config.network })
+}
 
-        let mut block_header: String = String::new(); // Initialize an empty string to store the block header
+impl BlockTemplate {
+    // POW LOGIC, A NEW EXTRANONCE CHANGES THE COINBASE AND SO THE MERKLE ROOT
+    pub fn mine(mut self) -> Result<BlockTemplate> {
+        // MINE AGAINST THE TARGET THE BITS FIELD ACTUALLY ENCODES, THAT IS WHAT A VERIFIER CHECKS
+        let target = bits_to_target(self.header.bits).ok_or_else(|| failure::err_msg("TARGET HAS NO VALID COMPACT FORM"))?;
 
-        block_header.push_str(&version); // Append the version to the block header
-        block_header.push_str(&prev_block_hash); // Append the previous block hash to the block header
-        block_header.push_str(&merkel_root); // Append the Merkle root to the block header
-        block_header.push_str(&time_stamp); // Append the timestamp to the block header
-        block_header.push_str(&bits_le); // Append the target bits to the block header
-        block_header.push_str(&nonce_hex); // Append the nonce to the block header
+        let (block, coinbase, network) = (&self.block, &self.coinbase, self.network);
+        let mut rolled: Option<(String, Vec<String>)> = None;
+        self.header = mine(self.header, &target, |extranonce| {
+            let (merkel_root, rolled_coinbase_tx, _, rolled_txids) = generate_roots(block, &coinbase.clone().extranonce(extranonce), network)?;
+            rolled = Some((rolled_coinbase_tx, rolled_txids));
+            Ok(hex::decode(&merkel_root)?.try_into().expect("MERKLE ROOT IS 32 BYTES"))
+        })?;
 
-        let mut block_hash_bytes = double_sha256(&hex::decode(&block_header)?); // Calculate the double SHA-256 hash of the block header
-        block_hash_bytes.reverse(); // Reverse the bytes
+        if let Some((coinbase_tx, txids)) = rolled {
+            self.coinbase_tx = coinbase_tx;
+            self.txids = txids;
+        }
+        Ok(self)
+    }
 
-        let block_hash = hex::encode(block_hash_bytes); // Encode the block hash as hexadecimal
+    // PUT THE BLOCK HEADER, COINBASE TX, AND TXIDS IN THE OUTPUT FILE
+    pub fn write(&self, output: &Path) -> Result<()> {
+        let mut block_file = File::create(output)?; // Create or open the output file for writing
 
-        let block_hash_int =
-            BigUint::from_str_radix(&block_hash, 16).expect("Invalid hex in block hash"); // Parse the block hash as a big integer
+        writeln!(block_file, "{}", hex::encode(self.header.serialise()))?; // Write the block header to the file
+        writeln!(block_file, "{}", self.coinbase_tx)?; // Write the coinbase transaction to the file
 
-        if block_hash_int <= target_int { // Check if the block hash meets the target
-            println!("Valid nonce found: {}", nonce); // Print the valid nonce
-            valid_block_header = block_header; // Store the valid block header
-            break; // Exit the loop
+        for txid in self.txids.iter() { // Iterate over each transaction ID
+            writeln!(block_file, "{}", txid)?; // Write each transaction ID to the file
         }
 
-        nonce += 1; // Increment the nonce
+        Ok(()) // Return Ok indicating success
+    }
+
+    // Every transaction in the block, coinbase first, the way the UTXO set connects them
+    pub fn transactions(&self) -> Result<Vec<TxEntry>> {
+        let coinbase = tx_entry(deserialise_tx(&self.coinbase_tx, &[coinbase_prevout()])?)?;
+        Ok(std::iter::once(coinbase).chain(self.block.iter().cloned()).collect())
     }
+}
+
+// Create a valid block header using proof of work and write the block out, the mined block is handed back
+pub fn valid_block_header(map: Vec<TxEntry>, config: &BlockConfig, utxos: Option<&UtxoSet>, output: &Path) -> Result<BlockTemplate> {
+    let template = block_template(map, config, utxos)?.mine()?;
+    println!("Valid nonce found: {}", template.header.nonce); // Print the valid nonce
 
-    // PUT THE BLOCK HEADER, COINBASE TX, AND TXIDS IN THE OUTPUT.TXT FILE
-    let mut block_file = File::create("./output.txt")?; // Create or open the output.txt file for writing
+    let mut block_hash = template.header.hash(); // Calculate the block hash
+    block_hash.reverse(); // Reverse the bytes for display
+    println!("Block hash: {}", hex::encode(block_hash)); // Print the block hash
 
-    println!("{}", txids.len()); // Print the number of transaction IDs
+    println!("{}", template.txids.len()); // Print the number of transaction IDs
 
-    writeln!(block_file, "{}", valid_block_header)?; // Write the valid block header to the file
-    writeln!(block_file, "{}", coinbase_tx)?; // Write the coinbase transaction to the file
+    template.write(output)?;
+    Ok(template)
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block_mine::serialise_tx::tx_entry;
+    use crate::fixtures::load;
+    use crate::transaction::Transaction;
+    use crate::utxo::SnapshotCoin;
+
+    const TIP: ChainTip = ChainTip { height: 900_000, median_time_past: 1_750_000_000 };
 
-    for txid in txids { // Iterate over each transaction ID
-        writeln!(block_file, "{}", txid)?; // Write each transaction ID to the file
+    fn entry(txid_hash: &str, edit: impl FnOnce(&mut Transaction)) -> Result<TxEntry> {
+        let mut transaction = load(txid_hash)?;
+        edit(&mut transaction);
+        tx_entry(transaction)
     }
 
-    Ok(()) // Return Ok indicating success
+    #[test]
+    fn test_non_final_transactions_are_held_out() -> Result<()> {
+        let final_tx = entry("4e8b1cc2c9ed315153b2457dec94128db0ba0a1dcebc136e187d0c4a0a81ce15", |_| {})?;
+        let locked = entry("19175e830784abf518b3ca20319177fcdff20b0bc5253b984a4515e85ec116b4", |tx| {
+            tx.locktime = TIP.height;
+            tx.vin[0].sequence = 0xfffffffd;
+        })?;
+
+        // A CHILD OF THE LOCKED TRANSACTION, AND ONE WITH A RELATIVE LOCK ON THE FINAL ONE
+        let child = entry("01f16e8312f9c882e869d31a3ab386b94a38f6091f7e947c6f2ed2b3389f4406", |tx| {
+            tx.vin[0].txid = locked.0.clone();
+        })?;
+        let relative = entry("01f16e8312f9c882e869d31a3ab386b94a38f6091f7e947c6f2ed2b3389f4406", |tx| {
+            tx.version = 2;
+            tx.vin[1].txid = final_tx.0.clone();
+            tx.vin[1].sequence = 1;
+        })?;
+
+        let (kept, held_out) =
+            hold_out_non_final(vec![child.clone(), final_tx.clone(), relative.clone(), locked.clone()], &TIP, None);
+        assert_eq!(kept.iter().map(|entry| entry.0.clone()).collect::<Vec<_>>(), vec![final_tx.0]);
+        assert_eq!(
+            held_out,
+            vec![
+                (child.0, Rejection::ParentHeldOut { parent: locked.0.clone() }),
+                (relative.0, Rejection::RelativeHeightLock { input: 1, unlocks_at: 900_001, height: 900_000 }),
+                (
+                    locked.0,
+                    Rejection::NonFinalLockTime { locktime: 900_000, height: 900_000, median_time_past: 1_750_000_000 }
+                ),
+            ]
+        );
+
+        // A RELATIVE LOCK ON A CONFIRMED COIN IS ONLY CHECKED WHEN THE UTXO SET SAYS WHEN IT WAS MINED
+        let confirmed = entry("4e8b1cc2c9ed315153b2457dec94128db0ba0a1dcebc136e187d0c4a0a81ce15", |tx| {
+            tx.version = 2;
+            tx.vin[0].sequence = 20;
+        })?;
+        let input = &confirmed.1.vin[0];
+        let coin = Coin { prevout: input.prevout.clone(), height: 899_990, median_time_past: 0, is_coinbase: false };
+        let utxos: UtxoSet = std::iter::once(SnapshotCoin { outpoint: input.outpoint(), coin }).collect();
+
+        let (kept, _) = hold_out_non_final(vec![confirmed.clone()], &TIP, None);
+        assert_eq!(kept.len(), 1);
+        let (_, held_out) = hold_out_non_final(vec![confirmed.clone()], &TIP, Some(&utxos));
+        assert_eq!(held_out, vec![(confirmed.0, Rejection::RelativeHeightLock { input: 0, unlocks_at: 900_010, height: 900_000 })]);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,262 @@
+// LOCAL HEADER CHAIN: EVERY BLOCK MINED SO FAR, EACH ON TOP OF THE ONE BEFORE
+//
+// KEPT ON DISK AS JSON SO THE NEXT RUN MINES ON TOP OF THE LAST TIP. EVERY BLOCK ALSO KEEPS THE TXIDS
+// IT CONFIRMED, THOSE ARE LEFT OUT OF THE MEMPOOL FROM THEN ON SO IT DRAINS BLOCK BY BLOCK.
+//
+// THE FIRST BLOCK'S BITS ARE ALSO THE CHAIN'S PROOF OF WORK LIMIT, RETARGETING CAN ONLY MAKE IT HARDER.
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use num_bigint::BigUint; // Importing big numbers for the chainwork
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result; // Importing Result type from the crate
+use crate::validation_checks::timelock::ChainTip; // Importing the height and median time past of the next block
+
+use super::difficulty::{chainwork, retarget, DIFFICULTY_ADJUSTMENT_INTERVAL}; // Importing the difficulty adjustment
+use super::pow::{compact_to_target, hash_meets_target, BlockHeader, HEADER_SIZE}; // Importing the block header and target checks
+
+// BIP113 TAKES THE MEDIAN OF THE LAST 11 BLOCK TIMES
+pub const MEDIAN_TIME_SPAN: usize = 11;
+
+// A MINED BLOCK: ITS SERIALISED HEADER AND THE TXIDS IT CONFIRMED, COINBASE LEFT OUT
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChainBlock {
+    pub header: String,
+    pub txids: Vec<String>,
+}
+
+impl ChainBlock {
+    pub fn header(&self) -> Result<BlockHeader> {
+        let bytes: [u8; HEADER_SIZE] =
+            hex::decode(&self.header)?.try_into().map_err(|_| ChainError::MalformedHeader(self.header.clone()))?;
+        Ok(BlockHeader::deserialise(&bytes))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainError {
+    MalformedHeader(String),
+    DoesNotExtendTip { prev_block_hash: String, tip: String },
+    UnexpectedBits { bits: u32, expected: u32 },
+    HashAboveTarget(String),
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::MalformedHeader(header) => write!(f, "CHAIN: HEADER {} IS NOT {} BYTES OF HEX", header, HEADER_SIZE),
+            ChainError::DoesNotExtendTip { prev_block_hash, tip } => {
+                write!(f, "CHAIN: BLOCK BUILDS ON {} BUT THE TIP IS {}", prev_block_hash, tip)
+            }
+            ChainError::UnexpectedBits { bits, expected } => {
+                write!(f, "CHAIN: BLOCK HAS BITS {:08x} BUT THE CHAIN EXPECTS {:08x}", bits, expected)
+            }
+            ChainError::HashAboveTarget(hash) => write!(f, "CHAIN: BLOCK {} DOES NOT MEET ITS TARGET", hash),
+        }
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+// THE FIRST BLOCK IS MINED AT `base_height` ON TOP OF AN ALL ZERO HASH, THE BLOCK BEFORE IT IS NOT KNOWN
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Chain {
+    pub base_height: u32,
+    pub base_median_time_past: u32, // Until the chain has a block of its own
+    pub base_bits: u32,
+    pub blocks: Vec<ChainBlock>,
+}
+
+impl Chain {
+    pub fn new(base: &ChainTip, base_bits: u32) -> Chain {
+        Chain { base_height: base.height, base_median_time_past: base.median_time_past, base_bits, blocks: Vec::new() }
+    }
+
+    pub fn read(path: &Path) -> Result<Chain> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    // Hash of the last block in internal byte order, all zero before the first one
+    pub fn tip_hash(&self) -> Result<[u8; 32]> {
+        match self.blocks.last() {
+            Some(block) => Ok(block.header()?.hash()),
+            None => Ok([0; 32]),
+        }
+    }
+
+    // Height of the block mined next and the median time past of the last 11 blocks before it
+    pub fn next_tip(&self) -> Result<ChainTip> {
+        if self.blocks.is_empty() {
+            return Ok(ChainTip { height: self.base_height, median_time_past: self.base_median_time_past });
+        }
+
+        let mut times: Vec<u32> = Vec::new();
+        for block in self.blocks.iter().rev().take(MEDIAN_TIME_SPAN) {
+            times.push(block.header()?.time);
+        }
+        times.sort_unstable();
+        Ok(ChainTip { height: self.base_height + self.blocks.len() as u32, median_time_past: times[times.len() / 2] })
+    }
+
+    // Bits the next block has to carry: the last block's, except on a 2016 block boundary where the
+    // target follows how long the interval took. Without the whole interval in the chain it stays.
+    pub fn next_bits(&self) -> Result<u32> {
+        let Some(last) = self.blocks.last() else {
+            return Ok(self.base_bits);
+        };
+        let last = last.header()?;
+        let height = self.base_height + self.blocks.len() as u32;
+        let interval = DIFFICULTY_ADJUSTMENT_INTERVAL as usize;
+        if !height.is_multiple_of(DIFFICULTY_ADJUSTMENT_INTERVAL) || self.blocks.len() < interval {
+            return Ok(last.bits);
+        }
+
+        let first = self.blocks[self.blocks.len() - interval].header()?;
+        Ok(retarget(last.bits, first.time, last.time, &compact_to_target(self.base_bits)?))
+    }
+
+    pub fn chainwork(&self) -> Result<BigUint> {
+        let mut bits: Vec<u32> = Vec::new();
+        for block in self.blocks.iter() {
+            bits.push(block.header()?.bits);
+        }
+        Ok(chainwork(bits.into_iter()))
+    }
+
+    pub fn confirmed_txids(&self) -> HashSet<&str> {
+        self.blocks.iter().flat_map(|block| block.txids.iter().map(|txid| txid.as_str())).collect()
+    }
+
+    // Appends a mined block, it has to build on the current tip with the bits the chain expects and meet them
+    pub fn push(&mut self, header: &BlockHeader, txids: Vec<String>) -> Result<()> {
+        let tip = self.tip_hash()?;
+        if header.prev_block_hash != tip {
+            return Err(ChainError::DoesNotExtendTip {
+                prev_block_hash: display_hash(&header.prev_block_hash),
+                tip: display_hash(&tip),
+            }
+            .into());
+        }
+        let expected = self.next_bits()?;
+        if header.bits != expected {
+            return Err(ChainError::UnexpectedBits { bits: header.bits, expected }.into());
+        }
+        if !hash_meets_target(&header.hash(), &compact_to_target(header.bits)?) {
+            return Err(ChainError::HashAboveTarget(display_hash(&header.hash())).into());
+        }
+        self.blocks.push(ChainBlock { header: hex::encode(header.serialise()), txids });
+        Ok(())
+    }
+}
+
+fn display_hash(hash: &[u8; 32]) -> String {
+    let mut hash = *hash;
+    hash.reverse();
+    hex::encode(hash)
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block_mine::difficulty::{block_work, TARGET_SPACING};
+
+    const BASE: ChainTip = ChainTip { height: 900_000, median_time_past: 1_750_000_000 };
+
+    // ANY TARGET WITH THE TOP BIT CLEAR, SO MINING TAKES A COUPLE OF HASHES
+    const EASY_BITS: u32 = 0x207fffff;
+
+    // MINES A HEADER ON THE TIP WITH THE BITS THE CHAIN EXPECTS
+    fn mined(chain: &Chain, time: u32) -> Result<BlockHeader> {
+        let bits = chain.next_bits()?;
+        let target = compact_to_target(bits)?;
+        let mut header = BlockHeader { version: 4, prev_block_hash: chain.tip_hash()?, merkle_root: [7; 32], time, bits, nonce: 0 };
+        while !hash_meets_target(&header.hash(), &target) {
+            header.nonce += 1;
+        }
+        Ok(header)
+    }
+
+    #[test]
+    fn test_blocks_build_on_the_tip() -> Result<()> {
+        let mut chain = Chain::new(&BASE, EASY_BITS);
+        assert_eq!((chain.next_tip()?, chain.tip_hash()?), (BASE, [0; 32]));
+
+        // TIMES OUT OF ORDER, THE MEDIAN IS TAKEN OVER THE LAST 11 ONLY
+        let times = [1_750_000_600, 1_750_000_100, 1_750_000_300];
+        for (index, time) in times.iter().enumerate() {
+            let block = mined(&chain, *time)?;
+            chain.push(&block, vec![format!("{:064x}", index)])?;
+            assert_eq!(chain.tip_hash()?, block.hash());
+        }
+        assert_eq!(chain.next_tip()?, ChainTip { height: 900_003, median_time_past: 1_750_000_300 });
+        for time in 0..10 {
+            chain.push(&mined(&chain, 1_760_000_000 + time)?, Vec::new())?;
+        }
+        assert_eq!(chain.next_tip()?, ChainTip { height: 900_013, median_time_past: 1_760_000_004 });
+        assert!(chain.confirmed_txids().contains(format!("{:064x}", 2).as_str()));
+        assert_eq!(chain.chainwork()?, block_work(EASY_BITS) * 13u32);
+
+        // A BLOCK ON ANYTHING BUT THE TIP, A STALE OR AN ORPHAN ONE, IS REFUSED
+        let orphan = BlockHeader { prev_block_hash: [0; 32], ..mined(&chain, 1_760_000_100)? };
+        let error = chain.push(&orphan, Vec::new()).unwrap_err();
+        assert!(matches!(error.downcast::<ChainError>(), Ok(ChainError::DoesNotExtendTip { .. })));
+
+        // SO IS ONE WITH OTHER BITS THAN EXPECTED, OR THAT DOES NOT MEET THEM
+        let easier = BlockHeader { bits: 0x2100ffff, ..mined(&chain, 1_760_000_100)? };
+        let error = chain.push(&easier, Vec::new()).unwrap_err();
+        assert_eq!(error.downcast::<ChainError>().ok(), Some(ChainError::UnexpectedBits { bits: 0x2100ffff, expected: EASY_BITS }));
+        let mut unmined = mined(&chain, 1_760_000_100)?;
+        while hash_meets_target(&unmined.hash(), &compact_to_target(EASY_BITS)?) {
+            unmined.nonce += 1;
+        }
+        let error = chain.push(&unmined, Vec::new()).unwrap_err();
+        assert!(matches!(error.downcast::<ChainError>(), Ok(ChainError::HashAboveTarget(_))));
+
+        // THE CHAIN IS THE SAME AFTER A TRIP TO THE DISK
+        let path = std::env::temp_dir().join(format!("chain_{}.json", std::process::id()));
+        chain.write(&path)?;
+        let read = Chain::read(&path);
+        fs::remove_file(&path)?;
+        assert_eq!(read?, chain);
+        Ok(())
+    }
+
+    #[test]
+    fn test_retarget_every_2016_blocks() -> Result<()> {
+        // STARTING ON A BOUNDARY, THE FIRST INTERVAL IS ALL IN THE CHAIN
+        let base = ChainTip { height: 400 * DIFFICULTY_ADJUSTMENT_INTERVAL, median_time_past: 1_750_000_000 };
+        let mut chain = Chain::new(&base, EASY_BITS);
+        for index in 0..DIFFICULTY_ADJUSTMENT_INTERVAL {
+            assert_eq!(chain.next_bits()?, EASY_BITS);
+            chain.push(&mined(&chain, 1_750_000_001 + index * TARGET_SPACING / 8)?, Vec::new())?;
+        }
+
+        // EIGHT TIMES TOO FAST IS CLAMPED TO FOUR
+        assert_eq!(chain.next_bits()?, 0x201fffff);
+        let header = mined(&chain, 1_760_000_000)?;
+        chain.push(&header, Vec::new())?;
+        assert_eq!(chain.next_bits()?, 0x201fffff);
+
+        // SLOW BLOCKS CANNOT TAKE THE CHAIN BELOW THE DIFFICULTY IT STARTED AT
+        let mut chain = Chain::new(&base, EASY_BITS);
+        for index in 0..DIFFICULTY_ADJUSTMENT_INTERVAL {
+            chain.push(&mined(&chain, 1_750_000_001 + index * TARGET_SPACING * 8)?, Vec::new())?;
+        }
+        assert_eq!(chain.next_bits()?, EASY_BITS);
+
+        // A CHAIN THAT DOES NOT HOLD THE WHOLE INTERVAL KEEPS ITS BITS
+        let mut chain = Chain::new(&ChainTip { height: base.height - 1, ..base }, EASY_BITS);
+        chain.push(&mined(&chain, 1_750_000_001)?, Vec::new())?;
+        assert_eq!(chain.next_bits()?, EASY_BITS);
+        Ok(())
+    }
+}
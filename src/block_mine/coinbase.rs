@@ -0,0 +1,296 @@
+// BUILDS THE COINBASE AS A REGULAR `Transaction`, SO BOTH SERIALISATIONS COME FROM `serialise_tx`
+use std::fmt;
+
+use crate::address::address_to_script_pubkey; // Importing the address decoder
+use crate::error::Result; // Importing Result type from the crate
+use crate::transaction::{Input, Output, Prevout, Transaction}; // Importing the transaction structs
+use crate::validation_checks::script::{decode_num, encode_num, push_data, Script}; // Importing script number and push encoding
+
+use super::serialise_tx::double_sha256; // Importing the double_sha256 function
+
+// CONSENSUS BOUNDS ON THE COINBASE SCRIPT SIG LENGTH
+pub const MIN_COINBASE_SCRIPT_SIG: usize = 2;
+pub const MAX_COINBASE_SCRIPT_SIG: usize = 100;
+
+// THE WITNESS OF THE COINBASE INPUT, COMMITTED TO TOGETHER WITH THE WITNESS MERKLE ROOT
+pub const WITNESS_RESERVED_VALUE: [u8; 32] = [0; 32];
+
+// OP_RETURN PUSH(36) AND THE BIP141 COMMITMENT HEADER
+const WITNESS_COMMITMENT_HEADER: [u8; 6] = [0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoinbaseError {
+    NoPayouts,
+    ZeroShares,
+    ScriptSigSize(usize),
+}
+
+impl fmt::Display for CoinbaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoinbaseError::NoPayouts => write!(f, "COINBASE: NO PAYOUT SCRIPTS"),
+            CoinbaseError::ZeroShares => write!(f, "COINBASE: PAYOUT SHARES ADD UP TO ZERO"),
+            CoinbaseError::ScriptSigSize(size) => {
+                write!(
+                    f,
+                    "COINBASE: SCRIPT SIG IS {} BYTES, MUST BE {} TO {}",
+                    size, MIN_COINBASE_SCRIPT_SIG, MAX_COINBASE_SCRIPT_SIG
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for CoinbaseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoinbaseBuilder {
+    height: u32,
+    extranonce: u64,
+    tag: Vec<u8>,
+    payouts: Vec<(Vec<u8>, u64)>, // (SCRIPT PUBKEY, SHARE OF THE REWARD)
+    witness_root: Option<[u8; 32]>,
+}
+
+impl CoinbaseBuilder {
+    pub fn new(height: u32) -> CoinbaseBuilder {
+        CoinbaseBuilder { height, extranonce: 0, tag: Vec::new(), payouts: Vec::new(), witness_root: None }
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn extranonce(mut self, extranonce: u64) -> CoinbaseBuilder {
+        self.extranonce = extranonce;
+        self
+    }
+
+    // FREE FORM BYTES AFTER THE HEIGHT AND EXTRANONCE, E.G. A POOL NAME
+    pub fn tag(mut self, tag: &[u8]) -> CoinbaseBuilder {
+        self.tag = tag.to_vec();
+        self
+    }
+
+    // THE REWARD IS SPLIT OVER THE PAYOUTS IN PROPORTION TO THEIR SHARES
+    pub fn payout_script(mut self, script_pubkey: Vec<u8>, share: u64) -> CoinbaseBuilder {
+        self.payouts.push((script_pubkey, share));
+        self
+    }
+
+    pub fn payout_address(self, address: &str, share: u64) -> Result<CoinbaseBuilder> {
+        let script_pubkey = address_to_script_pubkey(address)?;
+        Ok(self.payout_script(script_pubkey, share))
+    }
+
+    // WITHOUT A WITNESS MERKLE ROOT THE COINBASE HAS NO WITNESS AND NO COMMITMENT OUTPUT
+    pub fn witness_root(mut self, witness_root: [u8; 32]) -> CoinbaseBuilder {
+        self.witness_root = Some(witness_root);
+        self
+    }
+
+    // BIP34 HEIGHT, THEN THE EXTRANONCE AND TAG AS PLAIN PUSHES
+    pub fn script_sig(&self) -> std::result::Result<Vec<u8>, CoinbaseError> {
+        let mut script_sig = bip34_height(self.height);
+        script_sig.extend(push_data(&self.extranonce.to_le_bytes()));
+        if !self.tag.is_empty() {
+            script_sig.extend(push_data(&self.tag));
+        }
+
+        if !(MIN_COINBASE_SCRIPT_SIG..=MAX_COINBASE_SCRIPT_SIG).contains(&script_sig.len()) {
+            return Err(CoinbaseError::ScriptSigSize(script_sig.len()));
+        }
+        Ok(script_sig)
+    }
+
+    // Builds the coinbase paying out `reward` (subsidy plus fees)
+    pub fn build(&self, reward: u64) -> Result<Transaction> {
+        if self.payouts.is_empty() {
+            return Err(CoinbaseError::NoPayouts.into());
+        }
+        // ADDED UP WIDER THAN THE SHARES THEMSELVES, SO NO NUMBER OF u64 SHARES CAN OVERFLOW IT
+        let total_shares: u128 = self.payouts.iter().map(|(_, share)| *share as u128).sum();
+        if total_shares == 0 {
+            return Err(CoinbaseError::ZeroShares.into());
+        }
+
+        let script_sig = self.script_sig()?;
+
+        let mut vout: Vec<Output> = Vec::new();
+        for (script_pubkey, share) in self.payouts.iter() {
+            let value = (reward as u128 * *share as u128 / total_shares) as u64;
+            vout.push(output(script_pubkey, value));
+        }
+
+        // ROUNDING LEFTOVERS GO TO THE FIRST PAYOUT
+        let paid: u64 = vout.iter().map(|output| output.value).sum();
+        vout[0].value += reward - paid;
+
+        let mut witness = None;
+        if let Some(witness_root) = self.witness_root {
+            let mut commitment = WITNESS_COMMITMENT_HEADER.to_vec();
+            commitment.extend(double_sha256(&[witness_root, WITNESS_RESERVED_VALUE].concat()));
+            vout.push(output(&commitment, 0));
+
+            witness = Some(vec![hex::encode(WITNESS_RESERVED_VALUE)]);
+        }
+
+        let scriptsig = Script::new(script_sig);
+        let input = Input {
+            txid: hex::encode([0u8; 32]),
+            vout: u32::MAX,
+            prevout: coinbase_prevout(),
+            scriptsig: Some(hex::encode(scriptsig.as_bytes())),
+            scriptsig_asm: Some(scriptsig.to_asm()),
+            witness,
+            is_coinbase: true,
+            sequence: u32::MAX,
+            inner_redeemscript_asm: None,
+        };
+
+        Ok(Transaction { version: 1, locktime: 0, vin: vec![input], vout })
+    }
+}
+
+// THE HEIGHT EXACTLY AS `CScript() << height` WRITES IT, WHICH IS WHAT BIP34 CHECKS
+pub fn bip34_height(height: u32) -> Vec<u8> {
+    match height {
+        0 => vec![0x00],                     // OP_0
+        1..=16 => vec![0x50 + height as u8], // OP_1 .. OP_16
+        _ => push_data(&encode_num(height as i64)),
+    }
+}
+
+// READS THE HEIGHT BACK FROM THE START OF A COINBASE SCRIPT SIG, `None` IF IT DOES NOT START WITH ONE
+pub fn parse_bip34_height(script_sig: &[u8]) -> Option<u32> {
+    match *script_sig.first()? {
+        0x00 => Some(0),
+        opcode @ 0x51..=0x60 => Some((opcode - 0x50) as u32),
+        len @ 0x01..=0x05 => {
+            let bytes = script_sig.get(1..1 + len as usize)?;
+            let height = decode_num(bytes, 5).ok()?;
+
+            // ONLY THE EXACT ENCODING `bip34_height` WRITES COUNTS
+            if bip34_height(u32::try_from(height).ok()?) != script_sig[..1 + len as usize] {
+                return None;
+            }
+            Some(height as u32)
+        }
+        _ => None,
+    }
+}
+
+// A COINBASE SPENDS NOTHING, ITS INPUT STILL NEEDS A PREVOUT TO FIT THE `Transaction` STRUCT
+pub fn coinbase_prevout() -> Prevout {
+    Prevout {
+        scriptpubkey: String::new(),
+        scriptpubkey_asm: String::new(),
+        scriptpubkey_type: String::new(),
+        scriptpubkey_address: String::new(),
+        value: 0,
+    }
+}
+
+fn output(script_pubkey: &[u8], value: u64) -> Output {
+    let script = Script::new(script_pubkey.to_vec());
+    Output {
+        scriptpubkey: hex::encode(script_pubkey),
+        scriptpubkey_asm: script.to_asm(),
+        scriptpubkey_type: script.script_type().to_string(),
+        scriptpubkey_address: None,
+        value,
+    }
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block_mine::serialise_tx::serialise_tx;
+
+    const PAYOUT_SCRIPT: &str = "76a914edf10a7fac6b32e24daa5305c723f3de58db1bc888ac";
+
+    #[test]
+    fn test_bip34_height() {
+        assert_eq!(hex::encode(bip34_height(0)), "00");
+        assert_eq!(hex::encode(bip34_height(16)), "60");
+        assert_eq!(hex::encode(bip34_height(17)), "0111");
+        assert_eq!(hex::encode(bip34_height(128)), "028000"); // Sign bit needs an extra byte
+        assert_eq!(hex::encode(bip34_height(227931)), "035b7a03"); // The BIP34 activation block
+        assert_eq!(hex::encode(bip34_height(900000)), "03a0bb0d");
+
+        for height in [0, 1, 16, 17, 127, 128, 255, 256, 32767, 32768, 227931, 900000, u32::MAX] {
+            let mut script_sig = bip34_height(height);
+            script_sig.extend(push_data(b"tag"));
+            assert_eq!(parse_bip34_height(&script_sig), Some(height));
+        }
+
+        assert_eq!(parse_bip34_height(&hex::decode("0100").unwrap()), None); // Not minimal
+        assert_eq!(parse_bip34_height(&hex::decode("03a0bb").unwrap()), None); // Truncated
+        assert_eq!(parse_bip34_height(&hex::decode("6a").unwrap()), None);
+    }
+
+    #[test]
+    fn test_coinbase_serialisations() -> Result<()> {
+        let builder = CoinbaseBuilder::new(900000)
+            .extranonce(7)
+            .tag(b"tag")
+            .payout_script(hex::decode(PAYOUT_SCRIPT)?, 1)
+            .witness_root([0x11; 32]);
+        let coinbase = builder.build(5000)?;
+
+        assert_eq!(hex::encode(builder.script_sig()?), "03a0bb0d08070000000000000003746167");
+        assert_eq!(coinbase.vout[0].value, 5000);
+        assert_eq!(coinbase.vout[1].value, 0);
+        assert!(coinbase.vout[1].scriptpubkey.starts_with("6a24aa21a9ed"));
+
+        // THE WITNESS RESERVED VALUE ONLY SHOWS UP IN THE WITNESS SERIALISATION
+        let (tx, wtx, _, fees) = serialise_tx(&coinbase)?;
+        assert_eq!(fees, 0);
+        assert_eq!(wtx.len(), tx.len() + 2 + 1 + 1 + 32);
+        assert_eq!(&wtx[4..6], &[0x00, 0x01]);
+
+        // WITHOUT A WITNESS ROOT BOTH ARE THE SAME AND THERE IS NO COMMITMENT
+        let coinbase = CoinbaseBuilder::new(900000).payout_script(hex::decode(PAYOUT_SCRIPT)?, 1).build(5000)?;
+        let (tx, wtx, _, _) = serialise_tx(&coinbase)?;
+        assert_eq!(tx, wtx);
+        assert_eq!(coinbase.vout.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reward_split() -> Result<()> {
+        let coinbase = CoinbaseBuilder::new(1)
+            .payout_script(hex::decode(PAYOUT_SCRIPT)?, 1)
+            .payout_address("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4", 2)?
+            .build(1000)?;
+
+        // 1000 / 3 ROUNDS DOWN, THE FIRST PAYOUT TAKES THE REMAINDER
+        assert_eq!(coinbase.vout[0].value, 334);
+        assert_eq!(coinbase.vout[1].value, 666);
+        assert_eq!(coinbase.vout[1].scriptpubkey_type, "v0_p2wpkh");
+
+        // SHARES THAT ADD UP PAST u64::MAX STILL SPLIT THE REWARD
+        let coinbase = CoinbaseBuilder::new(1)
+            .payout_script(hex::decode(PAYOUT_SCRIPT)?, u64::MAX)
+            .payout_script(hex::decode(PAYOUT_SCRIPT)?, u64::MAX)
+            .build(1000)?;
+        assert_eq!((coinbase.vout[0].value, coinbase.vout[1].value), (500, 500));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_coinbases() -> Result<()> {
+        assert!(CoinbaseBuilder::new(1).build(1000).is_err());
+        assert!(CoinbaseBuilder::new(1).payout_script(hex::decode(PAYOUT_SCRIPT)?, 0).build(1000).is_err());
+        assert!(CoinbaseBuilder::new(1).payout_address("not an address", 1).is_err());
+
+        // HEIGHT (4) + EXTRANONCE (9) + TAG (OP_PUSHDATA1 + 1 + 90) GOES OVER 100 BYTES
+        let builder = CoinbaseBuilder::new(900000).tag(&[b'x'; 90]);
+        assert_eq!(builder.script_sig(), Err(CoinbaseError::ScriptSigSize(105)));
+
+        Ok(())
+    }
+}
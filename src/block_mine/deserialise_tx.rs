@@ -0,0 +1,262 @@
+// PARSES RAW (CONSENSUS SERIALISED) TRANSACTIONS BACK INTO THE TRANSACTION STRUCT
+use std::fmt;
+
+use crate::compact_size::{self, CompactSizeError}; // Importing the CompactSize decoder
+use crate::transaction::{Input, Output, Prevout, Transaction}; // Importing the transaction structs
+use crate::validation_checks::script::Script; // Importing Script for asm and output types
+use crate::error::Result; // Importing Result type from the crate
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeserialiseError {
+    UnexpectedEnd,
+    TrailingBytes(usize),
+    UnknownFlag(u8),
+    SuperfluousWitness,
+    CompactSize(CompactSizeError),
+    PrevoutCount { inputs: usize, prevouts: usize },
+}
+
+impl fmt::Display for DeserialiseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeserialiseError::UnexpectedEnd => write!(f, "RAW TX: UNEXPECTED END OF DATA"),
+            DeserialiseError::TrailingBytes(count) => write!(f, "RAW TX: {} TRAILING BYTES", count),
+            DeserialiseError::UnknownFlag(flag) => write!(f, "RAW TX: UNKNOWN SEGWIT FLAG {:#04x}", flag),
+            DeserialiseError::SuperfluousWitness => write!(f, "RAW TX: SEGWIT FLAG WITHOUT WITNESS DATA"),
+            DeserialiseError::CompactSize(error) => write!(f, "RAW TX: {}", error),
+            DeserialiseError::PrevoutCount { inputs, prevouts } => {
+                write!(f, "RAW TX: {} INPUTS BUT {} PREVOUTS", inputs, prevouts)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DeserialiseError {}
+
+impl From<CompactSizeError> for DeserialiseError {
+    fn from(error: CompactSizeError) -> DeserialiseError {
+        DeserialiseError::CompactSize(error)
+    }
+}
+
+// THE RAW BYTES DO NOT CARRY THE SPENT OUTPUTS, SO ONE PREVOUT PER INPUT HAS TO BE SUPPLIED
+pub fn deserialise_tx(raw_tx_hex: &str, prevouts: &[Prevout]) -> Result<Transaction> {
+    let raw_tx = hex::decode(raw_tx_hex.trim())?;
+    Ok(deserialise_tx_bytes(&raw_tx, prevouts)?)
+}
+
+pub fn deserialise_tx_bytes(raw_tx: &[u8], prevouts: &[Prevout]) -> std::result::Result<Transaction, DeserialiseError> {
+    let mut reader = Reader { bytes: raw_tx, position: 0 };
+
+    // VERSION
+    let version = i32::from_le_bytes(reader.read_array()?);
+
+    // MARKER FLAG: A ZERO INPUT COUNT FOLLOWED BY A NON-ZERO FLAG MEANS SEGWIT
+    let mut vin_len = reader.read_compact_size()?;
+    let mut segwit = false;
+    if vin_len == 0 {
+        let flag = reader.read_array::<1>()?[0];
+        if flag != 0 {
+            if flag != 0x01 {
+                return Err(DeserialiseError::UnknownFlag(flag));
+            }
+            segwit = true;
+            vin_len = reader.read_compact_size()?;
+        }
+    }
+
+    if vin_len != prevouts.len() {
+        return Err(DeserialiseError::PrevoutCount { inputs: vin_len, prevouts: prevouts.len() });
+    }
+
+    // INPUTS
+    let mut vin: Vec<Input> = Vec::new();
+    for prevout in prevouts.iter() {
+        // TXID REVERSED
+        let mut txid = reader.read_bytes(32)?.to_vec();
+        txid.reverse();
+
+        let vout = u32::from_le_bytes(reader.read_array()?);
+
+        // SCRIPT SIG
+        let scriptsig_len = reader.read_compact_size()?;
+        let scriptsig = Script::new(reader.read_bytes(scriptsig_len)?.to_vec());
+
+        let sequence = u32::from_le_bytes(reader.read_array()?);
+
+        vin.push(Input {
+            is_coinbase: txid.iter().all(|byte| *byte == 0) && vout == u32::MAX,
+            txid: hex::encode(txid),
+            vout,
+            prevout: prevout.clone(),
+            scriptsig: Some(hex::encode(scriptsig.as_bytes())),
+            scriptsig_asm: Some(scriptsig.to_asm()),
+            witness: None,
+            sequence,
+            inner_redeemscript_asm: None,
+        });
+    }
+
+    // OUTPUTS
+    let vout_len = reader.read_compact_size()?;
+    let mut vout: Vec<Output> = Vec::new();
+    for _ in 0..vout_len {
+        let value = u64::from_le_bytes(reader.read_array()?);
+
+        let scriptpubkey_len = reader.read_compact_size()?;
+        let scriptpubkey = Script::new(reader.read_bytes(scriptpubkey_len)?.to_vec());
+
+        vout.push(Output {
+            scriptpubkey: hex::encode(scriptpubkey.as_bytes()),
+            scriptpubkey_asm: scriptpubkey.to_asm(),
+            scriptpubkey_type: scriptpubkey.script_type().to_string(),
+            scriptpubkey_address: None,
+            value,
+        });
+    }
+
+    // WITNESS, ONE STACK PER INPUT. INPUTS WITHOUT ONE KEEP `None` LIKE IN THE MEMPOOL JSON
+    if segwit {
+        for input in vin.iter_mut() {
+            let item_count = reader.read_compact_size()?;
+            let mut witness: Vec<String> = Vec::new();
+            for _ in 0..item_count {
+                let item_len = reader.read_compact_size()?;
+                witness.push(hex::encode(reader.read_bytes(item_len)?));
+            }
+
+            if !witness.is_empty() {
+                input.witness = Some(witness);
+            }
+        }
+
+        if vin.iter().all(|input| input.witness.is_none()) {
+            return Err(DeserialiseError::SuperfluousWitness);
+        }
+    }
+
+    // LOCKTIME
+    let locktime = u32::from_le_bytes(reader.read_array()?);
+
+    if reader.position != raw_tx.len() {
+        return Err(DeserialiseError::TrailingBytes(raw_tx.len() - reader.position));
+    }
+
+    Ok(Transaction { version, locktime, vin, vout })
+}
+
+// HOW MANY PREVOUTS `deserialise_tx_bytes` WILL ASK FOR, READ WITHOUT DECODING THE REST
+pub fn input_count(raw_tx: &[u8]) -> std::result::Result<usize, DeserialiseError> {
+    let mut reader = Reader { bytes: raw_tx, position: 0 };
+    reader.read_array::<4>()?;
+
+    let vin_len = reader.read_compact_size()?;
+    if vin_len == 0 && reader.read_array::<1>()?[0] != 0 {
+        return reader.read_compact_size();
+    }
+    Ok(vin_len)
+}
+
+// CURSOR OVER THE RAW BYTES, EVERY READ IS BOUNDS CHECKED
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, len: usize) -> std::result::Result<&'a [u8], DeserialiseError> {
+        let end = self.position.checked_add(len).ok_or(DeserialiseError::UnexpectedEnd)?;
+        let bytes = self.bytes.get(self.position..end).ok_or(DeserialiseError::UnexpectedEnd)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> std::result::Result<[u8; N], DeserialiseError> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    fn read_compact_size(&mut self) -> std::result::Result<usize, DeserialiseError> {
+        let (value, consumed) = compact_size::decode(&self.bytes[self.position..])?;
+        self.position += consumed;
+        Ok(value)
+    }
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+    use crate::block_mine::serialise_tx::serialise_tx;
+    use crate::fixtures::load;
+
+    fn prevouts(tx: &Transaction) -> Vec<Prevout> {
+        tx.vin.iter().map(|input| input.prevout.clone()).collect()
+    }
+
+    #[test]
+    fn test_round_trip_mempool() -> Result<()> {
+        for entry in fs::read_dir("./mempool")? {
+            let transaction: Transaction = serde_json::from_str(&fs::read_to_string(entry?.path())?)?;
+            let (raw_tx, raw_wtx, _, _) = serialise_tx(&transaction)?;
+
+            // DECODING THE WTX MUST GIVE BACK BOTH SERIALISATIONS
+            let decoded = deserialise_tx(&hex::encode(&raw_wtx), &prevouts(&transaction))?;
+            assert_eq!(serialise_tx(&decoded)?.1, raw_wtx);
+            assert_eq!(serialise_tx(&decoded)?.0, raw_tx);
+
+            assert_eq!(decoded.version, transaction.version);
+            assert_eq!(decoded.locktime, transaction.locktime);
+            for (decoded_input, input) in decoded.vin.iter().zip(transaction.vin.iter()) {
+                assert_eq!(decoded_input.txid, input.txid);
+                assert_eq!(decoded_input.vout, input.vout);
+                assert_eq!(decoded_input.scriptsig, input.scriptsig);
+                assert_eq!(decoded_input.scriptsig_asm, input.scriptsig_asm);
+                assert_eq!(decoded_input.witness, input.witness);
+                assert_eq!(decoded_input.sequence, input.sequence);
+            }
+            for (decoded_output, output) in decoded.vout.iter().zip(transaction.vout.iter()) {
+                assert_eq!(decoded_output.scriptpubkey, output.scriptpubkey);
+                assert_eq!(decoded_output.scriptpubkey_type, output.scriptpubkey_type);
+                assert_eq!(decoded_output.value, output.value);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_raw_txs() -> Result<()> {
+        let transaction = load("fcc4d2ad88b7a040dc98ae29946b794258ae7c8ba1a4300a6fc761d0c9cb6a1f")?;
+        let (_, raw_wtx, _, _) = serialise_tx(&transaction)?;
+        let prevouts = prevouts(&transaction);
+
+        // TRUNCATED AT EVERY POSSIBLE LENGTH
+        for len in 0..raw_wtx.len() {
+            assert!(deserialise_tx_bytes(&raw_wtx[..len], &prevouts).is_err());
+        }
+
+        let mut trailing = raw_wtx.clone();
+        trailing.push(0);
+        assert_eq!(deserialise_tx_bytes(&trailing, &prevouts).err(), Some(DeserialiseError::TrailingBytes(1)));
+
+        let mut unknown_flag = raw_wtx.clone();
+        unknown_flag[5] = 0x02;
+        assert_eq!(deserialise_tx_bytes(&unknown_flag, &prevouts).err(), Some(DeserialiseError::UnknownFlag(0x02)));
+
+        assert_eq!(
+            deserialise_tx_bytes(&raw_wtx, &prevouts[1..]).err(),
+            Some(DeserialiseError::PrevoutCount { inputs: prevouts.len(), prevouts: prevouts.len() - 1 })
+        );
+
+        // THE INPUT COUNT IS READABLE BEFORE ANY PREVOUT IS KNOWN
+        assert_eq!(input_count(&raw_wtx), Ok(prevouts.len()));
+        assert_eq!(input_count(&serialise_tx(&transaction)?.0), Ok(prevouts.len()));
+        assert!(input_count(&raw_wtx[..4]).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+// DIFFICULTY: HOW MUCH WORK A TARGET STANDS FOR, AND HOW THE TARGET FOLLOWS THE BLOCK TIMES
+//
+// EVERY 2016 BLOCKS THE TARGET IS SCALED BY HOW LONG THOSE BLOCKS TOOK AGAINST THE TWO WEEKS THEY
+// SHOULD HAVE TAKEN, AT MOST 4X EITHER WAY AND NEVER EASIER THAN THE PROOF OF WORK LIMIT.
+use num_bigint::BigUint; // Importing big numbers for 256 bit targets
+use num_traits::One; // Importing the one constant for big numbers
+
+use super::block::target_to_compact; // Importing the target to bits conversion
+use super::pow::compact_to_target; // Importing the bits to target conversion
+
+pub const DIFFICULTY_ADJUSTMENT_INTERVAL: u32 = 2016;
+pub const TARGET_SPACING: u32 = 10 * 60;
+pub const TARGET_TIMESPAN: u32 = DIFFICULTY_ADJUSTMENT_INTERVAL * TARGET_SPACING;
+
+// Expected number of hashes to meet the target, 2^256 / (target + 1). Bits no hash can meet are worth nothing
+pub fn block_work(bits: u32) -> BigUint {
+    match compact_to_target(bits) {
+        Ok(target) => (BigUint::one() << 256u32) / (BigUint::from_bytes_be(&target) + 1u32),
+        Err(_) => BigUint::default(),
+    }
+}
+
+// Total work of a chain, the most of it decides between competing tips
+pub fn chainwork(bits: impl Iterator<Item = u32>) -> BigUint {
+    bits.map(block_work).sum()
+}
+
+// Same as Bitcoin Core's `CalculateNextWorkRequired`, the times are those of the first and last block
+// of the interval that just ended and `last_bits` what the last one was mined at
+pub fn retarget(last_bits: u32, first_time: u32, last_time: u32, pow_limit: &[u8; 32]) -> u32 {
+    let minimum = TARGET_TIMESPAN as i64 / 4;
+    let maximum = TARGET_TIMESPAN as i64 * 4;
+    let timespan = (last_time as i64 - first_time as i64).clamp(minimum, maximum) as u32;
+
+    let limit = BigUint::from_bytes_be(pow_limit);
+    let last_target = match compact_to_target(last_bits) {
+        Ok(target) => BigUint::from_bytes_be(&target),
+        Err(_) => limit.clone(),
+    };
+    let target = (last_target * timespan / TARGET_TIMESPAN).min(limit);
+
+    // BACK TO 32 BIG ENDIAN BYTES, THE LIMIT KEEPS IT FROM GROWING PAST THEM
+    let bytes = target.to_bytes_be();
+    let mut padded = [0u8; 32];
+    padded[32 - bytes.len()..].copy_from_slice(&bytes);
+    target_to_compact(&hex::encode(padded))
+}
+
+// Chainwork the way Bitcoin Core prints it, 64 hex digits
+pub fn work_hex(work: &BigUint) -> String {
+    format!("{:0>64}", work.to_str_radix(16))
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MAINNET_POW_LIMIT: u32 = 0x1d00ffff;
+
+    fn pow_limit() -> [u8; 32] {
+        compact_to_target(MAINNET_POW_LIMIT).unwrap()
+    }
+
+    #[test]
+    fn test_compact_round_trip() {
+        for bits in [0x1d00ffff, 0x1b0404cb, 0x17034219, 0x1f00ffff, 0x207fffff, 0x01120000, 0x02123400, 0x03123456] {
+            let target = compact_to_target(bits).unwrap();
+            assert_eq!(target_to_compact(&hex::encode(target)), bits, "{:08x}", bits);
+        }
+    }
+
+    #[test]
+    fn test_block_work_and_chainwork() {
+        // DIFFICULTY 1 IS 2^32 + 2^16 + 1 HASHES, THE GENESIS BLOCK'S CHAINWORK
+        assert_eq!(block_work(MAINNET_POW_LIMIT), BigUint::from(0x0001_0001_0001u64));
+        assert_eq!(work_hex(&block_work(MAINNET_POW_LIMIT)), format!("{:064x}", 0x0001_0001_0001u64));
+
+        // THE LOWEST POSSIBLE TARGET NEEDS EVERY HASH, INVALID BITS ARE NO WORK AT ALL
+        assert_eq!(block_work(0x01010000), BigUint::one() << 255u32);
+        assert_eq!(block_work(0x04923456), BigUint::default());
+
+        // WORK ADDS UP, EVEN WHEN THE DIFFICULTY CHANGES ALONG THE WAY
+        let work = chainwork([MAINNET_POW_LIMIT, MAINNET_POW_LIMIT, 0x1c7fff80].into_iter());
+        assert_eq!(work, block_work(MAINNET_POW_LIMIT) * 4u32);
+    }
+
+    #[test]
+    fn test_retarget() {
+        // MAINNET BLOCKS 30240 TO 32255, THE FIRST INTERVAL THAT RAISED THE DIFFICULTY
+        assert_eq!(retarget(0x1d00ffff, 1261130161, 1262152739, &pow_limit()), 0x1d00d86a);
+
+        // BLOCKS ON TIME KEEP THE TARGET, AND NOTHING GETS EASIER THAN THE LIMIT
+        assert_eq!(retarget(0x1c05a3f4, 1_700_000_000, 1_700_000_000 + TARGET_TIMESPAN, &pow_limit()), 0x1c05a3f4);
+        assert_eq!(retarget(MAINNET_POW_LIMIT, 1_700_000_000, 1_700_000_000 + 2 * TARGET_TIMESPAN, &pow_limit()), MAINNET_POW_LIMIT);
+
+        // 4X EITHER WAY AT MOST, HOWEVER FAST OR SLOW THE BLOCKS CAME
+        assert_eq!(retarget(0x1c05a3f4, 1_700_000_000, 1_700_000_000 + 1, &pow_limit()), 0x1c0168fd);
+        assert_eq!(retarget(0x1c05a3f4, 1_700_000_000, 1_600_000_000, &pow_limit()), 0x1c0168fd);
+        assert_eq!(retarget(0x1c05a3f4, 1_700_000_000, 1_700_000_000 + 10 * TARGET_TIMESPAN, &pow_limit()), 0x1c168fd0);
+        assert_eq!(retarget(0x1c387f6f, 1263163443, 1269211443, &pow_limit()), 0x1d00e1fd);
+    }
+}
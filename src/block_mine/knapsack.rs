@@ -0,0 +1,300 @@
+// BLOCK FILLING ON TOP OF PACKAGE SELECTION
+//
+// PACKAGE SELECTION ALREADY SKIPS PACKAGES THAT DO NOT FIT AND KEEPS GOING WITH SMALLER ONES. THE
+// OPTIONAL LOCAL SEARCH THEN SWAPS CHEAP TRANSACTIONS AT THE EDGE OF THE BLOCK FOR UNSELECTED ONES
+// THAT PAY MORE IN THE SAME SPACE, UNTIL NO SWAP IMPROVES THE TOTAL FEES.
+use std::cmp::Ordering;
+use std::fmt;
+
+use super::package::{mempool_parents, select_packages, BlockLimits}; // Importing the ancestor package selection
+use super::serialise_tx::TxEntry; // Importing the mempool entry type
+
+// HOW MANY OF THE CHEAPEST REMOVABLE TRANSACTIONS A ROUND TRIES TO SWAP OUT
+const SWAP_CANDIDATES: usize = 32;
+
+// EVERY ROUND STRICTLY INCREASES THE FEES, THIS ONLY BOUNDS THE RUNNING TIME
+const MAX_ROUNDS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillMode {
+    Packages,  // Ancestor package selection only
+    Optimised, // Package selection followed by the local search
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FillReport {
+    pub fees: u64,
+    pub weight: usize,
+    pub sigops: usize,
+    pub upper_bound: u64,
+}
+
+impl FillReport {
+    // THE MOST FEES ANY OTHER SELECTION COULD STILL ADD
+    pub fn gap(&self) -> u64 {
+        self.upper_bound.saturating_sub(self.fees)
+    }
+}
+
+impl fmt::Display for FillReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let gap_percent = if self.upper_bound == 0 {
+            0.0
+        } else {
+            self.gap() as f64 * 100.0 / self.upper_bound as f64
+        };
+        write!(
+            f,
+            "BLOCK FEES: {} SATS, WEIGHT: {} WU, SIGOPS: {}, FEE-RATE UPPER BOUND: {} SATS, GAP: {} SATS ({:.4}%)",
+            self.fees,
+            self.weight,
+            self.sigops,
+            self.upper_bound,
+            self.gap(),
+            gap_percent
+        )
+    }
+}
+
+// Selects the block transactions in block order and reports how well they fill it
+pub fn assemble_block(
+    map: Vec<TxEntry>,
+    sigops: &[usize],
+    limits: &BlockLimits,
+    mode: FillMode,
+) -> (Vec<TxEntry>, FillReport) {
+    let mut order = select_packages(&map, sigops, limits);
+    if mode == FillMode::Optimised {
+        order = local_search(&map, sigops, limits, order);
+    }
+
+    let report = FillReport {
+        fees: order.iter().map(|index| map[*index].4).sum(),
+        weight: order.iter().map(|index| map[*index].3).sum(),
+        sigops: order.iter().map(|index| sigops[*index]).sum(),
+        upper_bound: fee_rate_upper_bound(&map, limits.weight),
+    };
+
+    let mut entries: Vec<Option<TxEntry>> = map.into_iter().map(Some).collect();
+    (order.into_iter().filter_map(|index| entries[index].take()).collect(), report)
+}
+
+// FILLS THE WEIGHT LIMIT BY INDIVIDUAL FEERATE AND TAKES A FRACTION OF THE LAST TRANSACTION. THIS
+// IGNORES DEPENDENCIES AND SIGOPS, SO NO VALID BLOCK CAN COLLECT MORE FEES
+pub fn fee_rate_upper_bound(map: &[TxEntry], weight_limit: usize) -> u64 {
+    let mut entries: Vec<(u64, usize)> = map.iter().map(|entry| (entry.4, entry.3)).collect();
+    entries.sort_by(|a, b| by_fee_rate(*b, *a));
+
+    let mut room = weight_limit;
+    let mut bound = 0;
+    for (fees, weight) in entries {
+        if weight <= room {
+            bound += fees;
+            room -= weight;
+        } else {
+            // ROUNDING UP KEEPS IT AN UPPER BOUND
+            bound += (fees as u128 * room as u128).div_ceil(weight as u128) as u64;
+            break;
+        }
+    }
+
+    bound
+}
+
+// COMPARES TWO (FEES, WEIGHT) PAIRS BY fees / weight WITHOUT DIVIDING
+fn by_fee_rate(a: (u64, usize), b: (u64, usize)) -> Ordering {
+    (a.0 as u128 * b.1 as u128).cmp(&(b.0 as u128 * a.1 as u128))
+}
+
+// Swaps one transaction without selected children for the best unselected ones fitting in its place
+fn local_search(map: &[TxEntry], sigops: &[usize], limits: &BlockLimits, mut order: Vec<usize>) -> Vec<usize> {
+    let parents = mempool_parents(map);
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); map.len()];
+    for (child, set) in parents.iter().enumerate() {
+        for parent in set {
+            children[*parent].push(child);
+        }
+    }
+
+    let mut selected = vec![false; map.len()];
+    for index in order.iter() {
+        selected[*index] = true;
+    }
+    let mut block_weight: usize = order.iter().map(|index| map[*index].3).sum();
+    let mut block_sigops: usize = order.iter().map(|index| sigops[*index]).sum();
+
+    let rate = |index: usize| (map[index].4, map[index].3);
+    let mut by_rate: Vec<usize> = (0..map.len()).collect();
+    by_rate.sort_by(|a, b| by_fee_rate(rate(*b), rate(*a)));
+
+    for _ in 0..MAX_ROUNDS {
+        let mut leaves: Vec<usize> = order
+            .iter()
+            .copied()
+            .filter(|index| children[*index].iter().all(|child| !selected[*child]))
+            .collect();
+        leaves.sort_by(|a, b| by_fee_rate(rate(*a), rate(*b)));
+        leaves.truncate(SWAP_CANDIDATES);
+
+        // `None` ONLY REFILLS THE FREE SPACE WITHOUT TAKING ANYTHING OUT
+        let mut improved = false;
+        for leaf in std::iter::once(None).chain(leaves.into_iter().map(Some)) {
+            if let Some(leaf) = leaf {
+                selected[leaf] = false;
+            }
+
+            let mut room_weight = limits.weight - block_weight + leaf.map_or(0, |leaf| map[leaf].3);
+            let mut room_sigops = (limits.sigops - block_sigops).saturating_add(leaf.map_or(0, |leaf| sigops[leaf]));
+
+            let mut added: Vec<usize> = Vec::new();
+            let mut gain = 0;
+            for candidate in by_rate.iter().copied() {
+                if selected[candidate]
+                    || Some(candidate) == leaf
+                    || map[candidate].3 > room_weight
+                    || sigops[candidate] > room_sigops
+                {
+                    continue;
+                }
+
+                // PARENTS MUST ALREADY BE IN THE BLOCK OR ADDED EARLIER IN THIS REFILL
+                if !parents[candidate].iter().all(|parent| selected[*parent]) {
+                    continue;
+                }
+
+                selected[candidate] = true;
+                added.push(candidate);
+                gain += map[candidate].4;
+                room_weight -= map[candidate].3;
+                room_sigops -= sigops[candidate];
+            }
+
+            if gain > leaf.map_or(0, |leaf| map[leaf].4) {
+                order.retain(|index| Some(*index) != leaf);
+                order.extend(added);
+                block_weight = order.iter().map(|index| map[*index].3).sum();
+                block_sigops = order.iter().map(|index| sigops[*index]).sum();
+                improved = true;
+                break;
+            }
+
+            // NO GAIN, PUT EVERYTHING BACK
+            for index in added {
+                selected[index] = false;
+            }
+            if let Some(leaf) = leaf {
+                selected[leaf] = true;
+            }
+        }
+
+        if !improved {
+            break;
+        }
+    }
+
+    order
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+    use std::fs;
+
+    use super::*;
+    use crate::block_mine::serialise_tx::tx_entry;
+    use crate::error::Result;
+    use crate::fixtures::load;
+    use crate::transaction::Transaction;
+    use crate::validation_checks::sigops::transaction_sigop_cost;
+
+    fn entry(txid: &str, spends: &str, weight: usize, fees: u64) -> Result<TxEntry> {
+        let mut transaction = load("fcc4d2ad88b7a040dc98ae29946b794258ae7c8ba1a4300a6fc761d0c9cb6a1f")?;
+        transaction.vin.truncate(1);
+        transaction.vin[0].txid = spends.to_string();
+        Ok((txid.to_string(), transaction, txid.to_string(), weight, fees))
+    }
+
+    #[test]
+    fn test_local_search_beats_greedy() -> Result<()> {
+        let map = vec![
+            entry("a", "confirmed", 600, 6000)?,
+            entry("b", "confirmed", 500, 4500)?,
+            entry("c", "confirmed", 500, 4500)?,
+        ];
+        let sigops = vec![0; map.len()];
+        let limits = BlockLimits { weight: 1000, sigops: 80000 };
+
+        // A HAS THE BEST FEERATE BUT LEAVES NO ROOM FOR B OR C
+        let (block, report) = assemble_block(map.clone(), &sigops, &limits, FillMode::Packages);
+        assert_eq!(block.len(), 1);
+        assert_eq!(report.fees, 6000);
+
+        let (block, report) = assemble_block(map, &sigops, &limits, FillMode::Optimised);
+        assert_eq!(block.iter().map(|entry| entry.0.as_str()).collect::<Vec<_>>(), vec!["b", "c"]);
+        assert_eq!(report.fees, 9000);
+        assert_eq!(report.weight, 1000);
+
+        // ALL OF A PLUS 400 / 500 OF B
+        assert_eq!(report.upper_bound, 9600);
+        assert_eq!(report.gap(), 600);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_search_keeps_children_with_parents() -> Result<()> {
+        let map = vec![
+            entry("parent", "confirmed", 300, 300)?,
+            entry("child", "parent", 300, 6000)?,
+            entry("other", "confirmed", 500, 2500)?,
+        ];
+        let sigops = vec![0; map.len()];
+        let limits = BlockLimits { weight: 1000, sigops: 80000 };
+
+        // SWAPPING THE CHEAP PARENT FOR OTHER WOULD PAY MORE, BUT IT CANNOT LEAVE WHILE THE CHILD IS IN
+        let (block, report) = assemble_block(map, &sigops, &limits, FillMode::Optimised);
+        assert_eq!(block.iter().map(|entry| entry.0.as_str()).collect::<Vec<_>>(), vec!["parent", "child"]);
+        assert_eq!(report.fees, 6300);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_optimised_mempool_block() -> Result<()> {
+        let mut map: Vec<TxEntry> = Vec::new();
+        for entry in fs::read_dir("./mempool")? {
+            let transaction: Transaction = serde_json::from_str(&fs::read_to_string(entry?.path())?)?;
+            map.push(tx_entry(transaction)?);
+        }
+        let all_txids: BTreeSet<String> = map.iter().map(|entry| entry.0.clone()).collect();
+        let sigops: Vec<usize> = map.iter().map(|entry| transaction_sigop_cost(&entry.1)).collect();
+        let limits = BlockLimits { weight: 3993000, sigops: 79600 };
+
+        let (_, greedy) = assemble_block(map.clone(), &sigops, &limits, FillMode::Packages);
+        let (block, optimised) = assemble_block(map.clone(), &sigops, &limits, FillMode::Optimised);
+        println!("{}", greedy);
+        println!("{}", optimised);
+
+        assert!(optimised.fees >= greedy.fees);
+        assert!(optimised.fees <= optimised.upper_bound);
+        assert!(optimised.weight <= limits.weight);
+        assert!(optimised.sigops <= limits.sigops);
+
+        // A SIGOP BUDGET THIS TIGHT LEAVES MOST OF THE WEIGHT UNUSED
+        let tight = BlockLimits { weight: 3993000, sigops: 2000 };
+        let (_, report) = assemble_block(map, &sigops, &tight, FillMode::Packages);
+        assert!(report.sigops <= 2000 && report.weight < optimised.weight);
+
+        // STILL NO CHILD AHEAD OF ITS PARENT
+        let mut placed: BTreeSet<&str> = BTreeSet::new();
+        for (txid, transaction, _, _, _) in block.iter() {
+            for input in transaction.vin.iter() {
+                assert!(!all_txids.contains(&input.txid) || placed.contains(input.txid.as_str()));
+            }
+            placed.insert(txid);
+        }
+
+        Ok(())
+    }
+}
@@ -1,3 +1,12 @@
 pub mod block;
+pub mod chain;
+pub mod coinbase;
+pub mod deserialise_tx;
+pub mod difficulty;
+pub mod knapsack;
 pub mod merkle_root;
+pub mod package;
+pub mod pow;
 pub mod serialise_tx;
+pub mod subsidy;
+pub mod verify_block;
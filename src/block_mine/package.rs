@@ -0,0 +1,270 @@
+// ANCESTOR PACKAGE SELECTION, THE SAME IDEA AS BITCOIN CORE'S `CreateNewBlock`
+//
+// A TRANSACTION IS SCORED BY THE FEERATE OF ITSELF PLUS ALL ITS NOT YET SELECTED IN-MEMPOOL
+// ANCESTORS. A HIGH FEE CHILD THEREFORE PULLS A LOW FEE PARENT INTO THE BLOCK (CPFP), AND A
+// PACKAGE IS ALWAYS ADDED AS A WHOLE WITH THE PARENTS FIRST.
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, BinaryHeap, HashMap};
+
+use super::serialise_tx::TxEntry; // Importing the mempool entry type
+
+// FEES AND WEIGHT OF A TRANSACTION TOGETHER WITH ITS UNSELECTED ANCESTORS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PackageScore {
+    fees: u64,
+    weight: usize,
+    index: usize,
+}
+
+impl Ord for PackageScore {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // COMPARE fees / weight WITHOUT DIVIDING, TIES GO TO THE EARLIER ENTRY
+        let lhs = self.fees as u128 * other.weight as u128;
+        let rhs = other.fees as u128 * self.weight as u128;
+        lhs.cmp(&rhs).then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+impl PartialOrd for PackageScore {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// THE CAPS A BLOCK TEMPLATE HAS TO STAY UNDER
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockLimits {
+    pub weight: usize,
+    pub sigops: usize,
+}
+
+// PARENTS ARE THE MEMPOOL TRANSACTIONS WHOSE OUTPUTS ARE SPENT, EVERYTHING ELSE IS CONFIRMED
+pub fn mempool_parents(map: &[TxEntry]) -> Vec<BTreeSet<usize>> {
+    let positions: HashMap<&str, usize> =
+        map.iter().enumerate().map(|(index, entry)| (entry.0.as_str(), index)).collect(); // Txid to index
+
+    map.iter()
+        .map(|(_, transaction, _, _, _)| {
+            transaction.vin.iter().filter_map(|input| positions.get(input.txid.as_str()).copied()).collect()
+        })
+        .collect()
+}
+
+// Picks transactions by ancestor feerate until nothing else fits, returns their indices in block order
+pub fn select_packages(map: &[TxEntry], sigops: &[usize], limits: &BlockLimits) -> Vec<usize> {
+    let parents = mempool_parents(map);
+    let ancestors = ancestor_sets(&parents);
+
+    let mut descendants: Vec<Vec<usize>> = vec![Vec::new(); map.len()];
+    for (index, set) in ancestors.iter().enumerate() {
+        for ancestor in set {
+            descendants[*ancestor].push(index);
+        }
+    }
+
+    // RUNNING TOTALS OVER EACH TRANSACTION AND ITS UNSELECTED ANCESTORS
+    let mut package_fees: Vec<u64> = Vec::new();
+    let mut package_weight: Vec<usize> = Vec::new();
+    let mut package_sigops: Vec<usize> = Vec::new();
+    for set in ancestors.iter() {
+        package_fees.push(set.iter().map(|ancestor| map[*ancestor].4).sum());
+        package_weight.push(set.iter().map(|ancestor| map[*ancestor].3).sum());
+        package_sigops.push(set.iter().map(|ancestor| sigops[*ancestor]).sum());
+    }
+
+    let mut heap: BinaryHeap<PackageScore> = (0..map.len())
+        .map(|index| PackageScore { fees: package_fees[index], weight: package_weight[index], index })
+        .collect();
+
+    let mut selected = vec![false; map.len()];
+    let mut order: Vec<usize> = Vec::new();
+    let mut block_weight = 0;
+    let mut block_sigops = 0;
+
+    while let Some(score) = heap.pop() {
+        let index = score.index;
+
+        // STALE ENTRIES ARE LEFT IN THE HEAP WHEN AN ANCESTOR GETS SELECTED
+        if selected[index] || score.fees != package_fees[index] || score.weight != package_weight[index] {
+            continue;
+        }
+
+        // A PACKAGE THAT DOES NOT FIT IS SKIPPED, SMALLER ONES FURTHER DOWN MAY STILL FIT
+        if block_weight + package_weight[index] > limits.weight || block_sigops + package_sigops[index] > limits.sigops {
+            continue;
+        }
+
+        // ANCESTORS HAVE STRICTLY SMALLER ANCESTOR SETS, SO THIS ORDER PUTS PARENTS FIRST
+        let mut package: Vec<usize> =
+            ancestors[index].iter().copied().filter(|ancestor| !selected[*ancestor]).collect();
+        package.sort_by_key(|member| (ancestors[*member].len(), *member));
+
+        for member in package {
+            selected[member] = true;
+            block_weight += map[member].3;
+            block_sigops += sigops[member];
+            order.push(member);
+
+            // THE MEMBER NO LONGER COUNTS TOWARDS THE PACKAGES OF ITS DESCENDANTS
+            for descendant in descendants[member].iter().copied() {
+                if descendant == member || selected[descendant] {
+                    continue;
+                }
+                package_fees[descendant] -= map[member].4;
+                package_weight[descendant] -= map[member].3;
+                package_sigops[descendant] -= sigops[member];
+                heap.push(PackageScore {
+                    fees: package_fees[descendant],
+                    weight: package_weight[descendant],
+                    index: descendant,
+                });
+            }
+        }
+    }
+
+    order
+}
+
+// EVERY TRANSACTION'S ANCESTOR SET, ITSELF INCLUDED
+fn ancestor_sets(parents: &[BTreeSet<usize>]) -> Vec<BTreeSet<usize>> {
+    let mut ancestors: Vec<Option<BTreeSet<usize>>> = vec![None; parents.len()];
+
+    for start in 0..parents.len() {
+        // DEPTH FIRST, A TRANSACTION IS RESOLVED ONCE ALL ITS PARENTS ARE
+        let mut stack = vec![start];
+        while let Some(&index) = stack.last() {
+            if ancestors[index].is_some() {
+                stack.pop();
+                continue;
+            }
+
+            let pending: Vec<usize> =
+                parents[index].iter().copied().filter(|parent| ancestors[*parent].is_none()).collect();
+            if !pending.is_empty() {
+                stack.extend(pending);
+                continue;
+            }
+
+            let mut set = BTreeSet::from([index]);
+            for parent in parents[index].iter() {
+                set.extend(ancestors[*parent].iter().flatten());
+            }
+            ancestors[index] = Some(set);
+            stack.pop();
+        }
+    }
+
+    ancestors.into_iter().map(|set| set.unwrap_or_default()).collect()
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+    use crate::block_mine::serialise_tx::tx_entry;
+    use crate::error::Result;
+    use crate::fixtures::load;
+    use crate::transaction::Transaction;
+    use crate::validation_checks::sigops::transaction_sigop_cost;
+
+    // A FAKE ENTRY THAT SPENDS THE GIVEN MEMPOOL TXIDS, ONLY THE FIELDS SELECTION LOOKS AT MATTER
+    fn entry(txid: &str, spends: &[&str], weight: usize, fees: u64) -> Result<TxEntry> {
+        let mut transaction = load("fcc4d2ad88b7a040dc98ae29946b794258ae7c8ba1a4300a6fc761d0c9cb6a1f")?;
+        let template = transaction.vin[0].clone();
+        transaction.vin = spends
+            .iter()
+            .map(|parent| {
+                let mut input = template.clone();
+                input.txid = parent.to_string();
+                input
+            })
+            .collect();
+        Ok((txid.to_string(), transaction, txid.to_string(), weight, fees))
+    }
+
+    fn select(map: &[TxEntry], weight: usize) -> Vec<&str> {
+        let block = select_packages(map, &vec![0; map.len()], &BlockLimits { weight, sigops: usize::MAX });
+        block.iter().map(|index| map[*index].0.as_str()).collect()
+    }
+
+    #[test]
+    fn test_child_pays_for_parent() -> Result<()> {
+        let map = vec![
+            entry("child", &["parent"], 1000, 10000)?,
+            entry("other", &["confirmed"], 1000, 5000)?,
+            entry("parent", &["confirmed"], 1000, 1000)?,
+        ];
+
+        // THE PARENT ALONE PAYS 1 SAT/WU BUT THE PACKAGE PAYS 5.5, AHEAD OF THE OTHER TX
+        assert_eq!(select(&map, 10000), vec!["parent", "child", "other"]);
+
+        // ROOM FOR ONE PACKAGE: THE SINGLE TX WOULD FIT, BUT THE PACKAGE SCORES HIGHER
+        assert_eq!(select(&map, 2000), vec!["parent", "child"]);
+
+        // THE CHILD NEVER GOES IN WITHOUT ITS PARENT
+        assert_eq!(select(&map, 1500), vec!["other"]);
+
+        // THE PACKAGE GOES OVER THE SIGOP LIMIT, THE PARENT ON ITS OWN DOES NOT
+        let block = select_packages(&map, &[80, 0, 1], &BlockLimits { weight: 10000, sigops: 80 });
+        assert_eq!(block, vec![1, 2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diamond_and_selected_ancestors() -> Result<()> {
+        let map = vec![
+            entry("d", &["b", "c"], 1000, 40000)?,
+            entry("c", &["a"], 1000, 100)?,
+            entry("b", &["a"], 1000, 100)?,
+            entry("a", &["confirmed"], 1000, 30000)?,
+            entry("e", &["b"], 1000, 2000)?,
+        ];
+
+        let order = select(&map, 10000);
+
+        // A IS PICKED ON ITS OWN, THEN D PULLS IN B AND C, E COMES LAST
+        assert_eq!(order[0], "a");
+        assert_eq!(order[3], "d");
+        assert_eq!(order[4], "e");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mempool_block_is_topologically_ordered() -> Result<()> {
+        let mut map: Vec<TxEntry> = Vec::new();
+        for entry in fs::read_dir("./mempool")? {
+            let transaction: Transaction = serde_json::from_str(&fs::read_to_string(entry?.path())?)?;
+            map.push(tx_entry(transaction)?);
+        }
+        let all_txids: BTreeSet<String> = map.iter().map(|entry| entry.0.clone()).collect();
+
+        let weight_limit = 3993000;
+        let sigops: Vec<usize> = map.iter().map(|entry| transaction_sigop_cost(&entry.1)).collect();
+        let sigop_limit = 79600;
+        let block = select_packages(&map, &sigops, &BlockLimits { weight: weight_limit, sigops: sigop_limit });
+        assert!(block.iter().map(|index| map[*index].3).sum::<usize>() <= weight_limit);
+        assert!(block.iter().map(|index| sigops[*index]).sum::<usize>() <= sigop_limit);
+
+        // EVERY IN-MEMPOOL PARENT IS ALREADY IN THE BLOCK WHEN ITS CHILD IS ADDED
+        let mut placed: BTreeSet<&str> = BTreeSet::new();
+        let mut children = 0;
+        for (txid, transaction, _, _, _) in block.iter().map(|index| &map[*index]) {
+            for input in transaction.vin.iter() {
+                if all_txids.contains(&input.txid) {
+                    assert!(placed.contains(input.txid.as_str()));
+                    children += 1;
+                }
+            }
+            placed.insert(txid);
+        }
+
+        println!("block txs: {}, in-block parent spends: {}", block.len(), children);
+        assert!(children > 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,301 @@
+// PROOF OF WORK OVER A FIXED 80 BYTE HEADER
+//
+// ONLY THE LAST 16 BYTES (END OF MERKLE ROOT, TIME, BITS, NONCE) CHANGE BETWEEN NONCES, SO THE
+// SHA256 STATE AFTER THE FIRST 64 BYTE BLOCK IS COMPUTED ONCE AND CLONED FOR EVERY ATTEMPT.
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::atomic::{self, AtomicBool};
+use std::thread;
+
+use sha2::{Digest, Sha256}; // Importing functions for SHA-256 hashing
+
+use crate::error::Result; // Importing Result type from the crate
+
+pub const HEADER_SIZE: usize = 80;
+
+// HOW FAR THE TIMESTAMP IS ROLLED FORWARD BEFORE THE EXTRANONCE CHANGES, THE 2 HOUR FUTURE LIMIT
+pub const MAX_TIME_ROLL: u32 = 2 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub version: u32,
+    pub prev_block_hash: [u8; 32], // Internal byte order
+    pub merkle_root: [u8; 32],     // Internal byte order
+    pub time: u32,
+    pub bits: u32,
+    pub nonce: u32,
+}
+
+impl BlockHeader {
+    pub fn serialise(&self) -> [u8; HEADER_SIZE] {
+        let mut header = [0u8; HEADER_SIZE];
+        header[0..4].copy_from_slice(&self.version.to_le_bytes());
+        header[4..36].copy_from_slice(&self.prev_block_hash);
+        header[36..68].copy_from_slice(&self.merkle_root);
+        header[68..72].copy_from_slice(&self.time.to_le_bytes());
+        header[72..76].copy_from_slice(&self.bits.to_le_bytes());
+        header[76..80].copy_from_slice(&self.nonce.to_le_bytes());
+        header
+    }
+
+    pub fn deserialise(bytes: &[u8; HEADER_SIZE]) -> BlockHeader {
+        let word = |start: usize| u32::from_le_bytes(bytes[start..start + 4].try_into().unwrap());
+        BlockHeader {
+            version: word(0),
+            prev_block_hash: bytes[4..36].try_into().unwrap(),
+            merkle_root: bytes[36..68].try_into().unwrap(),
+            time: word(68),
+            bits: word(72),
+            nonce: word(76),
+        }
+    }
+
+    // DOUBLE SHA256 IN INTERNAL BYTE ORDER, REVERSE IT FOR THE DISPLAYED BLOCK HASH
+    pub fn hash(&self) -> [u8; 32] {
+        Sha256::digest(Sha256::digest(self.serialise())).into()
+    }
+}
+
+// THE HASH IS A LITTLE ENDIAN NUMBER AND THE TARGET A BIG ENDIAN ONE, SO COMPARE FROM OPPOSITE ENDS
+pub fn hash_meets_target(hash: &[u8; 32], target: &[u8; 32]) -> bool {
+    hash.iter().rev().cmp(target.iter()) != Ordering::Greater
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompactError {
+    Negative,
+    Overflow,
+    Zero,
+}
+
+impl fmt::Display for CompactError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompactError::Negative => write!(f, "POW: COMPACT TARGET HAS THE SIGN BIT SET"),
+            CompactError::Overflow => write!(f, "POW: COMPACT TARGET DOES NOT FIT IN 256 BITS"),
+            CompactError::Zero => write!(f, "POW: COMPACT TARGET IS ZERO"),
+        }
+    }
+}
+
+impl std::error::Error for CompactError {}
+
+// EXPANDS THE COMPACT `bits` FIELD (BASE 256 EXPONENT AND 3 BYTE MANTISSA) INTO A BIG ENDIAN TARGET,
+// THE REVERSE OF `block::target_to_compact`. SAME AS BITCOIN CORE'S `SetCompact`, A SET SIGN BIT ON A
+// NON-ZERO MANTISSA IS NEGATIVE AND BYTES THAT WOULD LAND ABOVE 256 BITS ARE AN OVERFLOW
+pub fn compact_to_target(bits: u32) -> std::result::Result<[u8; 32], CompactError> {
+    let exponent = (bits >> 24) as isize;
+    let mantissa = bits & 0x007f_ffff;
+
+    // WITH AN EXPONENT BELOW 3 THE LOW MANTISSA BYTES ARE SHIFTED OUT BEFORE ANYTHING IS CHECKED
+    let word = if exponent <= 3 { mantissa >> (8 * (3 - exponent)) } else { mantissa };
+    if bits & 0x0080_0000 != 0 && word != 0 {
+        return Err(CompactError::Negative);
+    }
+    if word != 0 && (exponent > 34 || (word > 0xff && exponent > 33) || (word > 0xffff && exponent > 32)) {
+        return Err(CompactError::Overflow);
+    }
+
+    // THE MANTISSA'S FIRST BYTE LANDS AT INDEX 32 - exponent, BYTES SHIFTED OUT OF THE BOTTOM ARE DROPPED
+    let mut target = [0u8; 32];
+    for (offset, byte) in mantissa.to_be_bytes()[1..].iter().enumerate() {
+        let index = 32 - exponent + offset as isize;
+        if (0..32).contains(&index) {
+            target[index as usize] = *byte;
+        }
+    }
+
+    if target == [0u8; 32] {
+        return Err(CompactError::Zero);
+    }
+    Ok(target)
+}
+
+// THE TARGET A HEADER'S `bits` ASKS FOR, NONE WHEN NO HASH COULD MEET IT, AS IN BITCOIN CORE'S `CheckProofOfWork`
+pub fn bits_to_target(bits: u32) -> Option<[u8; 32]> {
+    compact_to_target(bits).ok()
+}
+
+// Mines the header on every available core, rolling the timestamp and then the extranonce when the nonces run out
+pub fn mine<F>(header: BlockHeader, target: &[u8; 32], roll_extranonce: F) -> Result<BlockHeader>
+where
+    F: FnMut(u64) -> Result<[u8; 32]>,
+{
+    let threads = thread::available_parallelism().map(|count| count.get()).unwrap_or(1);
+    mine_with_limits(header, target, threads, u32::MAX, MAX_TIME_ROLL, roll_extranonce)
+}
+
+fn mine_with_limits<F>(
+    mut header: BlockHeader,
+    target: &[u8; 32],
+    threads: usize,
+    last_nonce: u32,
+    max_time_roll: u32,
+    mut roll_extranonce: F,
+) -> Result<BlockHeader>
+where
+    F: FnMut(u64) -> Result<[u8; 32]>,
+{
+    let start_time = header.time;
+    let mut extranonce = 0;
+
+    loop {
+        if let Some(nonce) = search_nonces(&header, target, threads, last_nonce) {
+            header.nonce = nonce;
+            return Ok(header);
+        }
+
+        // EVERY NONCE FAILED, SOMETHING ELSE IN THE HEADER HAS TO CHANGE
+        if header.time - start_time < max_time_roll {
+            header.time += 1;
+        } else {
+            extranonce += 1;
+            header.merkle_root = roll_extranonce(extranonce)?;
+            header.time = start_time;
+        }
+    }
+}
+
+// Splits nonces 0..=last_nonce over the threads, thread `i` tries i, i + threads, i + 2 * threads, ...
+fn search_nonces(header: &BlockHeader, target: &[u8; 32], threads: usize, last_nonce: u32) -> Option<u32> {
+    let bytes = header.serialise();
+    let midstate = Sha256::new_with_prefix(&bytes[..64]); // Hash state after the first 64 bytes
+    let tail: [u8; 12] = bytes[64..76].try_into().unwrap(); // Merkle root end, time and bits
+    let found = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|worker| {
+                let midstate = &midstate;
+                let found = &found;
+                scope.spawn(move || {
+                    let mut nonce = worker as u64;
+                    while nonce <= last_nonce as u64 {
+                        // ANOTHER THREAD ALREADY HAS A VALID HEADER
+                        if found.load(atomic::Ordering::Relaxed) {
+                            return None;
+                        }
+
+                        let mut hasher = midstate.clone();
+                        hasher.update(tail);
+                        hasher.update((nonce as u32).to_le_bytes());
+                        let hash: [u8; 32] = Sha256::digest(hasher.finalize()).into();
+
+                        if hash_meets_target(&hash, target) {
+                            found.store(true, atomic::Ordering::Relaxed);
+                            return Some(nonce as u32);
+                        }
+
+                        nonce += threads as u64;
+                    }
+                    None
+                })
+            })
+            .collect();
+
+        workers.into_iter().filter_map(|worker| worker.join().ok().flatten()).min()
+    })
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn reversed(display_hex: &str) -> [u8; 32] {
+        let mut bytes: [u8; 32] = hex::decode(display_hex).unwrap().try_into().unwrap();
+        bytes.reverse();
+        bytes
+    }
+
+    fn target(hex_target: &str) -> [u8; 32] {
+        hex::decode(hex_target).unwrap().try_into().unwrap()
+    }
+
+    fn genesis() -> BlockHeader {
+        BlockHeader {
+            version: 1,
+            prev_block_hash: [0; 32],
+            merkle_root: reversed("4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"),
+            time: 1231006505,
+            bits: 0x1d00ffff,
+            nonce: 2083236893,
+        }
+    }
+
+    #[test]
+    fn test_genesis_header() {
+        let header = genesis();
+        assert_eq!(
+            hex::encode(header.serialise()),
+            "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c"
+        );
+
+        let hash = header.hash();
+        assert_eq!(hash, reversed("000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"));
+
+        // 0x1d00ffff IS THE DIFFICULTY 1 TARGET
+        assert!(hash_meets_target(&hash, &target("00000000ffff0000000000000000000000000000000000000000000000000000")));
+        assert!(!hash_meets_target(&hash, &target("000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26e")));
+        assert!(hash_meets_target(&hash, &target("000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f")));
+    }
+
+    #[test]
+    fn test_header_round_trip_and_bits() {
+        let header = genesis();
+        assert_eq!(BlockHeader::deserialise(&header.serialise()), header);
+
+        assert_eq!(bits_to_target(0x1d00ffff), Some(target("00000000ffff0000000000000000000000000000000000000000000000000000")));
+        assert_eq!(bits_to_target(0x1f00ffff), Some(target("0000ffff00000000000000000000000000000000000000000000000000000000")));
+        assert_eq!(bits_to_target(0x207fffff), Some(target("7fffff0000000000000000000000000000000000000000000000000000000000")));
+        assert_eq!(bits_to_target(0x01123456), Some(target("0000000000000000000000000000000000000000000000000000000000000012")));
+        assert_eq!(bits_to_target(0x220000ff), Some(target("ff00000000000000000000000000000000000000000000000000000000000000")));
+
+        assert_eq!(bits_to_target(0x04923456), None); // Negative
+        assert_eq!(bits_to_target(0x01003456), None); // Zero after shifting
+        assert_eq!(bits_to_target(0x2200ffff), None); // Overflow
+
+        assert_eq!(compact_to_target(0x04923456), Err(CompactError::Negative));
+        assert_eq!(compact_to_target(0x01803456), Err(CompactError::Zero)); // Sign bit on a mantissa shifted out
+        assert_eq!(compact_to_target(0x00000000), Err(CompactError::Zero));
+        assert_eq!(compact_to_target(0x23000001), Err(CompactError::Overflow));
+        assert_eq!(compact_to_target(0xff123456), Err(CompactError::Overflow));
+    }
+
+    #[test]
+    fn test_midstate_search_matches_full_hash() {
+        let easy = target("00ffffff00000000000000000000000000000000000000000000000000000000");
+
+        for threads in [1, 4] {
+            let nonce = search_nonces(&genesis(), &easy, threads, u32::MAX).unwrap();
+            let header = BlockHeader { nonce, ..genesis() };
+            assert!(hash_meets_target(&header.hash(), &easy));
+        }
+
+        // A SINGLE THREAD RETURNS THE SMALLEST VALID NONCE
+        let nonce = search_nonces(&genesis(), &easy, 1, u32::MAX).unwrap();
+        assert!((0..nonce).all(|smaller| !hash_meets_target(&BlockHeader { nonce: smaller, ..genesis() }.hash(), &easy)));
+    }
+
+    #[test]
+    fn test_time_and_extranonce_rolling() -> Result<()> {
+        let hard = target("0000ffff00000000000000000000000000000000000000000000000000000000");
+
+        // 16 NONCES AND 2 TIMESTAMPS PER MERKLE ROOT, SO THE EXTRANONCE HAS TO MOVE
+        let mut rolls: Vec<u64> = Vec::new();
+        let header = mine_with_limits(genesis(), &hard, 2, 15, 1, |extranonce| {
+            rolls.push(extranonce);
+            let mut root = genesis().merkle_root;
+            root[..8].copy_from_slice(&extranonce.to_le_bytes());
+            Ok(root)
+        })?;
+
+        assert!(hash_meets_target(&header.hash(), &hard));
+        assert!(header.nonce <= 15);
+        assert!(header.time - genesis().time <= 1);
+        assert!(!rolls.is_empty());
+        assert_eq!(rolls, (1..=rolls.len() as u64).collect::<Vec<u64>>());
+
+        Ok(())
+    }
+}
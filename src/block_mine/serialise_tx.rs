@@ -1,18 +1,23 @@
 use std::fs; // Importing the file system module
+use std::path::Path; // Importing Path for the valid mempool directory
 use sha2::{Digest, Sha256}; // Importing functions for SHA-256 hashing
 use walkdir::WalkDir; // Importing WalkDir for directory traversal
 
-use crate::{error::Result, transaction::Transaction}; // Importing Result type and Transaction struct from the crate
+use crate::{compact_size, error::Result, transaction::Transaction}; // Importing Result type and Transaction struct from the crate
+
+// (TXID, TRANSACTION, WTXID, WEIGHT, FEES)
+pub type TxEntry = (String, Transaction, String, usize, u64);
+
+// (RAW TX, RAW WITNESS TX, WEIGHT, FEES)
+type SerialisedTx = (Vec<u8>, Vec<u8>, usize, u64);
 
 pub fn double_sha256(data: &[u8]) -> Vec<u8> {
-    Sha256::digest(&Sha256::digest(data)).to_vec() // Perform double SHA-256 hashing on input data
+    Sha256::digest(Sha256::digest(data)).to_vec() // Perform double SHA-256 hashing on input data
 }
 
 // Iterate through the valid-mempool to create a vector of features to be used for each transaction in block mining
-pub fn create_txid_tx_map() -> Result<Vec<(String, Transaction, String, usize, u64)>> {
-
-    let v_mempool_dir = "./valid-mempool"; // Define the directory for valid mempool
-    let mut map: Vec<(String, Transaction, String, usize, u64)> = Vec::new(); // Initialize a vector to store transaction features
+pub fn create_txid_tx_map(v_mempool_dir: &Path) -> Result<Vec<TxEntry>> {
+    let mut map: Vec<TxEntry> = Vec::new(); // Initialize a vector to store transaction features
 
     for entry in WalkDir::new(v_mempool_dir) // Iterate over entries in the directory
         .into_iter()
@@ -23,28 +28,8 @@ pub fn create_txid_tx_map() -> Result<Vec<(String, Transaction, String, usize, u
             match fs::read_to_string(path) { // Read contents of the file
                 Ok(contents) => match serde_json::from_str::<Transaction>(&contents) { // Deserialize JSON contents into Transaction struct
                     Ok(transaction) => {
-                        let (result, serialised_tx, serialised_wtx, tx_weight, fees) =
-                            serialise_tx(&transaction)?; // Serialize transaction
-
-                        if result == true { // If serialization is successful
-                            let mut txid = double_sha256(&serialised_tx); // Calculate transaction ID
-                            let mut wtxid = double_sha256(&serialised_wtx); // Calculate witness transaction ID
-
-                            txid.reverse(); // Reverse transaction ID bytes
-                            wtxid.reverse(); // Reverse witness transaction ID bytes
-
-                            let txid = hex::encode(txid); // Encode transaction ID as hexadecimal
-                            let wtxid = hex::encode(wtxid); // Encode witness transaction ID as hexadecimal
-
-                            // Find the correct position to insert the transaction based on its fees
-                            let position = map
-                                .iter()
-                                .position(|(_, _, _, net_weight, gas_fees)| {
-                                    fees / tx_weight as u64 > *gas_fees / (*net_weight as u64)
-                                })
-                                .unwrap_or(map.len());
-                            map.insert(position, (txid, transaction, wtxid, tx_weight, fees)); // Insert transaction features into the map
-                        }
+                        // Ordering is left to package selection, which needs parents and children together
+                        map.push(tx_entry(transaction)?); // Serialize transaction and store its features
                     }
                     Err(_e) => {}
                 },
@@ -56,26 +41,40 @@ pub fn create_txid_tx_map() -> Result<Vec<(String, Transaction, String, usize, u
     Ok(map) // Return the transaction ID - transaction map
 }
 
+// Serialise a transaction once and keep everything block building needs from it
+pub fn tx_entry(transaction: Transaction) -> Result<TxEntry> {
+    let (serialised_tx, serialised_wtx, tx_weight, fees) = serialise_tx(&transaction)?; // Serialize transaction
+
+    let mut txid = double_sha256(&serialised_tx); // Calculate transaction ID
+    let mut wtxid = double_sha256(&serialised_wtx); // Calculate witness transaction ID
+
+    txid.reverse(); // Reverse transaction ID bytes
+    wtxid.reverse(); // Reverse witness transaction ID bytes
+
+    Ok((hex::encode(txid), transaction, hex::encode(wtxid), tx_weight, fees)) // Encode both IDs as hexadecimal
+}
+
 // Aims to create the raw transaction for transaction ID and raw witness transaction for witness transaction ID
-fn serialise_tx(tx: &Transaction) -> Result<(bool, Vec<u8>, Vec<u8>, usize, u64)> {
-    let tx_type;
-    if tx.vin[0].witness == None {
-        tx_type = "LEGACY"; // Set transaction type to legacy if witness field is None
+pub fn serialise_tx(tx: &Transaction) -> Result<SerialisedTx> {
+    let tx_type = if tx.vin.iter().all(|input| input.witness.is_none()) {
+        "LEGACY" // Set transaction type to legacy if no input carries a witness
     } else {
-        tx_type = "SEGWIT"; // Set transaction type to SegWit otherwise
-    }
+        "SEGWIT" // Set transaction type to SegWit otherwise
+    };
 
-    let mut fees = 0; // Initialize fees variable
+    let mut fees: u64 = 0; // Initialize fees variable
     let mut non_witness_bytes = 0; // Initialize non-witness bytes variable
     let mut witness_bytes = 0; // Initialize witness bytes variable
 
-    // Calculate gas fees
-    for input in tx.vin.iter() {
-        fees += input.prevout.value; // Add input values to fees
-    }
+    // Calculate gas fees, a coinbase creates its outputs and pays none
+    if !tx.vin.iter().any(|input| input.is_coinbase) {
+        for input in tx.vin.iter() {
+            fees = fees.saturating_add(input.prevout.value); // Add input values to fees, validation rejects totals this large
+        }
 
-    for output in tx.vout.iter() {
-        fees -= output.value; // Subtract output values from fees
+        for output in tx.vout.iter() {
+            fees = fees.saturating_sub(output.value); // Subtract output values from fees, overspending pays nothing
+        }
     }
 
     let mut raw_tx: Vec<u8> = Vec::new(); // Initialize raw transaction vector
@@ -87,52 +86,44 @@ fn serialise_tx(tx: &Transaction) -> Result<(bool, Vec<u8>, Vec<u8>, usize, u64)
         non_witness_bytes += 4; // Increment non-witness bytes count
 
         // INPUT COUNT
-        if tx.vin.len() >= 50 {
-            return Ok((false, Vec::new(), Vec::new(), 0, 0)); // Return if input count exceeds limit
-        }
-
-        raw_tx.push(tx.vin.len().try_into()?); // Append input count to raw transaction
-        non_witness_bytes += 1; // Increment non-witness bytes count
+        raw_tx.extend(compact_size::encode(tx.vin.len())); // Append input count to raw transaction
+        non_witness_bytes += compact_size::encoded_len(tx.vin.len()); // Increment non-witness bytes count
 
         // INPUTS
         for input in tx.vin.iter() {
             // TXID REVERSED
-            let mut txid = hex::decode(&input.txid.clone())?; // Decode and reverse transaction ID
+            let mut txid = hex::decode(&input.txid)?; // Decode and reverse transaction ID
             txid.reverse();
             // SCRIPT SIG
-            let script_sig = hex::decode(&input.scriptsig.clone().unwrap())?; // Decode script signature
+            let script_sig = hex::decode(input.scriptsig.as_deref().unwrap_or_default())?; // Decode script signature, a missing one is empty
             let script_sig_len = script_sig.len(); // Get script signature length
 
             // Append transaction ID, output index, script signature length, and script signature to raw transaction
             raw_tx.extend_from_slice(&txid);
             raw_tx.extend(input.vout.to_le_bytes());
-            raw_tx.push(script_sig.len().try_into()?);
+            raw_tx.extend(compact_size::encode(script_sig_len));
             raw_tx.extend_from_slice(&script_sig);
             raw_tx.extend(input.sequence.to_le_bytes());
 
-            non_witness_bytes += 32 + 4 + 1 + script_sig_len + 4; // Update non-witness bytes count
+            non_witness_bytes += 32 + 4 + compact_size::encoded_len(script_sig_len) + script_sig_len + 4; // Update non-witness bytes count
         }
 
         // OUTPUT COUNT
-        if tx.vout.len() >= 200 {
-            return Ok((false, Vec::new(), Vec::new(), 0, 0)); // Return if output count exceeds limit
-        }
-
-        raw_tx.push(tx.vout.len().try_into()?); // Append output count to raw transaction
-        non_witness_bytes += 1; // Increment non-witness bytes count
+        raw_tx.extend(compact_size::encode(tx.vout.len())); // Append output count to raw transaction
+        non_witness_bytes += compact_size::encoded_len(tx.vout.len()); // Increment non-witness bytes count
 
         // OUTPUTS
         for output in tx.vout.iter() {
             // SCRIPT PUB KEY
-            let scriptpubkey = hex::decode(&output.scriptpubkey.clone())?; // Decode script public key
+            let scriptpubkey = hex::decode(&output.scriptpubkey)?; // Decode script public key
             let scriptpubkey_len = scriptpubkey.len(); // Get script public key length
 
             // Append output value and script public key length to raw transaction
             raw_tx.extend(output.value.to_le_bytes());
-            raw_tx.push(scriptpubkey.len().try_into()?);
+            raw_tx.extend(compact_size::encode(scriptpubkey_len));
             raw_tx.extend_from_slice(&scriptpubkey);
 
-            non_witness_bytes += 8 + 1 + scriptpubkey_len; // Update non-witness bytes count
+            non_witness_bytes += 8 + compact_size::encoded_len(scriptpubkey_len) + scriptpubkey_len; // Update non-witness bytes count
         }
 
         // LOCKTIME
@@ -148,30 +139,27 @@ fn serialise_tx(tx: &Transaction) -> Result<(bool, Vec<u8>, Vec<u8>, usize, u64)
         non_witness_bytes += 4; // Increment non-witness bytes count
 
         // MARKER FLAG IN WTX ONLY
-        let marker = 00;
-        let flag = 01;
-        raw_wtx.push(marker.try_into()?);
-        raw_wtx.push(flag.try_into()?);
+        let marker = 0x00;
+        let flag = 0x01;
+        raw_wtx.push(marker);
+        raw_wtx.push(flag);
 
         witness_bytes += 1 + 1; // Increment witness bytes count
 
         // INPUT COUNT
-        if tx.vin.len() >= 200 {
-            return Ok((false, Vec::new(), Vec::new(), 0, 0)); // Return if input count exceeds limit
-        }
-        raw_tx.push(tx.vin.len().try_into()?); // Append input count to raw transaction
-        raw_wtx.push(tx.vin.len().try_into()?); // Append input count to raw witness transaction
+        raw_tx.extend(compact_size::encode(tx.vin.len())); // Append input count to raw transaction
+        raw_wtx.extend(compact_size::encode(tx.vin.len())); // Append input count to raw witness transaction
 
-        non_witness_bytes += 1; // Increment non-witness bytes count
+        non_witness_bytes += compact_size::encoded_len(tx.vin.len()); // Increment non-witness bytes count
 
         // INPUTS
         for input in tx.vin.iter() {
             // TXID REVERSED
-            let mut txid = hex::decode(&input.txid.clone())?; // Decode and reverse transaction ID
+            let mut txid = hex::decode(&input.txid)?; // Decode and reverse transaction ID
             txid.reverse();
 
             // SCRIPT SIG
-            let script_sig = hex::decode(&input.scriptsig.clone().unwrap())?; // Decode script signature
+            let script_sig = hex::decode(input.scriptsig.as_deref().unwrap_or_default())?; // Decode script signature, a missing one is empty
             let script_sig_len = script_sig.len(); // Get script signature length
 
             // Append transaction ID and output index to raw transaction
@@ -184,17 +172,13 @@ fn serialise_tx(tx: &Transaction) -> Result<(bool, Vec<u8>, Vec<u8>, usize, u64)
 
             non_witness_bytes += 32 + 4; // Update non-witness bytes count
 
-            if script_sig.len() >= 255 {
-                return Ok((false, Vec::new(), Vec::new(), 0, 0)); // Return if script signature length exceeds limit
-            }
-
             // Append script signature length and script signature to raw transaction and raw witness transaction
-            raw_tx.push(script_sig.len().try_into()?);
-            raw_wtx.push(script_sig.len().try_into()?);
+            raw_tx.extend(compact_size::encode(script_sig_len));
+            raw_wtx.extend(compact_size::encode(script_sig_len));
 
-            non_witness_bytes += 1; // Update non-witness bytes count
+            non_witness_bytes += compact_size::encoded_len(script_sig_len); // Update non-witness bytes count
 
-            if script_sig.len() != 0 {
+            if !script_sig.is_empty() {
                 raw_tx.extend_from_slice(&script_sig);
                 raw_wtx.extend_from_slice(&script_sig);
 
@@ -207,18 +191,15 @@ fn serialise_tx(tx: &Transaction) -> Result<(bool, Vec<u8>, Vec<u8>, usize, u64)
         }
 
         // OUTPUT COUNT
-        if tx.vout.len() >= 255 {
-            return Ok((false, Vec::new(), Vec::new(), 0, 0)); // Return if output count exceeds limit
-        }
-        raw_tx.push(tx.vout.len().try_into()?); // Append output count to raw transaction
-        raw_wtx.push(tx.vout.len().try_into()?); // Append output count to raw witness transaction
+        raw_tx.extend(compact_size::encode(tx.vout.len())); // Append output count to raw transaction
+        raw_wtx.extend(compact_size::encode(tx.vout.len())); // Append output count to raw witness transaction
 
-        non_witness_bytes += 1; // Increment non-witness bytes count
+        non_witness_bytes += compact_size::encoded_len(tx.vout.len()); // Increment non-witness bytes count
 
         // OUTPUTS
         for output in tx.vout.iter() {
             // SCRIPT PUB KEY
-            let scriptpubkey = hex::decode(&output.scriptpubkey.clone())?; // Decode script public key
+            let scriptpubkey = hex::decode(&output.scriptpubkey)?; // Decode script public key
             let scriptpubkey_len = scriptpubkey.len(); // Get script public key length
 
             // Append output value to raw transaction and raw witness transaction
@@ -227,34 +208,31 @@ fn serialise_tx(tx: &Transaction) -> Result<(bool, Vec<u8>, Vec<u8>, usize, u64)
 
             non_witness_bytes += 8; // Update non-witness bytes count
 
-            if scriptpubkey.len() >= 50 {
-                return Ok((false, Vec::new(), Vec::new(), 0, 0)); // Return if script public key length exceeds limit
-            }
             // Append script public key length and script public key to raw transaction and raw witness transaction
-            raw_tx.push(scriptpubkey.len().try_into()?);
-            raw_wtx.push(scriptpubkey.len().try_into()?);
+            raw_tx.extend(compact_size::encode(scriptpubkey_len));
+            raw_wtx.extend(compact_size::encode(scriptpubkey_len));
             raw_tx.extend_from_slice(&scriptpubkey);
             raw_wtx.extend_from_slice(&scriptpubkey);
 
-            non_witness_bytes += 1 + scriptpubkey_len; // Update non-witness bytes count
+            non_witness_bytes += compact_size::encoded_len(scriptpubkey_len) + scriptpubkey_len; // Update non-witness bytes count
         }
 
         // Witness only in WTX
         for input in tx.vin.iter() {
-            let witness = input.witness.clone().unwrap(); // Clone witness
-            // let witness_len = witness.len();
+            let witness = input.witness.clone().unwrap_or_default(); // Clone witness, legacy inputs of a mixed tx have none
 
-            raw_wtx.push(witness.len().try_into()?); // Append witness length to raw witness transaction
+            raw_wtx.extend(compact_size::encode(witness.len())); // Append witness length to raw witness transaction
 
-            witness_bytes += 1; // Increment witness bytes count
+            witness_bytes += compact_size::encoded_len(witness.len()); // Increment witness bytes count
 
             for item in witness {
                 let item_bytes = hex::decode(&item)?; // Decode item
                 let item_bytes_len = item_bytes.len(); // Get item length
-                raw_wtx.push(item_bytes.len().try_into()?); // Append item length to raw witness transaction
+
+                raw_wtx.extend(compact_size::encode(item_bytes_len)); // Append item length to raw witness transaction
                 raw_wtx.extend_from_slice(&item_bytes); // Append item to raw witness transaction
 
-                witness_bytes += 1 + item_bytes_len; // Update witness bytes count
+                witness_bytes += compact_size::encoded_len(item_bytes_len) + item_bytes_len; // Update witness bytes count
             }
         }
 
@@ -267,7 +245,7 @@ fn serialise_tx(tx: &Transaction) -> Result<(bool, Vec<u8>, Vec<u8>, usize, u64)
 
     let tx_weight = (non_witness_bytes * 4) + (witness_bytes); // Calculate transaction weight
 
-    Ok((true, raw_tx, raw_wtx, tx_weight, fees)) // Return serialized transaction data
+    Ok((raw_tx, raw_wtx, tx_weight, fees)) // Return serialized transaction data
 }
 
 // Test module
@@ -276,6 +254,7 @@ mod test {
     use std::fs; // Import file system module
 
     use super::*;
+    use crate::fixtures::load;
 
     #[test]
     fn test2() -> Result<()> {
@@ -286,7 +265,7 @@ mod test {
 
         let transaction: Transaction = serde_json::from_str(&data)?; // Deserialize JSON data into Transaction struct
 
-        let (_, tx, wtx, _, _) = serialise_tx(&transaction)?; // Serialize transaction
+        let (tx, wtx, _, _) = serialise_tx(&transaction)?; // Serialize transaction
 
         println!("{}", hex::encode(tx)); // Print hexadecimal encoding of raw transaction
         println!("{}", hex::encode(wtx)); // Print hexadecimal encoding of raw witness transaction
@@ -294,4 +273,47 @@ mod test {
         Ok(()) // Return Ok indicating success
     }
 
+    #[test]
+    fn test_txids_match_file_names() -> Result<()> {
+        let mut large = 0;
+
+        // EVERY MEMPOOL FILE IS NAMED AFTER THE SHA256 OF ITS TXID, LARGE TXS INCLUDED
+        for entry in fs::read_dir("./mempool")? {
+            let path = entry?.path();
+            let transaction: Transaction = serde_json::from_str(&fs::read_to_string(&path)?)?;
+
+            let (tx, _, _, _) = serialise_tx(&transaction)?;
+            let mut txid = double_sha256(&tx);
+            txid.reverse();
+            assert_eq!(
+                format!("{}.json", hex::encode(Sha256::digest(txid))),
+                path.file_name().unwrap().to_string_lossy()
+            );
+
+            if transaction.vin.len() >= 0xfd || tx.len() > 0xffff {
+                large += 1;
+            }
+        }
+
+        assert!(large > 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mixed_inputs_serialise_as_segwit() -> Result<()> {
+        // THE FIRST INPUT SPENDS P2PKH, THE SECOND ONE P2WPKH
+        let txid_hash = "753b075bd84cdc7400acaf8b52cea866f099de2ebb70ea89fc98abe5f1162d5f";
+        let transaction = load(txid_hash)?;
+
+        let (tx, wtx, _, _) = serialise_tx(&transaction)?;
+        assert_ne!(tx, wtx); // Witness data of the second input only lives in the wtx
+
+        // THE MEMPOOL FILE NAME IS THE SHA256 OF THE TXID IN DISPLAY ORDER
+        let mut txid = double_sha256(&tx);
+        txid.reverse();
+        assert_eq!(hex::encode(Sha256::digest(txid)), txid_hash);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,128 @@
+// BLOCK SUBSIDY SCHEDULE AND THE LIMIT ON WHAT A COINBASE MAY CLAIM
+use std::fmt;
+
+use crate::transaction::Transaction; // Importing the Transaction struct
+
+pub const COIN: u64 = 100_000_000;
+
+// NO VALUE OR SUM OF VALUES MAY EVER BE LARGER THAN THE TOTAL SUPPLY
+pub const MAX_MONEY: u64 = 21_000_000 * COIN;
+
+// THE FIRST SUBSIDY, HALVED EVERY `halving_interval()` BLOCKS
+pub const INITIAL_SUBSIDY: u64 = 50 * COIN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl Network {
+    pub fn from_name(name: &str) -> Option<Network> {
+        match name {
+            "mainnet" | "main" => Some(Network::Mainnet),
+            "testnet" | "test" => Some(Network::Testnet),
+            "signet" => Some(Network::Signet),
+            "regtest" => Some(Network::Regtest),
+            _ => None,
+        }
+    }
+
+    pub fn halving_interval(&self) -> u32 {
+        match self {
+            Network::Mainnet | Network::Testnet | Network::Signet => 210_000,
+            Network::Regtest => 150,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubsidyError {
+    CoinbaseValueTooHigh { claimed: u64, allowed: u64 },
+}
+
+impl fmt::Display for SubsidyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubsidyError::CoinbaseValueTooHigh { claimed, allowed } => {
+                write!(f, "COINBASE: CLAIMS {} SATS BUT ONLY {} ARE ALLOWED", claimed, allowed)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SubsidyError {}
+
+pub fn block_subsidy(height: u32, network: Network) -> u64 {
+    let halvings = height / network.halving_interval();
+
+    // A SHIFT OF 64 OR MORE IS UNDEFINED, THE SUBSIDY IS LONG GONE BY THEN ANYWAY
+    if halvings >= 64 {
+        return 0;
+    }
+    INITIAL_SUBSIDY >> halvings
+}
+
+// THE COINBASE MAY TAKE LESS THAN SUBSIDY PLUS FEES (THE REST IS BURNT) BUT NEVER MORE
+pub fn check_coinbase_value(
+    coinbase: &Transaction,
+    height: u32,
+    network: Network,
+    fees: u64,
+) -> Result<(), SubsidyError> {
+    let claimed = coinbase.vout.iter().fold(0u64, |sum, output| sum.saturating_add(output.value));
+    let allowed = block_subsidy(height, network).saturating_add(fees);
+
+    if claimed > allowed {
+        return Err(SubsidyError::CoinbaseValueTooHigh { claimed, allowed });
+    }
+    Ok(())
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block_mine::coinbase::CoinbaseBuilder;
+    use crate::error::Result;
+
+    #[test]
+    fn test_halving_schedule() {
+        assert_eq!(block_subsidy(0, Network::Mainnet), 50 * COIN);
+        assert_eq!(block_subsidy(209_999, Network::Mainnet), 50 * COIN);
+        assert_eq!(block_subsidy(210_000, Network::Mainnet), 25 * COIN);
+        assert_eq!(block_subsidy(840_000, Network::Mainnet), 312_500_000);
+        assert_eq!(block_subsidy(900_000, Network::Testnet), 312_500_000);
+
+        // THE LAST SATOSHI IS PAID DURING THE 33RD HALVING PERIOD
+        assert_eq!(block_subsidy(32 * 210_000, Network::Mainnet), 1);
+        assert_eq!(block_subsidy(33 * 210_000, Network::Mainnet), 0);
+        assert_eq!(block_subsidy(u32::MAX, Network::Regtest), 0);
+
+        assert_eq!(Network::from_name("regtest"), Some(Network::Regtest));
+        assert_eq!(Network::from_name("litecoin"), None);
+        assert_eq!(block_subsidy(149, Network::Regtest), 50 * COIN);
+        assert_eq!(block_subsidy(150, Network::Regtest), 25 * COIN);
+    }
+
+    #[test]
+    fn test_coinbase_value_check() -> Result<()> {
+        let fees = 12_345;
+        let allowed = block_subsidy(900_000, Network::Mainnet) + fees;
+        let builder = CoinbaseBuilder::new(900_000).payout_address("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa", 1)?;
+
+        assert!(check_coinbase_value(&builder.build(allowed)?, 900_000, Network::Mainnet, fees).is_ok());
+        assert!(check_coinbase_value(&builder.build(allowed - 1)?, 900_000, Network::Mainnet, fees).is_ok());
+        assert_eq!(
+            check_coinbase_value(&builder.build(allowed + 1)?, 900_000, Network::Mainnet, fees),
+            Err(SubsidyError::CoinbaseValueTooHigh { claimed: allowed + 1, allowed })
+        );
+
+        // THE SAME CLAIM IS TOO MUCH ONE HALVING LATER
+        assert!(check_coinbase_value(&builder.build(allowed)?, 1_050_000, Network::Mainnet, fees).is_err());
+
+        Ok(())
+    }
+}
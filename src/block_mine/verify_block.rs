@@ -0,0 +1,412 @@
+// READS A MINED BLOCK BACK FROM OUTPUT.TXT AND CHECKS IT AGAINST THE MEMPOOL
+//
+//   LINE 1    80 BYTE HEADER
+//   LINE 2    SERIALISED COINBASE (WITH WITNESS)
+//   LINE 3..  TXIDS IN BLOCK ORDER, THE COINBASE TXID FIRST
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use sha2::{Digest, Sha256}; // Importing functions for SHA-256 hashing
+
+use crate::compact_size; // Importing the CompactSize encoding
+use crate::error::Result; // Importing Result type from the crate
+use crate::transaction::{OutPoint, Transaction}; // Importing the Transaction struct and outpoints
+use crate::validation_checks::rejection::Rejection; // Importing the reason a transaction is not final
+use crate::validation_checks::sigops::{transaction_sigop_cost, MAX_BLOCK_SIGOPS_COST}; // Importing the sigop cost counting
+use crate::validation_checks::timelock::{check_final, ChainTip, CoinAge}; // Importing the finality checks
+use crate::validation_checks::check_tx_with; // Importing the transaction validation
+use crate::validation_checks::policy::Policy; // Importing the consensus only rule set
+
+use super::coinbase::{coinbase_prevout, parse_bip34_height}; // Importing the coinbase helpers
+use super::deserialise_tx::deserialise_tx; // Importing the raw transaction decoder
+use super::merkle_root::merkel_root; // Importing the merkle root calculation
+use super::pow::{bits_to_target, hash_meets_target, BlockHeader, HEADER_SIZE}; // Importing the header and target helpers
+use super::serialise_tx::{double_sha256, serialise_tx}; // Importing the serialisation helpers
+use super::subsidy::{check_coinbase_value, Network, SubsidyError}; // Importing the coinbase value check
+
+pub const MAX_BLOCK_WEIGHT: usize = 4_000_000;
+
+// OP_RETURN PUSH(36) AND THE BIP141 COMMITMENT HEADER
+const WITNESS_COMMITMENT_PREFIX: &str = "6a24aa21a9ed";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockViolation {
+    MalformedHeader,
+    MalformedCoinbase(String),
+    NotCoinbase,
+    MalformedTxid(String),
+    CoinbaseTxidMismatch { listed: String, computed: String },
+    MissingHeight,
+    CoinbaseValue(SubsidyError),
+    MerkleRootMismatch { header: String, computed: String },
+    InvalidBits(u32),
+    InsufficientWork { hash: String, target: String },
+    MissingWitnessCommitment,
+    InvalidWitnessReservedValue,
+    WitnessCommitmentMismatch { committed: String, computed: String },
+    WeightExceeded { weight: usize, limit: usize },
+    SigopCostExceeded { cost: usize, limit: usize },
+    UnknownTransaction(String),
+    InvalidTransaction(String),
+    DuplicateTransaction(String),
+    ChildBeforeParent { child: String, parent: String },
+    ConflictingSpend { txid: String, outpoint: String },
+    NonFinalTransaction { txid: String, rejection: Rejection },
+}
+
+impl fmt::Display for BlockViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockViolation::MalformedHeader => write!(f, "HEADER: NOT 80 BYTES OF HEX"),
+            BlockViolation::MalformedCoinbase(error) => write!(f, "COINBASE: CANNOT BE DECODED ({})", error),
+            BlockViolation::NotCoinbase => write!(f, "COINBASE: NOT A SINGLE INPUT SPENDING THE NULL OUTPOINT"),
+            BlockViolation::MalformedTxid(txid) => write!(f, "TXIDS: {} IS NOT 32 BYTES OF HEX", txid),
+            BlockViolation::CoinbaseTxidMismatch { listed, computed } => {
+                write!(f, "COINBASE: LISTED AS {} BUT ITS TXID IS {}", listed, computed)
+            }
+            BlockViolation::MissingHeight => write!(f, "COINBASE: SCRIPT SIG DOES NOT START WITH THE BIP34 HEIGHT"),
+            BlockViolation::CoinbaseValue(error) => write!(f, "{}", error),
+            BlockViolation::MerkleRootMismatch { header, computed } => {
+                write!(f, "MERKLE ROOT: HEADER HAS {} BUT THE TXIDS GIVE {}", header, computed)
+            }
+            BlockViolation::InvalidBits(bits) => write!(f, "POW: BITS {:08x} IS NOT A VALID TARGET", bits),
+            BlockViolation::InsufficientWork { hash, target } => {
+                write!(f, "POW: BLOCK HASH {} IS ABOVE THE TARGET {}", hash, target)
+            }
+            BlockViolation::MissingWitnessCommitment => write!(f, "WITNESS: BLOCK HAS WITNESS DATA BUT NO COMMITMENT"),
+            BlockViolation::InvalidWitnessReservedValue => {
+                write!(f, "WITNESS: COINBASE WITNESS IS NOT A SINGLE 32 BYTE RESERVED VALUE")
+            }
+            BlockViolation::WitnessCommitmentMismatch { committed, computed } => {
+                write!(f, "WITNESS: COINBASE COMMITS TO {} BUT THE WTXIDS GIVE {}", committed, computed)
+            }
+            BlockViolation::WeightExceeded { weight, limit } => write!(f, "WEIGHT: {} WU IS OVER THE {} LIMIT", weight, limit),
+            BlockViolation::SigopCostExceeded { cost, limit } => {
+                write!(f, "SIGOPS: COST OF {} IS OVER THE {} LIMIT", cost, limit)
+            }
+            BlockViolation::UnknownTransaction(txid) => write!(f, "TX {}: NOT IN THE MEMPOOL", txid),
+            BlockViolation::InvalidTransaction(txid) => write!(f, "TX {}: FAILS VALIDATION", txid),
+            BlockViolation::DuplicateTransaction(txid) => write!(f, "TX {}: INCLUDED MORE THAN ONCE", txid),
+            BlockViolation::ChildBeforeParent { child, parent } => {
+                write!(f, "TX {}: COMES BEFORE ITS PARENT {}", child, parent)
+            }
+            BlockViolation::ConflictingSpend { txid, outpoint } => {
+                write!(f, "TX {}: SPENDS {} WHICH AN EARLIER TX ALREADY SPENT", txid, outpoint)
+            }
+            BlockViolation::NonFinalTransaction { txid, rejection } => write!(f, "TX {}: NOT FINAL, {}", txid, rejection),
+        }
+    }
+}
+
+// Checks the block in `block_path` and returns every violation found, an empty list means the block is valid
+pub fn verify_block(block_path: &Path, mempool_dir: &Path, network: Network) -> Result<Vec<BlockViolation>> {
+    let contents = fs::read_to_string(block_path)?;
+    let mut lines = contents.lines().map(|line| line.trim()).filter(|line| !line.is_empty());
+    let mut violations: Vec<BlockViolation> = Vec::new();
+
+    // HEADER
+    let header = match lines.next().and_then(|line| hex::decode(line).ok()) {
+        Some(bytes) if bytes.len() == HEADER_SIZE => BlockHeader::deserialise(&bytes.try_into().unwrap()),
+        _ => return Ok(vec![BlockViolation::MalformedHeader]),
+    };
+
+    // COINBASE
+    let coinbase = match lines.next().map(|line| deserialise_tx(line, &[coinbase_prevout()])) {
+        Some(Ok(coinbase)) => coinbase,
+        Some(Err(error)) => return Ok(vec![BlockViolation::MalformedCoinbase(error.to_string())]),
+        None => return Ok(vec![BlockViolation::MalformedCoinbase("MISSING".to_string())]),
+    };
+    if coinbase.vin.len() != 1 || !coinbase.vin[0].is_coinbase {
+        return Ok(vec![BlockViolation::NotCoinbase]);
+    }
+    let (coinbase_raw_tx, _, coinbase_weight, _) = serialise_tx(&coinbase)?;
+    let coinbase_txid = display_hash(&double_sha256(&coinbase_raw_tx));
+
+    // TXIDS, THE FIRST ONE IS THE COINBASE
+    let txids: Vec<String> = lines.map(|line| line.to_string()).collect();
+    if let Some(txid) = txids.iter().find(|txid| txid.len() != 64 || hex::decode(txid).is_err()) {
+        return Ok(vec![BlockViolation::MalformedTxid(txid.clone())]);
+    }
+    match txids.first() {
+        Some(listed) if *listed == coinbase_txid => {}
+        listed => violations.push(BlockViolation::CoinbaseTxidMismatch {
+            listed: listed.cloned().unwrap_or_default(),
+            computed: coinbase_txid.clone(),
+        }),
+    }
+
+    // MERKLE ROOT OVER THE TXIDS AS LISTED
+    let computed_root = if txids.is_empty() { String::new() } else { merkel_root(txids.clone())? };
+    if computed_root != hex::encode(header.merkle_root) {
+        violations.push(BlockViolation::MerkleRootMismatch {
+            header: hex::encode(header.merkle_root),
+            computed: computed_root,
+        });
+    }
+
+    // PROOF OF WORK
+    match bits_to_target(header.bits) {
+        Some(target) => {
+            let hash = header.hash();
+            if !hash_meets_target(&hash, &target) {
+                violations.push(BlockViolation::InsufficientWork { hash: display_hash(&hash), target: hex::encode(target) });
+            }
+        }
+        None => violations.push(BlockViolation::InvalidBits(header.bits)),
+    }
+
+    // THE REFERENCED TRANSACTIONS, IN BLOCK ORDER
+    let mut position: HashMap<&str, usize> = HashMap::new();
+    let mut transactions: Vec<(usize, &str, Transaction)> = Vec::new();
+    for (index, txid) in txids.iter().enumerate().skip(1) {
+        if position.contains_key(txid.as_str()) {
+            violations.push(BlockViolation::DuplicateTransaction(txid.clone()));
+            continue;
+        }
+        position.insert(txid.as_str(), index);
+        match load_mempool_tx(mempool_dir, txid) {
+            Some(transaction) => transactions.push((index, txid.as_str(), transaction)),
+            None => violations.push(BlockViolation::UnknownTransaction(txid.clone())),
+        }
+    }
+
+    // THE MEDIAN TIME PAST IS BEFORE THE HEADER TIME, SO A TRANSACTION THAT IS NOT FINAL AT THE
+    // HEADER TIME CANNOT BE FINAL UNDER BIP113 EITHER. PARENTS IN THE BLOCK CONFIRM AT ITS HEIGHT.
+    let script_sig = hex::decode(coinbase.vin[0].scriptsig.clone().unwrap_or_default())?;
+    let height = parse_bip34_height(&script_sig);
+    let tip = height.map(|height| ChainTip { height, median_time_past: header.time });
+
+    let mut weight = 4 * (HEADER_SIZE + compact_size::encoded_len(txids.len())) + coinbase_weight;
+    let mut sigop_cost = transaction_sigop_cost(&coinbase);
+    let mut fees = 0;
+    let mut wtxids: Vec<String> = vec![hex::encode([0u8; 32])]; // The coinbase wtxid is all zeros
+    let mut has_witness = false;
+    let mut spent: HashSet<OutPoint> = HashSet::new();
+
+    for (index, txid, transaction) in transactions.iter() {
+        let (_, raw_wtx, tx_weight, tx_fees) = serialise_tx(transaction)?;
+        weight += tx_weight;
+        sigop_cost += transaction_sigop_cost(transaction);
+        fees += tx_fees;
+        wtxids.push(display_hash(&double_sha256(&raw_wtx)));
+        has_witness |= transaction.vin.iter().any(|input| input.witness.is_some());
+
+        // A MINED BLOCK ONLY HAS TO FOLLOW CONSENSUS, STANDARDNESS IS OUR OWN RELAY CHOICE
+        if check_tx_with(transaction, &Policy::consensus()).is_err() {
+            violations.push(BlockViolation::InvalidTransaction(txid.to_string()));
+        }
+
+        if let Some(tip) = tip.as_ref() {
+            let coins: Vec<Option<CoinAge>> = transaction
+                .vin
+                .iter()
+                .map(|input| position.contains_key(input.txid.as_str()).then(|| CoinAge::unconfirmed(tip)))
+                .collect();
+            if let Err(rejection) = check_final(transaction, tip, &coins) {
+                violations.push(BlockViolation::NonFinalTransaction { txid: txid.to_string(), rejection });
+            }
+        }
+
+        for input in transaction.vin.iter() {
+            // A PARENT IN THE BLOCK MUST COME FIRST
+            if let Some(parent_index) = position.get(input.txid.as_str()) {
+                if parent_index > index {
+                    violations.push(BlockViolation::ChildBeforeParent {
+                        child: txid.to_string(),
+                        parent: input.txid.clone(),
+                    });
+                }
+            }
+
+            let outpoint = input.outpoint();
+            if !spent.insert(outpoint.clone()) {
+                violations.push(BlockViolation::ConflictingSpend { txid: txid.to_string(), outpoint: outpoint.to_string() });
+            }
+        }
+    }
+
+    if weight > MAX_BLOCK_WEIGHT {
+        violations.push(BlockViolation::WeightExceeded { weight, limit: MAX_BLOCK_WEIGHT });
+    }
+    if sigop_cost > MAX_BLOCK_SIGOPS_COST {
+        violations.push(BlockViolation::SigopCostExceeded { cost: sigop_cost, limit: MAX_BLOCK_SIGOPS_COST });
+    }
+
+    // WITNESS COMMITMENT, THE LAST MATCHING OUTPUT COUNTS
+    let commitment = coinbase
+        .vout
+        .iter()
+        .rev()
+        .find(|output| output.scriptpubkey.starts_with(WITNESS_COMMITMENT_PREFIX) && output.scriptpubkey.len() >= 76);
+    match commitment {
+        Some(output) => {
+            let reserved_value = match coinbase.vin[0].witness.as_deref() {
+                Some([item]) => hex::decode(item).ok().filter(|bytes| bytes.len() == 32),
+                _ => None,
+            };
+            match reserved_value {
+                Some(reserved_value) => {
+                    let witness_root = hex::decode(merkel_root(wtxids)?)?;
+                    let computed = hex::encode(double_sha256(&[witness_root, reserved_value].concat()));
+                    let committed = output.scriptpubkey[12..76].to_string();
+                    if committed != computed {
+                        violations.push(BlockViolation::WitnessCommitmentMismatch { committed, computed });
+                    }
+                }
+                None => violations.push(BlockViolation::InvalidWitnessReservedValue),
+            }
+        }
+        None if has_witness => violations.push(BlockViolation::MissingWitnessCommitment),
+        None => {}
+    }
+
+    // COINBASE VALUE AGAINST THE SUBSIDY AT ITS HEIGHT
+    match height {
+        Some(height) => {
+            if let Err(error) = check_coinbase_value(&coinbase, height, network, fees) {
+                violations.push(BlockViolation::CoinbaseValue(error));
+            }
+        }
+        None => violations.push(BlockViolation::MissingHeight),
+    }
+
+    Ok(violations)
+}
+
+// MEMPOOL FILES ARE NAMED AFTER THE SHA256 OF THE TXID IN DISPLAY ORDER
+fn load_mempool_tx(mempool_dir: &Path, txid: &str) -> Option<Transaction> {
+    let txid_bytes = hex::decode(txid).ok()?;
+    let path = mempool_dir.join(format!("{}.json", hex::encode(Sha256::digest(txid_bytes))));
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+fn display_hash(hash: &[u8]) -> String {
+    let mut hash = hash.to_vec();
+    hash.reverse();
+    hex::encode(hash)
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::block_mine::coinbase::CoinbaseBuilder;
+    use crate::block_mine::merkle_root::generate_roots;
+    use crate::block_mine::pow::mine;
+    use crate::block_mine::serialise_tx::TxEntry;
+    use crate::fixtures::entry;
+
+    const PARENT: &str = "4e8b1cc2c9ed315153b2457dec94128db0ba0a1dcebc136e187d0c4a0a81ce15";
+    const CHILD: &str = "ef4e2bf52d02426df890d8fcabfe807c479627a8406cd4b8fa9b236f8df19213";
+    const LEGACY: &str = "19175e830784abf518b3ca20319177fcdff20b0bc5253b984a4515e85ec116b4";
+
+    // ANY TARGET WITH THE TOP BIT CLEAR, SO MINING TAKES A COUPLE OF HASHES
+    const EASY_BITS: u32 = 0x207fffff;
+
+    // MINES THE ENTRIES IN THE GIVEN ORDER AND WRITES THE BLOCK THE WAY `valid_block_header` DOES
+    fn write_block(name: &str, block: &[TxEntry], txids_override: Option<Vec<String>>) -> Result<PathBuf> {
+        let coinbase = CoinbaseBuilder::new(900000).payout_address("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa", 1)?;
+        let (merkel_root, coinbase_tx, _, txids) = generate_roots(block, &coinbase, Network::Mainnet)?;
+
+        let header = BlockHeader {
+            version: 4,
+            prev_block_hash: [0; 32],
+            merkle_root: hex::decode(merkel_root)?.try_into().unwrap(),
+            time: 1713000000,
+            bits: EASY_BITS,
+            nonce: 0,
+        };
+        let header = mine(header, &bits_to_target(EASY_BITS).unwrap(), |_| unreachable!())?;
+
+        let path = std::env::temp_dir().join(format!("verify_block_{}_{}.txt", name, std::process::id()));
+        let mut file = fs::File::create(&path)?;
+        writeln!(file, "{}", hex::encode(header.serialise()))?;
+        writeln!(file, "{}", coinbase_tx)?;
+        for txid in txids_override.unwrap_or(txids) {
+            writeln!(file, "{}", txid)?;
+        }
+        Ok(path)
+    }
+
+    fn check(path: &Path) -> Result<Vec<BlockViolation>> {
+        let violations = verify_block(path, Path::new("./mempool"), Network::Mainnet)?;
+        fs::remove_file(path)?;
+        Ok(violations)
+    }
+
+    #[test]
+    fn test_valid_block() -> Result<()> {
+        let block = vec![entry(PARENT)?, entry(CHILD)?, entry(LEGACY)?];
+        let path = write_block("valid", &block, None)?;
+        assert_eq!(check(&path)?, vec![]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_violations() -> Result<()> {
+        let parent = entry(PARENT)?;
+        let child = entry(CHILD)?;
+
+        // CHILD FIRST, WITH A MATCHING MERKLE ROOT AND COMMITMENT
+        let path = write_block("order", &[child.clone(), parent.clone()], None)?;
+        assert_eq!(
+            check(&path)?,
+            vec![BlockViolation::ChildBeforeParent { child: child.0.clone(), parent: parent.0.clone() }]
+        );
+
+        // A TXID THE HEADER AND COMMITMENT DO NOT COVER
+        let block = vec![parent.clone()];
+        let coinbase = CoinbaseBuilder::new(900000).payout_address("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa", 1)?;
+        let (_, _, coinbase_txid, _) = generate_roots(&block, &coinbase, Network::Mainnet)?;
+        let path = write_block("extra", &block, Some(vec![coinbase_txid.clone(), parent.0.clone(), child.0.clone()]))?;
+        let violations = check(&path)?;
+        assert!(matches!(violations[0], BlockViolation::MerkleRootMismatch { .. }));
+        assert!(matches!(violations[1], BlockViolation::WitnessCommitmentMismatch { .. }));
+        assert_eq!(violations.len(), 2);
+
+        // UNKNOWN AND DUPLICATED TXIDS
+        let path = write_block("unknown", &block, Some(vec![coinbase_txid, parent.0.clone(), parent.0.clone(), "00".repeat(32)]))?;
+        let violations = check(&path)?;
+        assert!(violations.contains(&BlockViolation::DuplicateTransaction(parent.0.clone())));
+        assert!(violations.contains(&BlockViolation::UnknownTransaction("00".repeat(32))));
+
+        let path = write_block("malformed", &block, Some(vec!["not a txid".to_string()]))?;
+        assert_eq!(check(&path)?, vec![BlockViolation::MalformedTxid("not a txid".to_string())]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tampered_header_and_coinbase() -> Result<()> {
+        let path = write_block("tampered", &[entry(LEGACY)?], None)?;
+        let contents = fs::read_to_string(&path)?;
+        let lines: Vec<&str> = contents.lines().collect();
+
+        // BITS 0x1d00ffff NEEDS FAR MORE WORK THAN THE EASY TARGET THE HEADER WAS MINED FOR
+        let mut header = BlockHeader::deserialise(&hex::decode(lines[0])?.try_into().unwrap());
+        header.bits = 0x1d00ffff;
+        let hard = format!("{}\n{}", hex::encode(header.serialise()), lines[1..].join("\n"));
+        fs::write(&path, hard)?;
+        assert!(matches!(check(&path)?[..], [BlockViolation::InsufficientWork { .. }]));
+
+        // THE COINBASE CLAIMING ONE SAT MORE, WHICH ALSO CHANGES ITS TXID
+        let mut coinbase = deserialise_tx(lines[1], &[coinbase_prevout()])?;
+        coinbase.vout[0].value += 1;
+        let (_, raw_wtx, _, _) = serialise_tx(&coinbase)?;
+        let path = write_block("overpaid", &[entry(LEGACY)?], None)?;
+        let contents = fs::read_to_string(&path)?;
+        let lines: Vec<&str> = contents.lines().collect();
+        fs::write(&path, format!("{}\n{}\n{}", lines[0], hex::encode(raw_wtx), lines[2..].join("\n")))?;
+        let violations = check(&path)?;
+        assert!(matches!(violations[0], BlockViolation::CoinbaseTxidMismatch { .. }));
+        assert!(matches!(violations.last(), Some(BlockViolation::CoinbaseValue(_))));
+
+        Ok(())
+    }
+}
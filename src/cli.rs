@@ -0,0 +1,358 @@
+// COMMAND LINE PARSING
+//
+//   (NONE)             VALIDATE THE MEMPOOL, THEN MINE A BLOCK FROM WHAT PASSED
+//   validate           VALIDATE THE MEMPOOL AND PRINT WHY EVERY REJECTED TRANSACTION WAS LEFT OUT
+//   template           FILL A BLOCK FROM THE VALID SET AND WRITE IT WITHOUT MINING
+//   mine               FILL A BLOCK FROM THE VALID SET, MINE IT AND WRITE IT
+//   verify-block       CHECK A MINED BLOCK FILE AGAINST THE MEMPOOL
+//   inspect-tx         DECODE A RAW TRANSACTION
+//   disconnect-block   ROLL THE UTXO SET BACK WITH THE UNDO DATA OF A CONNECTED BLOCK
+//
+// THE VALID SET STAYS IN MEMORY UNLESS --valid-mempool IS GIVEN. VALIDATE AND THE DEFAULT RUN THEN
+// WRITE IT THERE, WHILE TEMPLATE AND MINE READ IT BACK INSTEAD OF VALIDATING AGAIN.
+//
+// WITH --utxo-set THE PREVOUTS ARE CHECKED AGAINST THAT SNAPSHOT, AND A MINED BLOCK IS CONNECTED TO
+// IT WITH THE UNDO DATA WRITTEN NEXT TO IT.
+//
+// WITH --chain MINE AND THE DEFAULT RUN BUILD ON THE TIP OF A LOCAL HEADER CHAIN INSTEAD OF MINING AN
+// ORPHAN BLOCK, --blocks AT A TIME. --height, --median-time-past AND --target ONLY SEED A NEW CHAIN,
+// AFTER THAT THE TARGET IS RETARGETED EVERY 2016 BLOCKS AND NEVER GETS EASIER THAN IT STARTED.
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::block_mine::block::BLOCK_HEIGHT; // Importing the default height of the block being mined
+use crate::block_mine::knapsack::FillMode; // Importing the block filling strategies
+use crate::block_mine::subsidy::Network; // Importing the networks the subsidy schedule knows about
+use crate::block_mine::verify_block::MAX_BLOCK_WEIGHT; // Importing the consensus weight limit
+use crate::validation_checks::policy::{Policy, POLICY_RULES}; // Importing the standardness rules that can be switched off
+
+pub const USAGE: &str = "\
+USAGE: code-challenge-2024-IamEzio [COMMAND] [ARGUMENT] [FLAGS]
+
+COMMANDS:
+    (none)                       validate, then mine
+    validate                     validate --mempool and print the rejection report
+    template                     fill a block from the valid set and write it to --output unmined
+    mine                         fill a block from the valid set, mine it and write it to --output
+    verify-block [FILE]          check a mined block (default --output) against --mempool
+    inspect-tx <RAW HEX>         decode a raw transaction, prevouts are looked up in --mempool
+    disconnect-block <UNDO FILE> roll --utxo-set back to before the block the undo data belongs to
+    help                         print this message
+
+FLAGS:
+    --mempool <DIR>              default ./mempool
+    --valid-mempool <DIR>        where the valid set is written to, or read from by template and mine
+    --output <FILE>              default ./output.txt
+    --rejection-report <FILE>    write the rejection report as JSON whenever the mempool is validated
+    --utxo-set <FILE>            snapshot the prevouts are checked against, a mined block is connected to it
+    --chain <FILE>               header chain to mine on top of, created at --height when missing. Each
+                                 block goes next to --output with its height added, e.g. output-900000.txt
+    --blocks <N>                 blocks mined one after the other with --chain, default 1
+    --target <HEX>               32 byte big endian target, default 0000ffff00..00
+    --weight-limit <WU>          block weight limit, default 4000000
+    --network <NAME>             mainnet, testnet, signet or regtest, default mainnet
+    --height <N>                 height of the block being mined, default 900000
+    --median-time-past <TIME>    median time past of the previous block, default an hour ago
+    --disable-policy <RULE,..>   switch standardness rules off: zero-value, min-fee, min-relay-fee,
+                                 max-weight, standard-outputs, op-return, dust, low-s, strict-encoding,
+                                 null-fail, minimal-if, clean-stack, or all
+    --optimise                   run the local search optimiser after package selection";
+
+// THE CHALLENGE'S DIFFICULTY TARGET
+const DEFAULT_TARGET: &str = "0000ffff00000000000000000000000000000000000000000000000000000000";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Run,
+    Validate,
+    Template,
+    Mine,
+    VerifyBlock,
+    InspectTx,
+    DisconnectBlock,
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub command: Command,
+    pub mempool: PathBuf,
+    pub valid_mempool: Option<PathBuf>, // The valid set only touches the disk when given
+    pub output: PathBuf,
+    pub rejection_report: Option<PathBuf>,
+    pub target: [u8; 32],
+    pub weight_limit: usize,
+    pub mode: FillMode,
+    pub network: Network,
+    pub height: u32,
+    pub median_time_past: Option<u32>, // Unix time, an hour before now when not given
+    pub policy: Policy, // Standardness on top of consensus when validating the mempool
+    pub utxo_set: Option<PathBuf>, // Without it the prevouts the mempool files claim are trusted
+    pub chain: Option<PathBuf>, // Without it a single block is mined on top of an all zero hash
+    pub blocks: u32,
+    pub argument: Option<String>, // Block file for verify-block, raw transaction for inspect-tx, undo file for disconnect-block
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliError {
+    UnknownCommand(String),
+    UnknownFlag(String),
+    MissingValue(String),
+    UnexpectedArgument(String),
+    MissingArgument(&'static str),
+    InvalidTarget(String),
+    InvalidWeightLimit(String),
+    InvalidBlockCount(String),
+    UnknownNetwork(String),
+    InvalidNumber { flag: String, value: String },
+    UnknownPolicyRule(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::UnknownCommand(command) => write!(f, "CLI: UNKNOWN COMMAND {}", command),
+            CliError::UnknownFlag(flag) => write!(f, "CLI: UNKNOWN FLAG {}", flag),
+            CliError::MissingValue(flag) => write!(f, "CLI: {} NEEDS A VALUE", flag),
+            CliError::UnexpectedArgument(argument) => write!(f, "CLI: UNEXPECTED ARGUMENT {}", argument),
+            CliError::MissingArgument(what) => write!(f, "CLI: MISSING {}", what),
+            CliError::InvalidTarget(target) => write!(f, "CLI: TARGET {} IS NOT 32 NON-ZERO HEX BYTES", target),
+            CliError::InvalidWeightLimit(limit) => {
+                write!(f, "CLI: WEIGHT LIMIT {} IS NOT BETWEEN 1 AND {}", limit, MAX_BLOCK_WEIGHT)
+            }
+            CliError::InvalidBlockCount(blocks) => write!(f, "CLI: BLOCK COUNT {} IS NOT A NUMBER ABOVE 0", blocks),
+            CliError::UnknownNetwork(name) => {
+                write!(f, "CLI: UNKNOWN NETWORK {}, EXPECTED mainnet, testnet, signet OR regtest", name)
+            }
+            CliError::InvalidNumber { flag, value } => write!(f, "CLI: {} {} IS NOT A 32 BIT NUMBER", flag, value),
+            CliError::UnknownPolicyRule(rule) => {
+                write!(f, "CLI: UNKNOWN POLICY RULE {}, EXPECTED all OR ONE OF {}", rule, POLICY_RULES.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            command: Command::Run,
+            mempool: PathBuf::from("./mempool"),
+            valid_mempool: None,
+            output: PathBuf::from("./output.txt"),
+            rejection_report: None,
+            target: parse_target(DEFAULT_TARGET).expect("DEFAULT TARGET IS VALID"),
+            weight_limit: MAX_BLOCK_WEIGHT,
+            mode: FillMode::Packages,
+            network: Network::Mainnet,
+            height: BLOCK_HEIGHT,
+            median_time_past: None,
+            policy: Policy::default(),
+            utxo_set: None,
+            chain: None,
+            blocks: 1,
+            argument: None,
+        }
+    }
+}
+
+// Parses everything after the program name, the command has to come first
+pub fn parse_args(args: &[String]) -> Result<Options, CliError> {
+    let mut options = Options::default();
+    let mut args = args.iter().peekable();
+
+    if let Some(first) = args.next_if(|arg| !arg.starts_with('-')) {
+        options.command = match first.as_str() {
+            "validate" => Command::Validate,
+            "template" => Command::Template,
+            "mine" => Command::Mine,
+            "verify-block" => Command::VerifyBlock,
+            "inspect-tx" => Command::InspectTx,
+            "disconnect-block" => Command::DisconnectBlock,
+            "help" => Command::Help,
+            _ => return Err(CliError::UnknownCommand(first.clone())),
+        };
+    }
+
+    while let Some(arg) = args.next() {
+        // FLAGS WITHOUT A VALUE
+        match arg.as_str() {
+            "--optimise" => {
+                options.mode = FillMode::Optimised;
+                continue;
+            }
+            "--help" | "-h" => {
+                options.command = Command::Help;
+                continue;
+            }
+            _ => {}
+        }
+
+        // ONLY VERIFY-BLOCK, INSPECT-TX AND DISCONNECT-BLOCK TAKE AN ARGUMENT, AND ONLY ONE
+        if !arg.starts_with('-') {
+            let takes_argument = matches!(options.command, Command::VerifyBlock | Command::InspectTx | Command::DisconnectBlock);
+            if !takes_argument || options.argument.is_some() {
+                return Err(CliError::UnexpectedArgument(arg.clone()));
+            }
+            options.argument = Some(arg.clone());
+            continue;
+        }
+
+        // FLAGS WITH A VALUE
+        let value = match arg.as_str() {
+            "--mempool" | "--valid-mempool" | "--output" | "--rejection-report" | "--target" | "--weight-limit"
+            | "--network" | "--height" | "--median-time-past" | "--disable-policy" | "--utxo-set" | "--chain"
+            | "--blocks" => {
+                args.next().ok_or_else(|| CliError::MissingValue(arg.clone()))?
+            }
+            _ => return Err(CliError::UnknownFlag(arg.clone())),
+        };
+        match arg.as_str() {
+            "--mempool" => options.mempool = PathBuf::from(value),
+            "--valid-mempool" => options.valid_mempool = Some(PathBuf::from(value)),
+            "--output" => options.output = PathBuf::from(value),
+            "--rejection-report" => options.rejection_report = Some(PathBuf::from(value)),
+            "--utxo-set" => options.utxo_set = Some(PathBuf::from(value)),
+            "--chain" => options.chain = Some(PathBuf::from(value)),
+            "--blocks" => {
+                options.blocks =
+                    value.parse().ok().filter(|blocks| *blocks > 0).ok_or_else(|| CliError::InvalidBlockCount(value.clone()))?
+            }
+            "--target" => options.target = parse_target(value)?,
+            "--weight-limit" => {
+                options.weight_limit = value
+                    .parse()
+                    .ok()
+                    .filter(|limit| (1..=MAX_BLOCK_WEIGHT).contains(limit))
+                    .ok_or_else(|| CliError::InvalidWeightLimit(value.clone()))?
+            }
+            "--height" => options.height = parse_number(arg, value)?,
+            "--median-time-past" => options.median_time_past = Some(parse_number(arg, value)?),
+            "--disable-policy" => {
+                for rule in value.split(',') {
+                    if !options.policy.disable(rule) {
+                        return Err(CliError::UnknownPolicyRule(rule.to_string()));
+                    }
+                }
+            }
+            _ => options.network = Network::from_name(value).ok_or_else(|| CliError::UnknownNetwork(value.clone()))?,
+        }
+    }
+
+    if options.command == Command::InspectTx && options.argument.is_none() {
+        return Err(CliError::MissingArgument("RAW TRANSACTION HEX"));
+    }
+    if options.blocks > 1 && options.chain.is_none() {
+        return Err(CliError::MissingArgument("--chain TO MINE SEVERAL BLOCKS ON"));
+    }
+    if options.command == Command::DisconnectBlock {
+        if options.argument.is_none() {
+            return Err(CliError::MissingArgument("UNDO FILE"));
+        }
+        if options.utxo_set.is_none() {
+            return Err(CliError::MissingArgument("--utxo-set TO DISCONNECT THE BLOCK FROM"));
+        }
+    }
+    Ok(options)
+}
+
+fn parse_number(flag: &str, value: &str) -> Result<u32, CliError> {
+    value.parse().map_err(|_| CliError::InvalidNumber { flag: flag.to_string(), value: value.to_string() })
+}
+
+fn parse_target(target_hex: &str) -> Result<[u8; 32], CliError> {
+    hex::decode(target_hex)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .filter(|target| *target != [0u8; 32])
+        .ok_or_else(|| CliError::InvalidTarget(target_hex.to_string()))
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Options, CliError> {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        parse_args(&args)
+    }
+
+    #[test]
+    fn test_defaults_and_flags() {
+        // NO ARGUMENTS KEEPS THE ORIGINAL VALIDATE THEN MINE BEHAVIOUR
+        assert_eq!(parse(""), Ok(Options::default()));
+        assert_eq!(parse("--optimise").unwrap().mode, FillMode::Optimised);
+
+        let options = parse(
+            "mine --mempool /data/snapshot --valid-mempool /tmp/valid --output /tmp/block.txt \
+             --rejection-report /tmp/rejected.json \
+             --target 00ffff0000000000000000000000000000000000000000000000000000000000 \
+             --weight-limit 2000000 --network regtest --height 150 --median-time-past 1700000000",
+        )
+        .unwrap();
+        assert_eq!(options.command, Command::Mine);
+        assert_eq!(options.mempool, PathBuf::from("/data/snapshot"));
+        assert_eq!(options.valid_mempool, Some(PathBuf::from("/tmp/valid")));
+        assert_eq!(options.output, PathBuf::from("/tmp/block.txt"));
+        assert_eq!(options.rejection_report, Some(PathBuf::from("/tmp/rejected.json")));
+        assert_eq!(options.target[..3], [0x00, 0xff, 0xff]);
+        assert_eq!(options.weight_limit, 2_000_000);
+        assert_eq!(options.network, Network::Regtest);
+        assert_eq!(options.height, 150);
+        assert_eq!(options.median_time_past, Some(1_700_000_000));
+
+        let options = parse("verify-block /tmp/block.txt --network testnet").unwrap();
+        assert_eq!(options.command, Command::VerifyBlock);
+        assert_eq!(options.argument, Some("/tmp/block.txt".to_string()));
+        assert_eq!(parse("verify-block").unwrap().argument, None);
+
+        assert_eq!(parse("template --help").unwrap().command, Command::Help);
+
+        // POLICY RULES ARE SWITCHED OFF ONE LIST AT A TIME, ALL LEAVES ONLY CONSENSUS
+        let policy = parse("validate --disable-policy dust,low-s --disable-policy min-fee").unwrap().policy;
+        assert_eq!((policy.dust_relay_feerate, policy.min_fee), (None, None));
+        assert!(!policy.script_flags.low_s && policy.script_flags.clean_stack && policy.standard_outputs);
+        assert_eq!(parse("--disable-policy all").unwrap().policy, Policy::consensus());
+
+        // THE UNDO FILE IS THE ARGUMENT, THE SET IT ROLLS BACK IS THE FLAG
+        let options = parse("disconnect-block /tmp/utxos-undo-150.json --utxo-set /tmp/utxos.json").unwrap();
+        assert_eq!(options.command, Command::DisconnectBlock);
+        assert_eq!(options.argument, Some("/tmp/utxos-undo-150.json".to_string()));
+        assert_eq!(options.utxo_set, Some(PathBuf::from("/tmp/utxos.json")));
+
+        let options = parse("mine --chain /tmp/chain.json --blocks 6").unwrap();
+        assert_eq!((options.chain, options.blocks), (Some(PathBuf::from("/tmp/chain.json")), 6));
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        assert_eq!(parse("mint"), Err(CliError::UnknownCommand("mint".to_string())));
+        assert_eq!(parse("mine --fast"), Err(CliError::UnknownFlag("--fast".to_string())));
+        assert_eq!(parse("mine --output"), Err(CliError::MissingValue("--output".to_string())));
+        assert_eq!(parse("mine extra"), Err(CliError::UnexpectedArgument("extra".to_string())));
+        assert_eq!(parse("verify-block a b"), Err(CliError::UnexpectedArgument("b".to_string())));
+        assert_eq!(parse("inspect-tx"), Err(CliError::MissingArgument("RAW TRANSACTION HEX")));
+        assert_eq!(parse("disconnect-block --utxo-set /tmp/utxos.json"), Err(CliError::MissingArgument("UNDO FILE")));
+        assert_eq!(
+            parse("disconnect-block /tmp/undo.json"),
+            Err(CliError::MissingArgument("--utxo-set TO DISCONNECT THE BLOCK FROM"))
+        );
+        assert_eq!(parse("--network litecoin"), Err(CliError::UnknownNetwork("litecoin".to_string())));
+
+        assert_eq!(parse("--target ffff"), Err(CliError::InvalidTarget("ffff".to_string())));
+        assert_eq!(parse(&format!("--target {}", "00".repeat(32))), Err(CliError::InvalidTarget("00".repeat(32))));
+
+        assert_eq!(parse("--weight-limit 0"), Err(CliError::InvalidWeightLimit("0".to_string())));
+        assert_eq!(parse("--weight-limit 4000001"), Err(CliError::InvalidWeightLimit("4000001".to_string())));
+        assert_eq!(
+            parse("--height -1"),
+            Err(CliError::InvalidNumber { flag: "--height".to_string(), value: "-1".to_string() })
+        );
+        assert_eq!(parse("--weight-limit lots"), Err(CliError::InvalidWeightLimit("lots".to_string())));
+        assert_eq!(parse("mine --blocks 3"), Err(CliError::MissingArgument("--chain TO MINE SEVERAL BLOCKS ON")));
+        assert_eq!(parse("--chain /tmp/chain.json --blocks 0"), Err(CliError::InvalidBlockCount("0".to_string())));
+        assert_eq!(parse("--disable-policy dust,rbf"), Err(CliError::UnknownPolicyRule("rbf".to_string())));
+    }
+}
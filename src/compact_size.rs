@@ -0,0 +1,124 @@
+// BITCOIN COMPACTSIZE (VARINT) ENCODING OF COUNTS AND LENGTHS
+//
+//   0x00..=0xfc          1 BYTE
+//   0xfd + u16           3 BYTES
+//   0xfe + u32           5 BYTES
+//   0xff + u64           9 BYTES
+use std::fmt;
+
+// NOTHING IN A TRANSACTION OR BLOCK CAN BE LONGER THAN THIS, SAME BOUND AS BITCOIN CORE
+pub const MAX_SIZE: u64 = 0x0200_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompactSizeError {
+    UnexpectedEnd,
+    NonCanonical,
+    TooLarge,
+}
+
+impl fmt::Display for CompactSizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompactSizeError::UnexpectedEnd => write!(f, "COMPACT SIZE: UNEXPECTED END OF DATA"),
+            CompactSizeError::NonCanonical => write!(f, "COMPACT SIZE: NON-CANONICAL ENCODING"),
+            CompactSizeError::TooLarge => write!(f, "COMPACT SIZE: VALUE EXCEEDS MAX_SIZE"),
+        }
+    }
+}
+
+impl std::error::Error for CompactSizeError {}
+
+pub fn encode(n: usize) -> Vec<u8> {
+    let n = n as u64;
+    match n {
+        0..=0xfc => vec![n as u8],
+        0xfd..=0xffff => {
+            let mut bytes = vec![0xfd];
+            bytes.extend((n as u16).to_le_bytes());
+            bytes
+        }
+        0x1_0000..=0xffff_ffff => {
+            let mut bytes = vec![0xfe];
+            bytes.extend((n as u32).to_le_bytes());
+            bytes
+        }
+        _ => {
+            let mut bytes = vec![0xff];
+            bytes.extend(n.to_le_bytes());
+            bytes
+        }
+    }
+}
+
+// NUMBER OF BYTES `encode(n)` WRITES, USED FOR WEIGHT ACCOUNTING
+pub fn encoded_len(n: usize) -> usize {
+    match n as u64 {
+        0..=0xfc => 1,
+        0xfd..=0xffff => 3,
+        0x1_0000..=0xffff_ffff => 5,
+        _ => 9,
+    }
+}
+
+// READS A COMPACT SIZE FROM THE FRONT OF `bytes`, RETURNS THE VALUE AND THE BYTES CONSUMED
+pub fn decode(bytes: &[u8]) -> Result<(usize, usize), CompactSizeError> {
+    let (value, consumed, minimum) = match bytes.first() {
+        None => return Err(CompactSizeError::UnexpectedEnd),
+        Some(0xfd) => (read_le(bytes, 2)?, 3, 0xfd),
+        Some(0xfe) => (read_le(bytes, 4)?, 5, 0x1_0000),
+        Some(0xff) => (read_le(bytes, 8)?, 9, 0x1_0000_0000),
+        Some(byte) => (*byte as u64, 1, 0),
+    };
+
+    // EVERY VALUE HAS EXACTLY ONE VALID ENCODING, THE SHORTEST ONE
+    if value < minimum {
+        return Err(CompactSizeError::NonCanonical);
+    }
+    if value > MAX_SIZE {
+        return Err(CompactSizeError::TooLarge);
+    }
+    Ok((value as usize, consumed))
+}
+
+fn read_le(bytes: &[u8], width: usize) -> Result<u64, CompactSizeError> {
+    let data = bytes.get(1..1 + width).ok_or(CompactSizeError::UnexpectedEnd)?;
+    Ok(data.iter().rev().fold(0u64, |acc, byte| (acc << 8) | *byte as u64))
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_at_boundaries() {
+        for (n, expected) in [
+            (0, "00"),
+            (0xfc, "fc"),
+            (0xfd, "fdfd00"),
+            (0xffff, "fdffff"),
+            (0x1_0000, "fe00000100"),
+            (MAX_SIZE as usize, "fe00000002"),
+        ] {
+            let encoded = encode(n);
+            assert_eq!(hex::encode(&encoded), expected);
+            assert_eq!(encoded_len(n), encoded.len());
+            assert_eq!(decode(&encoded), Ok((n, encoded.len())));
+        }
+
+        assert_eq!(hex::encode(encode(0x1_0000_0000)), "ff0000000001000000");
+        assert_eq!(encoded_len(0x1_0000_0000), 9);
+    }
+
+    #[test]
+    fn test_decode_rejects_bad_encodings() {
+        assert_eq!(decode(&[]), Err(CompactSizeError::UnexpectedEnd));
+        assert_eq!(decode(&[0xfd, 0x01]), Err(CompactSizeError::UnexpectedEnd));
+        assert_eq!(decode(&[0xfd, 0xfc, 0x00]), Err(CompactSizeError::NonCanonical));
+        assert_eq!(decode(&[0xfe, 0xff, 0xff, 0x00, 0x00]), Err(CompactSizeError::NonCanonical));
+        assert_eq!(decode(&[0xfe, 0x01, 0x00, 0x00, 0x02]), Err(CompactSizeError::TooLarge));
+
+        // TRAILING BYTES ARE LEFT FOR THE CALLER
+        assert_eq!(decode(&[0x02, 0xaa, 0xbb]), Ok((2, 1)));
+    }
+}
@@ -0,0 +1,16 @@
+// MEMPOOL TRANSACTIONS THE TESTS BUILD ON, READ FROM ./mempool BY THE NAME OF THEIR FILE
+use std::fs;
+
+use crate::block_mine::serialise_tx::{tx_entry, TxEntry}; // Importing the mempool entry type
+use crate::error::Result; // Importing Result type from the crate
+use crate::transaction::Transaction; // Importing the Transaction struct
+
+// The transaction in ./mempool/<txid_hash>.json
+pub fn load(txid_hash: &str) -> Result<Transaction> {
+    Ok(serde_json::from_str(&fs::read_to_string(format!("./mempool/{}.json", txid_hash))?)?)
+}
+
+// The same transaction as the entry validation hands to block assembly
+pub fn entry(txid_hash: &str) -> Result<TxEntry> {
+    tx_entry(load(txid_hash)?)
+}
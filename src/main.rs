@@ -1,35 +1,332 @@
+// Importing module for decoding addresses
+mod address;
+
+// Importing module for parsing the command line
+mod cli;
+
 // Importing module for mining blocks
 mod block_mine;
 
+// Importing module for CompactSize encoding
+mod compact_size;
+
 // Importing module for handling errors
 mod error;
 
+// Importing module for the mempool transactions the tests use
+#[cfg(test)]
+mod fixtures;
+
 // Importing module for transaction processing
 mod transaction;
 
+// Importing module for the UTXO set
+mod utxo;
+
 // Importing module for validation checks
 mod validation_checks;
 
+// Importing HashSet for the transactions a block confirmed
+use std::collections::HashSet;
+
+// Importing the file system module for the mempool lookup
+use std::fs;
+
+// Importing Path for the block file and mempool locations
+use std::path::{Path, PathBuf};
+
+// Importing the clock for the default median time past
+use std::time::{SystemTime, UNIX_EPOCH};
+
 // Importing Result type from the error module
 use crate::error::Result;
 
-// Importing the transaction verification function from validation_checks module
-use crate::validation_checks::all_transaction_verification;
+// Importing the command line parser
+use crate::cli::{parse_args, Command, Options, USAGE};
+
+// Importing the transaction verification function and its in-memory result from validation_checks module
+use crate::validation_checks::{all_transaction_verification, ValidMempool};
+
+// Importing the height and median time past finality is checked against
+use crate::validation_checks::timelock::ChainTip;
+
+// Importing the block building, mining and writing from block_mine module
+use crate::block_mine::block::{block_template, target_to_compact, valid_block_header, BlockConfig, BlockTemplate};
+
+// Importing the local header chain blocks are mined on top of
+use crate::block_mine::chain::Chain;
+
+// Importing the chainwork formatting
+use crate::block_mine::difficulty::work_hex;
+
+// Importing the bits to target conversion for retargeted blocks
+use crate::block_mine::pow::compact_to_target;
+
+// Importing the raw transaction decoder
+use crate::block_mine::deserialise_tx::{deserialise_tx, input_count};
+
+// Importing the serialisation used for txids, weight and fees
+use crate::block_mine::serialise_tx::{tx_entry, TxEntry};
+
+// Importing the checks for an already mined block
+use crate::block_mine::verify_block::verify_block;
 
-// Importing the function for validating block headers from block_mine module
-use crate::block_mine::block::valid_block_header;
+// Importing the transaction structs
+use crate::transaction::{Prevout, Transaction};
+
+// Importing the UTXO set and the undo data of a connected block
+use crate::utxo::{BlockUndo, UtxoSet};
 
 fn main() -> Result<()> {
-    
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // Printing the usage next to the problem instead of a debug dump of the error
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let config = BlockConfig {
+        target: options.target,
+        weight_limit: options.weight_limit,
+        mode: options.mode,
+        network: options.network,
+        tip: ChainTip { height: options.height, median_time_past: median_time_past(&options)? },
+        prev_block_hash: [0; 32],
+    };
+
+    // Reading the UTXO set once, every command that validates checks the prevouts against it
+    let utxos = match options.utxo_set.as_deref() {
+        Some(path) if options.command != Command::DisconnectBlock => Some(UtxoSet::read(path)?),
+        _ => None,
+    };
+
+    match options.command {
+        Command::Help => println!("{}", USAGE),
+        Command::Validate => println!("{}", validate(&options, utxos.as_ref())?.rejections),
+        Command::Template => {
+            let template = block_template(valid_set(&options, utxos.as_ref())?.entries, &config, utxos.as_ref())?;
+            print_selection(&template);
+            template.write(&options.output)?;
+        }
+        Command::Mine => {
+            let entries = valid_set(&options, utxos.as_ref())?.entries;
+            mine_blocks(&options, utxos, entries, config)?;
+        }
+        Command::VerifyBlock => check_block(&options)?,
+        Command::InspectTx => inspect_tx(options.argument.as_deref().unwrap_or_default(), &options.mempool)?,
+        Command::DisconnectBlock => disconnect_block(&options)?,
+        Command::Run => {
+            let valid_mempool = validate(&options, utxos.as_ref())?;
+
+            // Performing block mining with valid block headers
+            mine_blocks(&options, utxos, valid_mempool.entries, config)?;
+        }
+    }
+
+    // Returning Ok if all operations completed successfully
+    Ok(())
+}
+
+fn validate(options: &Options, utxos: Option<&UtxoSet>) -> Result<ValidMempool> {
     // Performing transaction verification
-    all_transaction_verification()?;
-    
+    let valid_mempool = all_transaction_verification(&options.mempool, &options.policy, utxos, next_height(options)?)?;
+
     // Printing confirmation message for transaction verification
-    println!("TRANSACTION VERIFICATION: COMPLETED");
+    println!(
+        "TRANSACTION VERIFICATION: COMPLETED, {} VALID, {} REJECTED",
+        valid_mempool.entries.len(),
+        valid_mempool.rejections.rejected.len()
+    );
 
-    // Performing block mining with valid block headers
-    valid_block_header()?;
-    
-    // Returning Ok if all operations completed successfully
+    // Keeping the reasons for an audit when asked for
+    if let Some(path) = options.rejection_report.as_deref() {
+        fs::write(path, valid_mempool.rejections.to_json()?)?;
+    }
+
+    // Keeping a copy on disk only when asked for
+    if let Some(dir) = options.valid_mempool.as_deref() {
+        valid_mempool.write(dir, &options.mempool)?;
+    }
+    Ok(valid_mempool)
+}
+
+// Height of the next block, on top of the chain when there is one. Coinbase maturity is checked against it
+fn next_height(options: &Options) -> Result<u32> {
+    match options.chain.as_deref() {
+        Some(path) if path.exists() => Ok(Chain::read(path)?.next_tip()?.height),
+        _ => Ok(options.height),
+    }
+}
+
+// Taking the median time past from the command line, or an hour before now which is about where it trails the clock
+fn median_time_past(options: &Options) -> Result<u32> {
+    match options.median_time_past {
+        Some(median_time_past) => Ok(median_time_past),
+        None => Ok((SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as u32).saturating_sub(3600)),
+    }
+}
+
+// Reading the valid set a previous validate run wrote, or validating the mempool now
+fn valid_set(options: &Options, utxos: Option<&UtxoSet>) -> Result<ValidMempool> {
+    match options.valid_mempool.as_deref() {
+        Some(dir) => ValidMempool::read(dir),
+        None => validate(options, utxos),
+    }
+}
+
+// Mining a single block on top of nothing, or with a chain the next blocks on top of its tip
+fn mine_blocks(options: &Options, mut utxos: Option<UtxoSet>, mut entries: Vec<TxEntry>, mut config: BlockConfig) -> Result<()> {
+    let Some(chain_path) = options.chain.as_deref() else {
+        let template = valid_block_header(entries, &config, utxos.as_ref(), &options.output)?;
+        print_selection(&template);
+        return connect_block(options, utxos.as_mut(), &template, &config);
+    };
+
+    let mut chain = match chain_path.exists() {
+        true => Chain::read(chain_path)?,
+        false => Chain::new(&config.tip, target_to_compact(&hex::encode(config.target))),
+    };
+
+    // Leaving out what earlier runs already confirmed
+    let confirmed = chain.confirmed_txids();
+    entries.retain(|entry| !confirmed.contains(entry.0.as_str()));
+
+    for _ in 0..options.blocks {
+        config.tip = chain.next_tip()?;
+        config.prev_block_hash = chain.tip_hash()?;
+        config.target = compact_to_target(chain.next_bits()?)?; // Retargeted every 2016 blocks
+        let output = block_path(&options.output, config.tip.height);
+        let template = valid_block_header(entries.clone(), &config, utxos.as_ref(), &output)?;
+        print_selection(&template);
+        connect_block(options, utxos.as_mut(), &template, &config)?;
+
+        // Taking what the block confirmed out of the mempool, the coinbase comes first
+        let txids = template.txids[1..].to_vec();
+        let mined: HashSet<&str> = txids.iter().map(|txid| txid.as_str()).collect();
+        entries.retain(|entry| !mined.contains(entry.0.as_str()));
+
+        chain.push(&template.header, txids)?;
+        chain.write(chain_path)?;
+        println!(
+            "CHAIN: BLOCK {} WRITTEN TO {}, CHAINWORK {}, {} TRANSACTIONS LEFT IN THE MEMPOOL",
+            config.tip.height,
+            output.display(),
+            work_hex(&chain.chainwork()?),
+            entries.len()
+        );
+    }
     Ok(())
 }
+
+// Printing the valid transactions the template left out because their locks have not expired, and how
+// close the block gets to the best possible fees
+fn print_selection(template: &BlockTemplate) {
+    for (txid, rejection) in template.held_out.iter() {
+        println!("HELD OUT {}: {}", txid, rejection);
+    }
+    println!("HELD OUT: {} NON-FINAL TRANSACTIONS", template.held_out.len());
+    println!("{}", template.report);
+}
+
+// Block at `height` of a chain goes next to --output, e.g. output-900000.txt
+fn block_path(output: &Path, height: u32) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    match output.extension() {
+        Some(extension) => output.with_file_name(format!("{}-{}.{}", stem, height, extension.to_string_lossy())),
+        None => output.with_file_name(format!("{}-{}", stem, height)),
+    }
+}
+
+// Connecting the mined block to the UTXO set and keeping the undo data next to it
+fn connect_block(options: &Options, utxos: Option<&mut UtxoSet>, template: &BlockTemplate, config: &BlockConfig) -> Result<()> {
+    let (Some(utxos), Some(path)) = (utxos, options.utxo_set.as_deref()) else {
+        return Ok(());
+    };
+    let undo = utxos.connect_block(&template.transactions()?, config.tip.height, config.tip.median_time_past)?;
+
+    let undo_path = undo_path(path, undo.height);
+    undo.write(&undo_path)?;
+    utxos.write(path)?;
+    println!(
+        "UTXO SET: CONNECTED BLOCK {}, {} SPENT, {} CREATED, {} COINS, UNDO DATA IN {}",
+        undo.height,
+        undo.spent.len(),
+        undo.created.len(),
+        utxos.len(),
+        undo_path.display()
+    );
+    Ok(())
+}
+
+// Rolling the UTXO set back to before the block the undo data belongs to
+fn disconnect_block(options: &Options) -> Result<()> {
+    let path = options.utxo_set.as_deref().expect("THE PARSER REQUIRES --utxo-set");
+    let undo = BlockUndo::read(Path::new(options.argument.as_deref().unwrap_or_default()))?;
+
+    let mut utxos = UtxoSet::read(path)?;
+    utxos.disconnect_block(&undo)?;
+    utxos.write(path)?;
+    println!("UTXO SET: DISCONNECTED BLOCK {}, {} COINS", undo.height, utxos.len());
+    Ok(())
+}
+
+// Undo data of the block at `height` goes next to the set, e.g. utxos-undo-900000.json
+fn undo_path(utxo_set: &Path, height: u32) -> PathBuf {
+    let stem = utxo_set.file_stem().unwrap_or_default().to_string_lossy();
+    utxo_set.with_file_name(format!("{}-undo-{}.json", stem, height))
+}
+
+// Checking an existing block file instead of mining a new block
+fn check_block(options: &Options) -> Result<()> {
+    let block_path = options.argument.as_deref().map(Path::new).unwrap_or(&options.output);
+    let violations = verify_block(block_path, &options.mempool, options.network)?;
+
+    for violation in violations.iter() {
+        println!("{}", violation);
+    }
+    if !violations.is_empty() {
+        return Err(failure::err_msg(format!("BLOCK INVALID: {} VIOLATIONS", violations.len())));
+    }
+    println!("BLOCK VALID");
+    Ok(())
+}
+
+// Decoding a raw transaction, the spent outputs come from the mempool transaction with the same inputs
+fn inspect_tx(raw_tx_hex: &str, mempool_dir: &Path) -> Result<()> {
+    let raw_tx = hex::decode(raw_tx_hex.trim())?;
+    let decoded = deserialise_tx(raw_tx_hex, &vec![Prevout::default(); input_count(&raw_tx)?])?;
+
+    let prevouts = mempool_prevouts(&decoded, mempool_dir)?;
+    let transaction = match prevouts.as_ref() {
+        Some(prevouts) => deserialise_tx(raw_tx_hex, prevouts)?,
+        None => decoded,
+    };
+    let (txid, transaction, wtxid, weight, fees) = tx_entry(transaction)?;
+
+    println!("TXID: {}", txid);
+    println!("WTXID: {}", wtxid);
+    println!("WEIGHT: {}", weight);
+    match prevouts {
+        Some(_) => println!("FEES: {}", fees),
+        None => println!("FEES: UNKNOWN, NO MEMPOOL TRANSACTION SPENDS THE SAME OUTPUTS"),
+    }
+    println!("{}", serde_json::to_string_pretty(&transaction)?);
+    Ok(())
+}
+
+fn mempool_prevouts(transaction: &Transaction, mempool_dir: &Path) -> Result<Option<Vec<Prevout>>> {
+    let outpoints: Vec<(&str, u32)> = transaction.vin.iter().map(|input| (input.txid.as_str(), input.vout)).collect();
+
+    for entry in fs::read_dir(mempool_dir)? {
+        let Ok(candidate) = serde_json::from_str::<Transaction>(&fs::read_to_string(entry?.path())?) else {
+            continue;
+        };
+        if candidate.vin.iter().map(|input| (input.txid.as_str(), input.vout)).eq(outpoints.iter().copied()) {
+            return Ok(Some(candidate.vin.into_iter().map(|input| input.prevout).collect()));
+        }
+    }
+    Ok(None)
+}
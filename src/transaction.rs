@@ -1,4 +1,6 @@
 
+use std::fmt;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -9,14 +11,6 @@ pub struct Transaction {
     pub vout: Vec<Output>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Transaction2 {
-    pub version2: i32,
-    pub locktime2: u32,
-    pub vin2: Vec<Input>,
-    pub vout2: Vec<Output>,
-}
-
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Input {
     pub txid: String,
@@ -30,18 +24,26 @@ pub struct Input {
     pub inner_redeemscript_asm: Option<String>, 
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Input2 {
-    pub txid2: String,
-    pub vout2: u32,
-    pub prevout2: Prevout, 
-    pub witness2: Option<Vec<String>>,
-    pub is_coinbase2: bool,
-    pub sequence2: u32,
-    pub inner_redeemscript_asm2: Option<String>, 
+impl Input {
+    pub fn outpoint(&self) -> OutPoint {
+        OutPoint { txid: self.txid.clone(), vout: self.vout }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// THE OUTPUT AN INPUT SPENDS, TXID IN DISPLAY ORDER
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OutPoint {
+    pub txid: String,
+    pub vout: u32,
+}
+
+impl fmt::Display for OutPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.txid, self.vout)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct Prevout {
     pub scriptpubkey: String,
     pub scriptpubkey_asm: String,
@@ -50,14 +52,6 @@ pub struct Prevout {
     pub value: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Prevout2 {
-    pub scriptpubkey2: String,
-    pub scriptpubkey_type2: String,
-    pub scriptpubkey_address2: String,
-    pub value2: u64,
-}
-
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Output {
     pub scriptpubkey: String,
@@ -66,11 +60,3 @@ pub struct Output {
     pub scriptpubkey_address: Option<String>,
     pub value: u64,
 }
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Output2 {
-    pub scriptpubkey2: String,
-    pub scriptpubkey_asm2: String,
-    pub scriptpubkey_type2: String,
-    pub value2: u64,
-}
\ No newline at end of file
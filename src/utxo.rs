@@ -0,0 +1,402 @@
+// UTXO SET: EVERY UNSPENT OUTPUT, KEYED BY OUTPOINT
+//
+// SEEDED FROM A JSON SNAPSHOT, THEN UPDATED BLOCK BY BLOCK. CONNECTING A BLOCK SPENDS ITS INPUTS AND
+// ADDS ITS OUTPUTS (COINBASE INCLUDED, OP_RETURN LEFT OUT SINCE IT CAN NEVER BE SPENT), AND RETURNS THE
+// UNDO DATA THAT PUTS THE SET BACK THE WAY IT WAS.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::block_mine::serialise_tx::TxEntry; // Importing the validated transaction entries
+use crate::error::Result; // Importing Result type from the crate
+use crate::transaction::{OutPoint, Output, Prevout, Transaction}; // Importing the transaction structs
+use crate::validation_checks::rejection::Rejection; // Importing the rejection reasons
+use crate::validation_checks::timelock::CoinAge; // Importing the confirmation age used by BIP68
+
+// A COINBASE OUTPUT CAN ONLY BE SPENT IN A BLOCK THIS MANY BLOCKS ABOVE THE ONE THAT CREATED IT
+pub const COINBASE_MATURITY: u32 = 100;
+
+// AN UNSPENT OUTPUT AND THE BLOCK THAT CREATED IT
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Coin {
+    #[serde(flatten)]
+    pub prevout: Prevout,
+    pub height: u32,
+    #[serde(default)]
+    pub median_time_past: u32, // Of the block before the one that created it, for BIP68
+    #[serde(default)]
+    pub is_coinbase: bool,
+}
+
+impl Coin {
+    // When it was confirmed, for the relative locks of whatever spends it
+    pub fn age(&self) -> CoinAge {
+        CoinAge { height: self.height, median_time_past: self.median_time_past }
+    }
+}
+
+// ONE LINE OF A SNAPSHOT OR OF UNDO DATA
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotCoin {
+    #[serde(flatten)]
+    pub outpoint: OutPoint,
+    #[serde(flatten)]
+    pub coin: Coin,
+}
+
+// WHAT CONNECTING A BLOCK CHANGED, IN THE ORDER IT HAPPENED
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockUndo {
+    pub height: u32,
+    pub spent: Vec<SnapshotCoin>,
+    pub created: Vec<OutPoint>,
+}
+
+impl BlockUndo {
+    pub fn read(path: &Path) -> Result<BlockUndo> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UtxoError {
+    MissingCoin { txid: String, outpoint: OutPoint },
+    CoinExists(OutPoint),
+    UndoMismatch(OutPoint),
+}
+
+impl fmt::Display for UtxoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UtxoError::MissingCoin { txid, outpoint } => {
+                write!(f, "UTXO: TX {} SPENDS {} WHICH IS NOT IN THE SET", txid, outpoint)
+            }
+            UtxoError::CoinExists(outpoint) => write!(f, "UTXO: {} IS ALREADY IN THE SET", outpoint),
+            UtxoError::UndoMismatch(outpoint) => {
+                write!(f, "UTXO: UNDO DATA REMOVES {} BUT THE SET DOES NOT HAVE IT", outpoint)
+            }
+        }
+    }
+}
+
+impl std::error::Error for UtxoError {}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UtxoSet {
+    coins: HashMap<OutPoint, Coin>,
+}
+
+impl UtxoSet {
+    // Reads a snapshot, a JSON list of coins each with its txid and vout
+    pub fn read(path: &Path) -> Result<UtxoSet> {
+        let snapshot: Vec<SnapshotCoin> = serde_json::from_str(&fs::read_to_string(path)?)?;
+        Ok(snapshot.into_iter().collect())
+    }
+
+    // Writes the set as a snapshot `read` takes back, sorted so the same set gives the same file
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut snapshot: Vec<SnapshotCoin> = self
+            .coins
+            .iter()
+            .map(|(outpoint, coin)| SnapshotCoin { outpoint: outpoint.clone(), coin: coin.clone() })
+            .collect();
+        snapshot.sort_by(|a, b| a.outpoint.cmp(&b.outpoint));
+        fs::write(path, serde_json::to_string_pretty(&snapshot)?)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.coins.len()
+    }
+
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&Coin> {
+        self.coins.get(outpoint)
+    }
+
+    // Every input has to spend a coin in the set, or an output of `unconfirmed`, and describe it as it is.
+    // `height` is the block the transaction would go into, a coinbase output has to be mature by then.
+    fn check_inputs(&self, tx: &Transaction, unconfirmed: &HashMap<OutPoint, Output>, height: u32) -> std::result::Result<(), Rejection> {
+        for (input_index, input) in tx.vin.iter().enumerate() {
+            if input.is_coinbase {
+                continue;
+            }
+            let outpoint = input.outpoint();
+            let (value, scriptpubkey) = match (self.coins.get(&outpoint), unconfirmed.get(&outpoint)) {
+                (Some(coin), _) if coin.is_coinbase && height.saturating_sub(coin.height) < COINBASE_MATURITY => {
+                    let depth = height.saturating_sub(coin.height);
+                    return Err(Rejection::PrematureCoinbaseSpend { input: input_index, outpoint: outpoint.to_string(), depth });
+                }
+                (Some(coin), _) => (coin.prevout.value, coin.prevout.scriptpubkey.as_str()),
+                (None, Some(output)) => (output.value, output.scriptpubkey.as_str()),
+                (None, None) => {
+                    return Err(Rejection::MissingInput { input: input_index, outpoint: outpoint.to_string() });
+                }
+            };
+            if input.prevout.value != value || input.prevout.scriptpubkey != scriptpubkey {
+                return Err(Rejection::PrevoutMismatch { input: input_index, outpoint: outpoint.to_string() });
+            }
+        }
+        Ok(())
+    }
+
+    // Keeps the mempool transactions whose inputs all exist, in the set or as outputs of the others kept.
+    // Dropping one can take away the inputs of its children, so it goes round until nothing changes.
+    pub fn check_mempool(&self, mut entries: Vec<TxEntry>, height: u32) -> (Vec<TxEntry>, Vec<(String, Rejection)>) {
+        let mut rejections: Vec<(String, Rejection)> = Vec::new();
+        loop {
+            let unconfirmed = unconfirmed_outputs(&entries);
+            let before = rejections.len();
+            entries.retain(|(txid, transaction, _, _, _)| match self.check_inputs(transaction, &unconfirmed, height) {
+                Ok(()) => true,
+                Err(rejection) => {
+                    rejections.push((txid.clone(), rejection));
+                    false
+                }
+            });
+            if rejections.len() == before {
+                return (entries, rejections);
+            }
+        }
+    }
+
+    // Connects a block, coinbase first, on top of a tip with the given median time past. Nothing
+    // changes when an input is missing.
+    pub fn connect_block(&mut self, block: &[TxEntry], height: u32, median_time_past: u32) -> Result<BlockUndo> {
+        let mut undo = BlockUndo { height, ..BlockUndo::default() };
+
+        for (txid, transaction, _, _, _) in block.iter() {
+            let is_coinbase = transaction.vin.iter().any(|input| input.is_coinbase);
+            if !is_coinbase {
+                for input in transaction.vin.iter() {
+                    let outpoint = input.outpoint();
+                    match self.coins.remove(&outpoint) {
+                        Some(coin) => undo.spent.push(SnapshotCoin { outpoint, coin }),
+                        None => {
+                            self.disconnect_block(&undo)?;
+                            return Err(UtxoError::MissingCoin { txid: txid.clone(), outpoint }.into());
+                        }
+                    }
+                }
+            }
+
+            for (vout, output) in transaction.vout.iter().enumerate() {
+                if output.scriptpubkey.starts_with("6a") {
+                    continue; // OP_RETURN
+                }
+                let outpoint = OutPoint { txid: txid.clone(), vout: vout as u32 };
+                if self.coins.contains_key(&outpoint) {
+                    self.disconnect_block(&undo)?;
+                    return Err(UtxoError::CoinExists(outpoint).into());
+                }
+                let coin = Coin { prevout: prevout(output), height, median_time_past, is_coinbase };
+                self.coins.insert(outpoint.clone(), coin);
+                undo.created.push(outpoint);
+            }
+        }
+        Ok(undo)
+    }
+
+    // Rolls back a connected block: the coins it spent come back, its outputs go. Spent coins come back
+    // first since an output can be created and spent in the same block.
+    pub fn disconnect_block(&mut self, undo: &BlockUndo) -> Result<()> {
+        for spent in undo.spent.iter().rev() {
+            self.coins.insert(spent.outpoint.clone(), spent.coin.clone());
+        }
+        for outpoint in undo.created.iter().rev() {
+            if self.coins.remove(outpoint).is_none() {
+                return Err(UtxoError::UndoMismatch(outpoint.clone()).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromIterator<SnapshotCoin> for UtxoSet {
+    fn from_iter<I: IntoIterator<Item = SnapshotCoin>>(snapshot: I) -> UtxoSet {
+        UtxoSet { coins: snapshot.into_iter().map(|entry| (entry.outpoint, entry.coin)).collect() }
+    }
+}
+
+// The outputs of transactions that are not in a block yet
+fn unconfirmed_outputs(entries: &[TxEntry]) -> HashMap<OutPoint, Output> {
+    let mut outputs = HashMap::new();
+    for (txid, transaction, _, _, _) in entries.iter() {
+        for (vout, output) in transaction.vout.iter().enumerate() {
+            outputs.insert(OutPoint { txid: txid.clone(), vout: vout as u32 }, output.clone());
+        }
+    }
+    outputs
+}
+
+fn prevout(output: &Output) -> Prevout {
+    Prevout {
+        scriptpubkey: output.scriptpubkey.clone(),
+        scriptpubkey_asm: output.scriptpubkey_asm.clone(),
+        scriptpubkey_type: output.scriptpubkey_type.clone(),
+        scriptpubkey_address: output.scriptpubkey_address.clone().unwrap_or_default(),
+        value: output.value,
+    }
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block_mine::coinbase::CoinbaseBuilder;
+    use crate::block_mine::serialise_tx::tx_entry;
+    use crate::fixtures::entry;
+
+    const HEIGHT: u32 = 900_000;
+
+    // A SET HOLDING EXACTLY THE COINS THE TRANSACTION CLAIMS TO SPEND
+    fn seeded(transaction: &Transaction) -> UtxoSet {
+        let coins = transaction
+            .vin
+            .iter()
+            .map(|input| (input.outpoint(), Coin { prevout: input.prevout.clone(), height: 899_990, median_time_past: 0, is_coinbase: false }))
+            .collect();
+        UtxoSet { coins }
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() -> Result<()> {
+        let (_, transaction, _, _, _) = entry("0136f8e20b42cf02779feef9f0f2925b5006c9b5d73df15bcbc054e6310cde27")?;
+        let utxos = seeded(&transaction);
+
+        let path = std::env::temp_dir().join(format!("utxo_snapshot_{}.json", std::process::id()));
+        utxos.write(&path)?;
+        let read = UtxoSet::read(&path);
+        fs::remove_file(&path)?;
+        assert_eq!(read?, utxos);
+
+        // THE SNAPSHOT IS A FLAT LIST, THE BLOCK FIELDS CAN BE LEFT OUT
+        let input = &transaction.vin[0];
+        let line = format!(
+            r#"[{{"txid":"{}","vout":{},"scriptpubkey":"{}","scriptpubkey_asm":"","scriptpubkey_type":"v0_p2wsh","scriptpubkey_address":"","value":{},"height":1}}]"#,
+            input.txid, input.vout, input.prevout.scriptpubkey, input.prevout.value
+        );
+        let snapshot: Vec<SnapshotCoin> = serde_json::from_str(&line)?;
+        assert_eq!((snapshot[0].outpoint.clone(), snapshot[0].coin.is_coinbase), (input.outpoint(), false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_inputs() -> Result<()> {
+        let parent_entry = entry("0136f8e20b42cf02779feef9f0f2925b5006c9b5d73df15bcbc054e6310cde27")?;
+        let (parent_txid, parent) = (parent_entry.0.clone(), parent_entry.1.clone());
+        let utxos = seeded(&parent);
+        let none = HashMap::new();
+        assert_eq!(utxos.check_inputs(&parent, &none, HEIGHT), Ok(()));
+
+        // A COIN THE SET HAS NEVER SEEN
+        assert_eq!(
+            UtxoSet::default().check_inputs(&parent, &none, HEIGHT),
+            Err(Rejection::MissingInput { input: 0, outpoint: parent.vin[0].outpoint().to_string() })
+        );
+
+        // CLAIMING MORE THAN THE COIN HOLDS, OR A DIFFERENT SCRIPT
+        let mut inflated = parent.clone();
+        inflated.vin[0].prevout.value += 1;
+        let mismatch = Err(Rejection::PrevoutMismatch { input: 0, outpoint: parent.vin[0].outpoint().to_string() });
+        assert_eq!(utxos.check_inputs(&inflated, &none, HEIGHT), mismatch);
+        let mut rescripted = parent.clone();
+        rescripted.vin[0].prevout.scriptpubkey = parent.vout[0].scriptpubkey.clone();
+        assert_eq!(utxos.check_inputs(&rescripted, &none, HEIGHT), mismatch);
+
+        // A COINBASE OUTPUT 10 BLOCKS DEEP, SPENDABLE ONCE IT IS 100 DEEP
+        let mut young = utxos.clone();
+        young.coins.get_mut(&parent.vin[0].outpoint()).unwrap().is_coinbase = true;
+        assert_eq!(
+            young.check_inputs(&parent, &none, HEIGHT),
+            Err(Rejection::PrematureCoinbaseSpend { input: 0, outpoint: parent.vin[0].outpoint().to_string(), depth: 10 })
+        );
+        assert!(young.check_inputs(&parent, &none, 899_990 + COINBASE_MATURITY - 1).is_err());
+        assert_eq!(young.check_inputs(&parent, &none, 899_990 + COINBASE_MATURITY), Ok(()));
+
+        // A CHILD OF A MEMPOOL TRANSACTION SPENDS AN OUTPUT THAT IS NOT CONFIRMED YET
+        let mut child = parent.clone();
+        child.vin.truncate(1);
+        child.vin[0].txid = parent_txid.clone();
+        child.vin[0].vout = 0;
+        child.vin[0].prevout = prevout(&parent.vout[0]);
+        let unconfirmed = unconfirmed_outputs(std::slice::from_ref(&parent_entry));
+        assert_eq!(utxos.check_inputs(&child, &unconfirmed, HEIGHT), Ok(()));
+        let missing_parent = Rejection::MissingInput { input: 0, outpoint: format!("{}:0", parent_txid) };
+        assert_eq!(utxos.check_inputs(&child, &none, HEIGHT), Err(missing_parent.clone()));
+
+        // IN THE MEMPOOL THE CHILD STAYS WITH ITS PARENT, AND GOES WHEN THE PARENT'S OWN INPUT IS MISSING
+        let child_entry = tx_entry(child)?;
+        let (kept, dropped) = utxos.check_mempool(vec![child_entry.clone(), parent_entry.clone()], HEIGHT);
+        assert_eq!((kept.len(), dropped.len()), (2, 0));
+
+        let (kept, dropped) = UtxoSet::default().check_mempool(vec![child_entry.clone(), parent_entry.clone()], HEIGHT);
+        assert!(kept.is_empty());
+        assert_eq!(
+            dropped,
+            vec![
+                (parent_txid, Rejection::MissingInput { input: 0, outpoint: parent.vin[0].outpoint().to_string() }),
+                (child_entry.0, missing_parent),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_connect_and_disconnect_block() -> Result<()> {
+        let spend = entry("0136f8e20b42cf02779feef9f0f2925b5006c9b5d73df15bcbc054e6310cde27")?;
+        let payout = hex::decode(&spend.1.vout[0].scriptpubkey)?;
+        let coinbase = tx_entry(CoinbaseBuilder::new(900_000).payout_script(payout, 1).witness_root([0; 32]).build(1000)?)?;
+        let before = seeded(&spend.1);
+
+        let mut utxos = before.clone();
+        let undo = utxos.connect_block(&[coinbase.clone(), spend.clone()], 900_000, 1_750_000_000)?;
+        assert_eq!(undo.spent.len(), spend.1.vin.len());
+        assert!(spend.1.vin.iter().all(|input| !utxos.coins.contains_key(&input.outpoint())));
+
+        // THE WITNESS COMMITMENT IS NEVER SPENDABLE, THE REWARD IS
+        let commitment = coinbase.1.vout.iter().position(|output| output.scriptpubkey.starts_with("6a")).unwrap();
+        assert!(!utxos.coins.contains_key(&OutPoint { txid: coinbase.0.clone(), vout: commitment as u32 }));
+        let reward = &utxos.coins[&OutPoint { txid: coinbase.0.clone(), vout: 1 - commitment as u32 }];
+        assert!(reward.is_coinbase && reward.height == 900_000);
+        assert_eq!(utxos.len(), coinbase.1.vout.len() - 1 + spend.1.vout.len());
+
+        // THE UNDO DATA SURVIVES THE DISK AND PUTS THE SET BACK
+        let path = std::env::temp_dir().join(format!("utxo_undo_{}.json", std::process::id()));
+        undo.write(&path)?;
+        let read = BlockUndo::read(&path);
+        fs::remove_file(&path)?;
+        utxos.disconnect_block(&read?)?;
+        assert_eq!(utxos, before);
+
+        // SPENDING THE SAME COIN TWICE FAILS HALFWAY AND LEAVES THE SET AS IT WAS
+        let error = utxos.connect_block(&[coinbase.clone(), spend.clone(), spend.clone()], 900_000, 1_750_000_000).unwrap_err();
+        assert_eq!(
+            error.downcast::<UtxoError>().ok(),
+            Some(UtxoError::MissingCoin { txid: spend.0.clone(), outpoint: spend.1.vin[0].outpoint() })
+        );
+        assert_eq!(utxos, before);
+
+        // AN OUTPUT CREATED AND SPENT IN THE SAME BLOCK IS NEITHER IN THE SET AFTER IT NOR BEFORE
+        let mut child = spend.1.clone();
+        child.vin.truncate(1);
+        child.vin[0].txid = spend.0.clone();
+        child.vin[0].vout = 0;
+        let child = tx_entry(child)?;
+        let undo = utxos.connect_block(&[coinbase.clone(), spend.clone(), child], 900_000, 1_750_000_000)?;
+        assert!(!utxos.coins.contains_key(&OutPoint { txid: spend.0.clone(), vout: 0 }));
+        utxos.disconnect_block(&undo)?;
+        assert_eq!(utxos, before);
+
+        // UNDO DATA OF A BLOCK THAT WAS NEVER CONNECTED
+        assert!(utxos.disconnect_block(&undo).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,290 @@
+// CONFLICTING SPENDS AND BIP125 REPLACE-BY-FEE
+//
+// THE MEMPOOL DIRECTORY HAS NO ARRIVAL ORDER, SO CONFLICTING TRANSACTIONS ARE REPLAYED CHEAPEST
+// FIRST (TIES BY TXID). EACH ONE EITHER SPENDS NOTHING ALREADY SPENT, OR HAS TO REPLACE WHAT IT
+// CONFLICTS WITH UNDER THE BIP125 RULES. WHATEVER LOSES, AND EVERYTHING SPENDING IT, IS RECORDED.
+use std::collections::{BTreeSet, HashMap};
+
+use crate::block_mine::package::mempool_parents; // Importing the in-mempool parent lookup
+use crate::block_mine::serialise_tx::TxEntry; // Importing the mempool entry type
+use crate::transaction::{OutPoint, Transaction}; // Importing the Transaction struct and outpoints
+
+use super::rejection::Rejection; // Importing the rejection reasons
+
+// AN INPUT WITH A SEQUENCE BELOW THIS OPTS ITS TRANSACTION IN TO REPLACEMENT (RULE 1)
+pub const MAX_BIP125_RBF_SEQUENCE: u32 = 0xfffffffd;
+
+// RULE 4: A REPLACEMENT PAYS FOR ITS OWN RELAY ON TOP OF WHAT IT REPLACES, IN SATS PER 1000 VBYTES
+pub const INCREMENTAL_RELAY_FEERATE: u64 = 1000;
+
+// RULE 5: ORIGINALS PLUS THEIR DESCENDANTS ONE REPLACEMENT CAN EVICT
+pub const MAX_REPLACEMENT_CANDIDATES: usize = 100;
+
+pub fn signals_replaceability(tx: &Transaction) -> bool {
+    tx.vin.iter().any(|input| input.sequence <= MAX_BIP125_RBF_SEQUENCE)
+}
+
+// Keeps one side of every conflict, returns the kept entries in their original order and the txid
+// and reason of every entry that was left out
+pub fn resolve_conflicts(entries: Vec<TxEntry>) -> (Vec<TxEntry>, Vec<(String, Rejection)>) {
+    // CONFLICT INDEX: EVERY OUTPOINT AND WHO SPENDS IT. THE CONSENSUS CHECKS ALREADY REJECTED ANY
+    // TRANSACTION SPENDING ONE OUTPOINT TWICE, SO EVERY SPENDER IS LISTED ONCE PER OUTPOINT.
+    let mut spenders: HashMap<OutPoint, Vec<usize>> = HashMap::new();
+    for (index, (_, transaction, _, _, _)) in entries.iter().enumerate() {
+        for input in transaction.vin.iter() {
+            let spent_by = spenders.entry(input.outpoint()).or_default();
+            debug_assert!(!spent_by.contains(&index), "DUPLICATE INPUT IN {}", entries[index].0);
+            spent_by.push(index);
+        }
+    }
+
+    let mut conflicting: BTreeSet<usize> = BTreeSet::new();
+    for spent_by in spenders.values().filter(|spent_by| spent_by.len() > 1) {
+        conflicting.extend(spent_by.iter().copied());
+    }
+    if conflicting.is_empty() {
+        return (entries, Vec::new());
+    }
+
+    let parents = mempool_parents(&entries);
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); entries.len()];
+    for (child, set) in parents.iter().enumerate() {
+        for parent in set {
+            children[*parent].push(child);
+        }
+    }
+
+    // EVERYTHING WITHOUT A CONFLICT IS IN FROM THE START
+    let mut in_pool: Vec<bool> = (0..entries.len()).map(|index| !conflicting.contains(&index)).collect();
+    let mut rejections: Vec<(String, Rejection)> = Vec::new();
+
+    let mut arrivals: Vec<usize> = conflicting.into_iter().collect();
+    arrivals.sort_by(|a, b| entries[*a].4.cmp(&entries[*b].4).then_with(|| entries[*a].0.cmp(&entries[*b].0)));
+
+    for candidate in arrivals {
+        let transaction = &entries[candidate].1;
+        let mut originals: BTreeSet<usize> = BTreeSet::new();
+        for input in transaction.vin.iter() {
+            let spent_by = &spenders[&input.outpoint()];
+            originals.extend(spent_by.iter().copied().filter(|other| *other != candidate && in_pool[*other]));
+        }
+
+        match check_replacement(&entries, candidate, &originals, &parents, &children, &in_pool, &spenders) {
+            Ok(evicted) => {
+                in_pool[candidate] = true;
+                for (evicted, rejection) in evicted {
+                    in_pool[evicted] = false;
+                    rejections.push((entries[evicted].0.clone(), rejection));
+                }
+            }
+            Err(rejection) => rejections.push((entries[candidate].0.clone(), rejection)),
+        }
+    }
+
+    // A TRANSACTION WHOSE PARENT LOST A CONFLICT SPENDS AN OUTPUT THAT WILL NEVER EXIST
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in 0..entries.len() {
+            if !in_pool[index] {
+                continue;
+            }
+            if let Some(parent) = parents[index].iter().find(|parent| !in_pool[**parent]) {
+                in_pool[index] = false;
+                rejections.push((entries[index].0.clone(), Rejection::ParentConflicted { parent: entries[*parent].0.clone() }));
+                changed = true;
+            }
+        }
+    }
+
+    let kept = entries.into_iter().zip(in_pool).filter_map(|(entry, kept)| kept.then_some(entry)).collect();
+    (kept, rejections)
+}
+
+// THE BIP125 RULES FOR `candidate` REPLACING `originals`, RETURNS WHAT IT EVICTS AND WHY
+fn check_replacement(
+    entries: &[TxEntry],
+    candidate: usize,
+    originals: &BTreeSet<usize>,
+    parents: &[BTreeSet<usize>],
+    children: &[Vec<usize>],
+    in_pool: &[bool],
+    spenders: &HashMap<OutPoint, Vec<usize>>,
+) -> Result<Vec<(usize, Rejection)>, Rejection> {
+    let (txid, transaction, _, weight, fees) = &entries[candidate];
+    if originals.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // RULE 1: EVERY ORIGINAL OPTED IN, OTHERWISE THE FIRST SPEND STAYS
+    for original in originals.iter() {
+        if !signals_replaceability(&entries[*original].1) {
+            let outpoint = transaction
+                .vin
+                .iter()
+                .map(|input| input.outpoint())
+                .find(|outpoint| spenders[outpoint].contains(original))
+                .expect("AN ORIGINAL SPENDS ONE OF THE CANDIDATE'S OUTPOINTS");
+            return Err(Rejection::DoubleSpend { outpoint: outpoint.to_string(), spent_by: entries[*original].0.clone() });
+        }
+    }
+
+    // RULE 5: THE ORIGINALS AND ALL THEIR DESCENDANTS STILL IN THE POOL
+    let mut evicted: BTreeSet<usize> = BTreeSet::new();
+    let mut stack: Vec<usize> = originals.iter().copied().collect();
+    while let Some(index) = stack.pop() {
+        if in_pool[index] && evicted.insert(index) {
+            stack.extend(children[index].iter().copied());
+        }
+    }
+    if evicted.len() > MAX_REPLACEMENT_CANDIDATES {
+        return Err(Rejection::TooManyReplacements { count: evicted.len(), maximum: MAX_REPLACEMENT_CANDIDATES });
+    }
+
+    // RULE 2: NO UNCONFIRMED INPUT THE ORIGINALS DID NOT ALREADY HAVE, AND NOTHING IT EVICTS
+    let original_parents: BTreeSet<usize> =
+        originals.iter().flat_map(|original| parents[*original].iter().copied()).collect();
+    for (input_index, input) in transaction.vin.iter().enumerate() {
+        if let Some(parent) = parents[candidate].iter().copied().find(|parent| entries[*parent].0 == input.txid) {
+            if evicted.contains(&parent) || !original_parents.contains(&parent) {
+                return Err(Rejection::ReplacementAddsUnconfirmed { input: input_index });
+            }
+        }
+    }
+
+    // RULE 3: AT LEAST THE FEES OF EVERYTHING EVICTED
+    let replaced_fees: u64 = evicted.iter().map(|index| entries[*index].4).sum();
+    if *fees < replaced_fees {
+        return Err(Rejection::ReplacementFeeTooLow { fee: *fees, replaced_fees });
+    }
+
+    // RULE 4: AND ON TOP OF THAT ITS OWN SIZE AT THE INCREMENTAL RELAY FEERATE
+    let required = INCREMENTAL_RELAY_FEERATE * weight.div_ceil(4) as u64 / 1000;
+    let additional_fee = fees - replaced_fees;
+    if additional_fee < required {
+        return Err(Rejection::ReplacementFeerateTooLow { additional_fee, required });
+    }
+
+    // DESCENDANTS NAME THE EVICTED PARENT THEY CAME IN WITH
+    Ok(evicted
+        .iter()
+        .map(|index| match parents[*index].iter().find(|parent| evicted.contains(parent)) {
+            Some(parent) if !originals.contains(index) => {
+                (*index, Rejection::ParentConflicted { parent: entries[*parent].0.clone() })
+            }
+            _ => (*index, Rejection::ReplacedBy { replacement: txid.clone() }),
+        })
+        .collect())
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::Result;
+    use crate::fixtures::load;
+
+    const FINAL: u32 = 0xffffffff;
+
+    // A FAKE ENTRY SPENDING THE GIVEN OUTPOINTS, ONLY THE FIELDS CONFLICT RESOLUTION LOOKS AT MATTER
+    fn entry(txid: &str, spends: &[(&str, u32)], sequence: u32, weight: usize, fees: u64) -> Result<TxEntry> {
+        let mut transaction = load("fcc4d2ad88b7a040dc98ae29946b794258ae7c8ba1a4300a6fc761d0c9cb6a1f")?;
+        let template = transaction.vin[0].clone();
+        transaction.vin = spends
+            .iter()
+            .map(|(spent_txid, vout)| {
+                let mut input = template.clone();
+                input.txid = spent_txid.to_string();
+                input.vout = *vout;
+                input.sequence = sequence;
+                input
+            })
+            .collect();
+        Ok((txid.to_string(), transaction, txid.to_string(), weight, fees))
+    }
+
+    fn kept(entries: &[TxEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.0.as_str()).collect()
+    }
+
+    #[test]
+    fn test_first_spend_stays_without_signalling() -> Result<()> {
+        let original = entry("original", &[("coin", 0)], FINAL, 400, 2000)?;
+        let rival = entry("rival", &[("coin", 0)], FINAL, 400, 9000)?;
+
+        // THE DIRECTORY ORDER DOES NOT MATTER, THE CHEAPER ONE IS REPLAYED FIRST
+        for entries in [vec![original.clone(), rival.clone()], vec![rival.clone(), original.clone()]] {
+            let (entries, rejections) = resolve_conflicts(entries);
+            assert_eq!(kept(&entries), vec!["original"]);
+            assert_eq!(
+                rejections,
+                vec![(
+                    "rival".to_string(),
+                    Rejection::DoubleSpend { outpoint: "coin:0".to_string(), spent_by: "original".to_string() }
+                )]
+            );
+        }
+
+        // WHAT SPENDS THE LOSER GOES WITH IT
+        let spender = entry("spender", &[("rival", 0)], FINAL, 400, 2000)?;
+        let (entries, rejections) = resolve_conflicts(vec![spender, rival, original]);
+        assert_eq!(kept(&entries), vec!["original"]);
+        assert_eq!(rejections[1], ("spender".to_string(), Rejection::ParentConflicted { parent: "rival".to_string() }));
+
+        // A TXID ENDING IN THE DIGIT OF A VOUT IS A DIFFERENT OUTPOINT, NOT A CONFLICT
+        let (entries, rejections) = resolve_conflicts(vec![
+            entry("x", &[("coin1", 0)], FINAL, 400, 2000)?,
+            entry("y", &[("coin", 10)], FINAL, 400, 2000)?,
+        ]);
+        assert_eq!((entries.len(), rejections.len()), (2, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_by_fee() -> Result<()> {
+        let original = entry("original", &[("coin", 0), ("other", 1)], 0xfffffffd, 400, 2000)?;
+        let child = entry("child", &[("original", 0)], FINAL, 400, 1000)?;
+
+        // 100 VBYTES, SO 100 SATS OVER THE 3000 THE ORIGINAL AND ITS CHILD PAY
+        let replacement = entry("replacement", &[("coin", 0)], FINAL, 400, 3100)?;
+        let (entries, rejections) = resolve_conflicts(vec![child.clone(), replacement, original.clone()]);
+        assert_eq!(kept(&entries), vec!["replacement"]);
+        assert_eq!(
+            rejections,
+            vec![
+                ("child".to_string(), Rejection::ParentConflicted { parent: "original".to_string() }),
+                ("original".to_string(), Rejection::ReplacedBy { replacement: "replacement".to_string() }),
+            ]
+        );
+
+        // RULE 3 AND RULE 4
+        let cheap = entry("cheap", &[("coin", 0)], FINAL, 400, 2500)?;
+        let (entries, rejections) = resolve_conflicts(vec![original.clone(), child.clone(), cheap]);
+        assert_eq!(kept(&entries), vec!["original", "child"]);
+        assert_eq!(rejections[0].1, Rejection::ReplacementFeeTooLow { fee: 2500, replaced_fees: 3000 });
+
+        let barely = entry("barely", &[("coin", 0)], FINAL, 400, 3099)?;
+        let (_, rejections) = resolve_conflicts(vec![original.clone(), child.clone(), barely]);
+        assert_eq!(rejections[0].1, Rejection::ReplacementFeerateTooLow { additional_fee: 99, required: 100 });
+
+        // RULE 2: A NEW UNCONFIRMED INPUT, FROM A TRANSACTION THE ORIGINAL DID NOT SPEND
+        let unrelated = entry("unrelated", &[("elsewhere", 0)], FINAL, 400, 2000)?;
+        let greedy = entry("greedy", &[("coin", 0), ("unrelated", 0)], FINAL, 400, 9000)?;
+        let (entries, rejections) = resolve_conflicts(vec![original.clone(), unrelated, greedy]);
+        assert_eq!(kept(&entries), vec!["original", "unrelated"]);
+        assert_eq!(rejections[0], ("greedy".to_string(), Rejection::ReplacementAddsUnconfirmed { input: 1 }));
+
+        // RULE 5: THE ORIGINAL WITH 100 CHILDREN IS 101 TRANSACTIONS
+        let mut entries = vec![original.clone()];
+        for index in 0..MAX_REPLACEMENT_CANDIDATES {
+            entries.push(entry(&format!("child{}", index), &[("original", index as u32)], FINAL, 400, 1)?);
+        }
+        entries.push(entry("replacement", &[("coin", 0)], FINAL, 400, 1_000_000)?);
+        let (entries, rejections) = resolve_conflicts(entries);
+        assert_eq!(entries.len(), 101);
+        assert_eq!(rejections[0].1, Rejection::TooManyReplacements { count: 101, maximum: 100 });
+
+        Ok(())
+    }
+}
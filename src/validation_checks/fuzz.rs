@@ -0,0 +1,265 @@
+// FUZZ TARGETS FOR THE VALIDATION ENTRY POINTS
+//
+//   fuzz_transaction_json   MEMPOOL JSON -> VALIDATION AND SERIALISATION, AS THE MEMPOOL PIPELINE DOES IT
+//   fuzz_raw_tx             RAW TRANSACTION BYTES -> DECODER
+//   fuzz_script             RAW SCRIPT BYTES -> PARSER, ASM AND INTERPRETER
+//
+// EVERY TARGET MUST RETURN FOR ANY INPUT, A PANIC IS A BUG. THE DRIVER BELOW MUTATES THE MEMPOOL
+// FILES WITH A SEEDED XORSHIFT SO FAILURES REPRODUCE. SET FUZZ_ITERATIONS FOR A LONGER RUN, E.G.
+//
+//   FUZZ_ITERATIONS=200000 cargo test --release fuzz
+use std::fs;
+use std::panic;
+
+use crate::block_mine::deserialise_tx::{deserialise_tx_bytes, input_count};
+use crate::block_mine::serialise_tx::serialise_tx;
+use crate::error::Result;
+use crate::transaction::{Prevout, Transaction};
+
+use super::accept_tx;
+use super::policy::Policy;
+use super::script::{Script, ScriptContext, ScriptFlags, SigVersion, TapscriptExecution};
+
+pub fn fuzz_transaction_json(data: &[u8]) {
+    let Ok(json) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(transaction) = serde_json::from_str::<Transaction>(json) else {
+        return;
+    };
+
+    // THE SAME STEP THE MEMPOOL PIPELINE RUNS FOR EVERY FILE, WITH AND WITHOUT THE STANDARDNESS RULES
+    for policy in [Policy::default(), Policy::consensus()] {
+        let _ = accept_tx(transaction.clone(), &policy).map_err(|(_, rejection)| rejection.to_string());
+    }
+}
+
+pub fn fuzz_raw_tx(data: &[u8]) {
+    // EVERY INPUT NEEDS AT LEAST 41 BYTES, SO A HUGE COUNT CANNOT BE BACKED BY THE DATA ANYWAY
+    let Ok(count) = input_count(data) else {
+        return;
+    };
+    if count > data.len() / 41 {
+        return;
+    }
+
+    if let Ok(transaction) = deserialise_tx_bytes(data, &vec![Prevout::default(); count]) {
+        let _ = serialise_tx(&transaction);
+    }
+}
+
+// THE FIRST BYTE PICKS THE SIGNATURE VERSION AND FLAGS, THE REST IS THE SCRIPT
+pub fn fuzz_script(data: &[u8], tx: &Transaction) {
+    let Some((version, bytes)) = data.split_first() else {
+        return;
+    };
+    let script = Script::new(bytes.to_vec());
+
+    let _ = script.to_asm();
+    let _ = script.script_type();
+    let _ = script.witness_program();
+    let _ = script.is_push_only();
+
+    let sig_version = match version % 3 {
+        0 => SigVersion::Base,
+        1 => SigVersion::WitnessV0,
+        _ => SigVersion::Tapscript,
+    };
+    let ctx = ScriptContext {
+        tx,
+        input_index: 0,
+        sig_version,
+        tapscript: (sig_version == SigVersion::Tapscript).then(|| TapscriptExecution {
+            leaf_hash: vec![0; 32],
+            annex: None,
+            validation_weight: 50 + bytes.len() as i64,
+        }),
+        flags: if version & 4 == 0 { ScriptFlags::CONSENSUS } else { ScriptFlags::STANDARD },
+    };
+
+    // A FEW ITEMS ALREADY ON THE STACK SO MORE OPCODES GET PAST THEIR STACK CHECKS
+    let mut stack = vec![vec![], vec![1], vec![0x81], bytes.iter().take(4).copied().collect()];
+    let _ = script.execute(&mut stack, &ctx);
+}
+
+// XORSHIFT64, GOOD ENOUGH TO PICK MUTATIONS AND DETERMINISTIC FOR A GIVEN SEED
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound.max(1) as u64) as usize
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+
+    // A FEW BYTE LEVEL EDITS: FLIPS, OVERWRITES WITH EDGE VALUES, INSERTS, DELETES AND TRUNCATION
+    fn mutate(&mut self, data: &mut Vec<u8>) {
+        for _ in 0..1 + self.below(4) {
+            let position = self.below(data.len());
+            match self.below(6) {
+                0 if !data.is_empty() => data[position] ^= 1 << self.below(8),
+                1 if !data.is_empty() => data[position] = [0x00, 0x01, 0x4c, 0x4d, 0x4e, 0x7f, 0x80, 0xfd, 0xff][self.below(9)],
+                2 => data.insert(position.min(data.len()), self.next() as u8),
+                3 if !data.is_empty() => {
+                    data.remove(position);
+                }
+                4 => data.truncate(position),
+                _ => {
+                    let len = self.below(8);
+                    let extra = self.bytes(len);
+                    data.splice(position.min(data.len())..position.min(data.len()), extra);
+                }
+            }
+        }
+    }
+}
+
+// STRUCTURE AWARE EDITS, BYTE FLIPS IN THE JSON TEXT ALMOST NEVER PARSE
+fn mutate_transaction(rng: &mut Rng, tx: &mut Transaction) {
+    let hex_field = |rng: &mut Rng, field: &str| -> String {
+        let mut bytes = hex::decode(field).unwrap_or_default();
+        rng.mutate(&mut bytes);
+        hex::encode(bytes)
+    };
+
+    for _ in 0..1 + rng.below(3) {
+        let input = rng.below(tx.vin.len());
+        let output = rng.below(tx.vout.len());
+        match rng.below(10) {
+            0 if !tx.vin.is_empty() => {
+                let scriptsig = tx.vin[input].scriptsig.clone().unwrap_or_default();
+                tx.vin[input].scriptsig = Some(hex_field(rng, &scriptsig));
+            }
+            1 if !tx.vin.is_empty() => {
+                tx.vin[input].prevout.scriptpubkey = hex_field(rng, &tx.vin[input].prevout.scriptpubkey);
+            }
+            2 if !tx.vin.is_empty() => {
+                let mut witness = tx.vin[input].witness.clone().unwrap_or_default();
+                let item = rng.below(witness.len() + 1);
+                match witness.get(item).cloned() {
+                    Some(existing) => witness[item] = hex_field(rng, &existing),
+                    None => {
+                        let len = rng.below(80);
+                        witness.push(hex::encode(rng.bytes(len)));
+                    }
+                }
+                if rng.below(4) == 0 {
+                    witness.truncate(rng.below(witness.len() + 1));
+                }
+                tx.vin[input].witness = Some(witness);
+            }
+            3 if !tx.vin.is_empty() => {
+                tx.vin[input].prevout.value = [0, 1, u64::MAX, u64::MAX / 2, rng.next()][rng.below(5)];
+            }
+            4 if !tx.vout.is_empty() => {
+                tx.vout[output].value = [0, 1, u64::MAX, u64::MAX / 2, rng.next()][rng.below(5)];
+            }
+            5 if !tx.vout.is_empty() => tx.vout[output].scriptpubkey = hex_field(rng, &tx.vout[output].scriptpubkey),
+            6 if !tx.vin.is_empty() => {
+                let types = ["p2pkh", "p2sh", "v0_p2wpkh", "v0_p2wsh", "v1_p2tr", "unknown"];
+                tx.vin[input].prevout.scriptpubkey_type = types[rng.below(types.len())].to_string();
+            }
+            7 if !tx.vin.is_empty() => {
+                tx.vin[input].txid = hex_field(rng, &tx.vin[input].txid);
+                tx.vin[input].vout = rng.next() as u32;
+            }
+            8 => match rng.below(4) {
+                0 => tx.vin.truncate(rng.below(tx.vin.len() + 1)),
+                1 => tx.vout.truncate(rng.below(tx.vout.len() + 1)),
+                2 if !tx.vin.is_empty() => tx.vin.push(tx.vin[input].clone()),
+                _ if !tx.vout.is_empty() => tx.vout.push(tx.vout[output].clone()),
+                _ => {}
+            },
+            _ => {
+                tx.version = rng.next() as i32;
+                tx.locktime = rng.next() as u32;
+                if !tx.vin.is_empty() {
+                    tx.vin[input].sequence = rng.next() as u32;
+                    tx.vin[input].is_coinbase = rng.below(8) == 0;
+                }
+            }
+        }
+    }
+}
+
+fn iterations(default: usize) -> usize {
+    std::env::var("FUZZ_ITERATIONS").ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+// ONE SEED PER COMBINATION OF INPUT TYPES IN THE MEMPOOL
+fn seeds() -> Result<Vec<Transaction>> {
+    let mut seeds: Vec<Transaction> = Vec::new();
+    let mut seen: Vec<String> = Vec::new();
+    for entry in fs::read_dir("./mempool")? {
+        let transaction: Transaction = serde_json::from_str(&fs::read_to_string(entry?.path())?)?;
+        let kind: String = transaction.vin.iter().map(|input| input.prevout.scriptpubkey_type.as_str()).collect();
+        if !seen.contains(&kind) {
+            seen.push(kind);
+            seeds.push(transaction);
+        }
+    }
+    Ok(seeds)
+}
+
+// RUNS ONE TARGET, PRINTING THE FAILING INPUT BEFORE PASSING THE PANIC ON
+fn run(name: &str, data: &[u8], target: impl FnOnce(&[u8]) + panic::UnwindSafe) {
+    if let Err(panic) = panic::catch_unwind(|| target(data)) {
+        eprintln!("{} PANICKED ON INPUT {}", name, hex::encode(data));
+        panic::resume_unwind(panic);
+    }
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+mod test {
+    use super::*;
+
+    #[test]
+    fn fuzz_validation_entry_points() -> Result<()> {
+        let seeds = seeds()?;
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+
+        for _ in 0..iterations(300) {
+            let mut transaction = seeds[rng.below(seeds.len())].clone();
+            mutate_transaction(&mut rng, &mut transaction);
+
+            // STRUCTURED MUTATIONS, THEN BYTE MUTATIONS OF THE JSON TEXT
+            let mut json = serde_json::to_vec(&transaction)?;
+            run("fuzz_transaction_json", &json, fuzz_transaction_json);
+            rng.mutate(&mut json);
+            run("fuzz_transaction_json", &json, fuzz_transaction_json);
+
+            // THE SAME TRANSACTION AS RAW BYTES
+            if let Ok((_, mut raw_wtx, _, _)) = serialise_tx(&transaction) {
+                rng.mutate(&mut raw_wtx);
+                run("fuzz_raw_tx", &raw_wtx, fuzz_raw_tx);
+            }
+
+            // A SCRIPT FROM THE TRANSACTION, OR RANDOM BYTES
+            let mut script = match rng.below(3) {
+                0 => hex::decode(transaction.vin.first().map(|input| input.prevout.scriptpubkey.clone()).unwrap_or_default())
+                    .unwrap_or_default(),
+                1 => hex::decode(transaction.vin.first().and_then(|input| input.scriptsig.clone()).unwrap_or_default())
+                    .unwrap_or_default(),
+                _ => {
+                    let len = rng.below(64);
+                    rng.bytes(len)
+                }
+            };
+            script.insert(0, rng.next() as u8);
+            rng.mutate(&mut script);
+            let seed = &seeds[0];
+            run("fuzz_script", &script, |data| fuzz_script(data, seed));
+        }
+
+        Ok(())
+    }
+}
@@ -1,34 +1,63 @@
 use std::{
-    collections::HashMap, // Importing HashMap from the standard library
+    collections::{HashMap, HashSet}, // Importing HashMap and HashSet from the standard library
     fs::{self}, // Importing fs module from the standard library
     path::Path, // Importing Path module from the standard library
+    sync::OnceLock, // Importing OnceLock for the shared secp256k1 context
 };
 
 use ripemd::Ripemd160; // Importing Ripemd160 hash function
-use secp256k1::{ecdsa::Signature, Message, PublicKey, Secp256k1}; // Importing cryptographic functions from secp256k1 crate
+use secp256k1::{ecdsa::Signature, Message, PublicKey, Secp256k1, VerifyOnly}; // Importing cryptographic functions from secp256k1 crate
 use sha2::{Digest, Sha256}; // Importing cryptographic functions from sha2 crate
 use walkdir::WalkDir; // Importing WalkDir for directory traversal
 
-use crate::{error::Result, transaction::Transaction}; // Importing Transaction and Result types from the crate
+use crate::{compact_size, error::Result, transaction::{Input, OutPoint, Output, Transaction}}; // Importing Transaction and Result types from the crate
+use crate::block_mine::serialise_tx::{create_txid_tx_map, tx_entry, TxEntry}; // Importing the serialisation block assembly works from
+use crate::block_mine::subsidy::MAX_MONEY; // Importing the money supply cap
+use crate::utxo::UtxoSet; // Importing the UTXO set the prevouts are checked against
 use self::{ // Importing modules from the current crate
     p2pkh::input_verification_p2pkh, // Importing function from p2pkh module
+    p2sh::input_verification_p2sh, // Importing function from p2sh module
+    p2tr::input_verification_p2tr, // Importing function from p2tr module
     p2wpkh::input_verification_p2wpkh, // Importing function from p2wpkh module
     p2wsh::input_verification_p2wsh, // Importing function from p2wsh module
+    conflict::resolve_conflicts, // Importing the BIP125 conflict resolution
+    policy::Policy, // Importing the standardness rules
+    rejection::{Rejection, RejectionReport, Rule}, // Importing the rejection reasons and report
+    script::{
+        cast_to_bool, Script, ScriptContext, ScriptError, ScriptErrorKind, ScriptFlags, SigVersion,
+        MAX_SCRIPT_ELEMENT_SIZE,
+    }, // Importing the script interpreter
 };
 
+#[cfg(test)]
+mod fuzz; // Importing the fuzz targets, they only run as tests
+pub mod conflict; // Importing the conflict resolution
+pub mod opcodes; // Importing opcodes module
 pub mod p2pkh; // Importing p2pkh module
 pub mod p2sh; // Importing p2sh module
+pub mod p2tr; // Importing p2tr module
 pub mod p2wpkh; // Importing p2wpkh module
 pub mod p2wsh; // Importing p2wsh module
+pub mod policy; // Importing the standardness rules
+pub mod rejection; // Importing the rejection reasons and report
+pub mod script; // Importing script interpreter module
+pub mod sigops; // Importing the sigop cost counting
+pub mod timelock; // Importing the finality checks
+
+// SIGNATURE HASH TYPES
+pub const SIGHASH_ALL: u32 = 0x01;
+pub const SIGHASH_NONE: u32 = 0x02;
+pub const SIGHASH_SINGLE: u32 = 0x03;
+pub const SIGHASH_ANYONECANPAY: u32 = 0x80;
 
 // HASH160
 pub fn hash160(data: &[u8]) -> Vec<u8> { // Defining a function to compute hash160
-    Ripemd160::digest(&Sha256::digest(data)).to_vec() // Computing the hash160
+    Ripemd160::digest(Sha256::digest(data)).to_vec() // Computing the hash160
 }
 
 // HASH256
 pub fn double_sha256(data: &[u8]) -> Vec<u8> { // Defining a function to compute double SHA256
-    Sha256::digest(&Sha256::digest(data)).to_vec() // Computing the double SHA256
+    Sha256::digest(Sha256::digest(data)).to_vec() // Computing the double SHA256
 }
 
 // SHA256
@@ -36,699 +65,838 @@ pub fn single_sha256(data: &[u8]) -> Vec<u8> { // Defining a function to compute
     Sha256::digest(data).to_vec() // Computing the SHA256
 }
 
-// OPCHECKSIG OPCODE IMPLEMENTATION
-pub fn op_checksig( // Defining a function to implement op_checksig opcode
-    stack: &mut Vec<Vec<u8>>, // Mutable reference to a stack of byte vectors
-    tx: Transaction, // Transaction object
-    tx_input_index: usize, // Index of the transaction input
-    input_type: &str, // Type of the input
-) -> Result<bool> { // Result indicating success or failure
-
-    let dummy_pubkey: PublicKey = PublicKey::from_slice(
-        &hex::decode("03bf68f1ce783df58a2459d549d5c655a1edc0f0cf4d79421fe978d358d79ee42a").unwrap(),
-    )
-    .unwrap();
-    let dummy_signature: Signature = Signature::from_der(&hex::decode("304402205112f96bf7423703c221976603307f0e33913d39efc3344d68376fd2b8c0bd20022003ea588c06fa1a3e262e07ed6bf01a36f78741fe7bc6a91ff43c38a0a14e43fe").unwrap()).unwrap();
-
-    // POP THE PUBLIC KEY FROM THE STACK
-
-    let pubkey_bytes = stack.pop().unwrap();
-    let pubkey = PublicKey::from_slice(&pubkey_bytes).unwrap_or(dummy_pubkey);
-    let signature_bytes = stack.pop().expect("STACK UNDERFLOW");
-
-    let sig = Signature::from_der(&signature_bytes[..signature_bytes.len() - 1])
-        .unwrap_or(dummy_signature);
-
-    // EXTRACT THE SIGHASH TYPE
-    let sighash_type = signature_bytes.last().copied().expect("SIGHASH: MISSING") as u32;
+// SHARED SECP256K1 CONTEXT, CREATING ONE PER SIGNATURE IS EXPENSIVE
+fn secp() -> &'static Secp256k1<VerifyOnly> {
+    static SECP: OnceLock<Secp256k1<VerifyOnly>> = OnceLock::new();
+    SECP.get_or_init(Secp256k1::verification_only)
+}
 
-    let mut trimmed_tx = trimmed_tx(tx.clone(), tx_input_index, input_type, sighash_type.clone())?;
-    trimmed_tx.extend(&sighash_type.to_le_bytes());
+// DECODES THE HEX WITNESS ITEMS OF AN INPUT
+pub fn witness_items(tx: &Transaction, tx_input_index: usize) -> Result<Vec<Vec<u8>>> {
+    let mut items = Vec::new();
+    for item in tx.vin[tx_input_index].witness.iter().flatten() {
+        items.push(hex::decode(item)?);
+    }
+    Ok(items)
+}
 
-    // HASH256 THE TRIMMED-TX AND THEN CREATE THE MESSAGE OBJECT
-    let trimmed_tx_hash = double_sha256(&trimmed_tx);
-    let msg = Message::from_digest_slice(&trimmed_tx_hash).expect("PARSING: FAILED");
+// RUNS A WITNESS V0 SCRIPT, IT MUST LEAVE EXACTLY ONE TRUE ELEMENT ON THE STACK
+pub fn execute_witness_script(
+    witness_script: &Script,
+    stack: Vec<Vec<u8>>,
+    tx: &Transaction,
+    tx_input_index: usize,
+    flags: ScriptFlags,
+) -> Result<bool> {
+    let ctx = ScriptContext {
+        tx,
+        input_index: tx_input_index,
+        sig_version: SigVersion::WitnessV0,
+        tapscript: None,
+        flags,
+    };
+    execute_witness_stack(witness_script, stack, &ctx)
+}
 
-    // VERIFYING THE SIGNATURE
-    let secp = Secp256k1::new();
+// SHARED BY WITNESS V0 SCRIPTS AND TAPSCRIPTS, ONLY THE CONTEXT DIFFERS
+pub fn execute_witness_stack(witness_script: &Script, mut stack: Vec<Vec<u8>>, ctx: &ScriptContext) -> Result<bool> {
+    if stack.iter().any(|item| item.len() > MAX_SCRIPT_ELEMENT_SIZE) {
+        return Err(ScriptError::new(ScriptErrorKind::PushSize).into());
+    }
 
-    let mut result: bool = false;
+    witness_script.execute(&mut stack, ctx)?;
 
-    // ECDSA VERIFICATION FUNCTION
-    if secp.verify_ecdsa(&msg, &sig, &pubkey).is_ok() {
-        result = true;
+    if stack.len() != 1 {
+        return Err(ScriptError::new(ScriptErrorKind::CleanStack).into());
     }
-    Ok(result)
+    Ok(cast_to_bool(&stack[0]))
 }
 
-// OPMULTICHECKSIG OPCODE IMPLEMENTATION
-pub fn op_checkmultisig( // Defining a function to implement op_checkmultisig opcode
-    stack: &mut Vec<Vec<u8>>, // Mutable reference to a stack of byte vectors
-    tx: Transaction, // Transaction object
+// VERIFIES AN ECDSA SIGNATURE (DER + SIGHASH BYTE) FOR THE INPUT BEING SPENT
+pub fn check_ecdsa_signature(
+    signature_bytes: &[u8], // DER signature followed by the sighash type
+    pubkey_bytes: &[u8], // Serialized public key
+    tx: &Transaction, // Spending transaction
     tx_input_index: usize, // Index of the transaction input
-    input_type: &str, // Type of the input
-) -> Result<bool> { // Result indicating success or failure
-
-    let dummy_pubkey: PublicKey = PublicKey::from_slice(
-        &hex::decode("03bf68f1ce783df58a2459d549d5c655a1edc0f0cf4d79421fe978d358d79ee42a").unwrap(),
-    )
-    .unwrap();
-
-    let dummy_signature: Signature = Signature::from_der(&hex::decode("304402205112f96bf7423703c221976603307f0e33913d39efc3344d68376fd2b8c0bd20022003ea588c06fa1a3e262e07ed6bf01a36f78741fe7bc6a91ff43c38a0a14e43fe").unwrap()).unwrap();
+    script_code: &[u8], // Part of the script committed to by the signature
+    sig_version: SigVersion, // Legacy or BIP143 signature hashing
+) -> Result<bool> {
+    // EXTRACT THE SIGHASH TYPE
+    let (sighash_type, der_bytes) = match signature_bytes.split_last() {
+        Some((sighash_type, der_bytes)) => (*sighash_type as u32, der_bytes),
+        None => return Ok(false),
+    };
+
+    let pubkey = match PublicKey::from_slice(pubkey_bytes) {
+        Ok(pubkey) => pubkey,
+        Err(_) => return Ok(false),
+    };
+
+    // LIBSECP256K1 ONLY ACCEPTS LOW-S SIGNATURES, CONSENSUS ACCEPTS BOTH
+    let mut sig = match Signature::from_der(der_bytes) {
+        Ok(sig) => sig,
+        Err(_) => return Ok(false),
+    };
+    sig.normalize_s();
+
+    let sighash = signature_hash(tx, tx_input_index, script_code, sig_version, sighash_type)?;
+    let msg = Message::from_digest_slice(&sighash)?;
 
-    // POP THE PUBLIC KEYS FROM THE STACK
-    let mut n_keys = 0;
-    if let Some(item) = stack.pop() {
-        if let Some(&byte) = item.first() {
-            n_keys = byte as u8;
-        }
-    }
+    // ECDSA VERIFICATION FUNCTION
+    Ok(secp().verify_ecdsa(&msg, &sig, &pubkey).is_ok())
+}
 
-    // EXTRACT THE PUBKEYS AND SIGNATURES FROM THE STACK
-    let mut pubkeys: Vec<PublicKey> = Vec::new();
+// OUTPOINTS, SEQUENCES AND OUTPUTS AS THE SIGNATURE HASHES COMMIT TO THEM
+fn serialise_prevouts(tx: &Transaction) -> Result<Vec<u8>> {
+    let mut prevouts: Vec<u8> = Vec::new();
+    for input in tx.vin.iter() {
+        let mut txid_bytes_reversed = hex::decode(&input.txid)?;
+        txid_bytes_reversed.reverse();
 
-    for _ in 0..n_keys {
-        let pubkey_bytes = stack.pop().expect("STACK UNDERFLOW: PUBKEY");
-        pubkeys.push(PublicKey::from_slice(&pubkey_bytes).unwrap_or(dummy_pubkey));
+        prevouts.extend_from_slice(&txid_bytes_reversed);
+        prevouts.extend(input.vout.to_le_bytes());
     }
+    Ok(prevouts)
+}
 
-    // POP THE SIGNATURES FROM THE STACK
-    let mut n_signatures = 0;
-    if let Some(item) = stack.pop() {
-        if let Some(&byte) = item.first() {
-            n_signatures = byte as u8;
-        }
+fn serialise_sequences(tx: &Transaction) -> Vec<u8> {
+    let mut sequence: Vec<u8> = Vec::new();
+    for input in tx.vin.iter() {
+        sequence.extend(input.sequence.to_le_bytes());
     }
+    sequence
+}
 
-    let mut signatures: Vec<(Signature, u32)> = Vec::new();
+fn serialise_output(output: &Output) -> Result<Vec<u8>> {
+    let mut serialised: Vec<u8> = Vec::new();
+    serialised.extend(output.value.to_le_bytes());
 
-    for _ in 0..n_signatures {
-        let signature_bytes = stack.pop().expect("STACK UNDERFLOW: SIGNATURES");
+    let scriptpubkey_bytes = hex::decode(&output.scriptpubkey)?;
+    serialised.extend(compact_size::encode(scriptpubkey_bytes.len()));
+    serialised.extend_from_slice(&scriptpubkey_bytes);
+    Ok(serialised)
+}
 
-        // EXTRACT THE SIGHASH TYPE
-        let sighash_type = signature_bytes.last().copied().expect("SIGHASH: MISSING") as u32;
-        signatures.push((
-            Signature::from_der(&signature_bytes[..&signature_bytes.len() - 1])
-                .unwrap_or(dummy_signature),
-            sighash_type,
-        ));
+fn serialise_outputs(tx: &Transaction) -> Result<Vec<u8>> {
+    let mut outputs: Vec<u8> = Vec::new();
+    for output in tx.vout.iter() {
+        outputs.extend(serialise_output(output)?);
     }
+    Ok(outputs)
+}
 
-    let secp = Secp256k1::new();
-    let mut valid_sig_count = 0;
+// HASHPREVOUTS, HASHSEQUENCE AND HASHOUTPUTS FROM BIP143
+fn hash_prevouts(tx: &Transaction) -> Result<Vec<u8>> {
+    Ok(double_sha256(&serialise_prevouts(tx)?))
+}
 
-    for (sig, sighash) in signatures {
-        // TRIM THE TRANSACTION AS PER THE SIGHASH_TYPE
-        let mut trimmed_tx = trimmed_tx(tx.clone(), tx_input_index, input_type, sighash.clone())?;
-        trimmed_tx.extend(&sighash.to_le_bytes());
+fn hash_sequence(tx: &Transaction) -> Vec<u8> {
+    double_sha256(&serialise_sequences(tx))
+}
 
-        let trimmed_tx_hash = double_sha256(&trimmed_tx);
-        let msg = Message::from_digest_slice(&trimmed_tx_hash).expect("PARSING: FAILED");
+fn hash_outputs(tx: &Transaction) -> Result<Vec<u8>> {
+    Ok(double_sha256(&serialise_outputs(tx)?))
+}
 
-        for pk in &pubkeys {
-            if secp.verify_ecdsa(&msg, &sig, pk).is_ok() {
-                valid_sig_count += 1;
-                break;
-            }
-        }
+// THE DIGEST AN ECDSA SIGNATURE SIGNS: HASH256 OF THE TRIMMED TX AND THE SIGHASH TYPE
+pub fn signature_hash(
+    tx: &Transaction,
+    tx_input_index: usize,
+    script_code: &[u8],
+    sig_version: SigVersion,
+    sighash_type: u32,
+) -> Result<Vec<u8>> {
+    // LEGACY SIGHASH_SINGLE WITHOUT A MATCHING OUTPUT SIGNS THE NUMBER ONE INSTEAD OF FAILING
+    if sig_version == SigVersion::Base
+        && sighash_type & 0x1f == SIGHASH_SINGLE
+        && tx_input_index >= tx.vout.len()
+    {
+        let mut one = vec![0u8; 32];
+        one[0] = 1;
+        return Ok(one);
     }
-    let mut result: bool = false;
 
-    if valid_sig_count == n_signatures {
-        result = true;
-    }
-    Ok(result)
+    let mut trimmed_tx = trimmed_tx(tx, tx_input_index, script_code, sig_version, sighash_type)?;
+    trimmed_tx.extend(&sighash_type.to_le_bytes());
+
+    Ok(double_sha256(&trimmed_tx))
 }
 
+// BUILDS THE SIGNATURE PREIMAGE (WITHOUT THE TRAILING SIGHASH TYPE)
 pub fn trimmed_tx(
-    tx: Transaction,
+    tx: &Transaction,
     tx_input_index: usize,
-    input_type: &str,
+    script_code: &[u8],
+    sig_version: SigVersion,
     sighash_type: u32,
 ) -> Result<Vec<u8>> {
     let mut trimmed_tx: Vec<u8> = Vec::new();
 
-    // FOR SIGHASH_ALL
-    if sighash_type == 01 {
+    // ONLY THE LOW BITS PICK THE OUTPUTS, UNKNOWN VALUES BEHAVE LIKE SIGHASH_ALL
+    let output_type = sighash_type & 0x1f;
+    let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+
+    match sig_version {
         // FOR LEGACY TXS
-        if input_type == "NON_SEGWIT" {
+        SigVersion::Base => {
             trimmed_tx.extend(&tx.version.to_le_bytes());
 
-            // INPUTS
-            trimmed_tx.push(tx.vin.len() as u8);
+            // INPUTS, ANYONECANPAY ONLY KEEPS THE ONE BEING SIGNED
+            let inputs: Vec<(usize, &Input)> = if anyone_can_pay {
+                vec![(tx_input_index, &tx.vin[tx_input_index])]
+            } else {
+                tx.vin.iter().enumerate().collect()
+            };
+
+            trimmed_tx.extend(compact_size::encode(inputs.len()));
 
-            for input_index in 0..tx.vin.len() {
-                let mut txid_bytes_reversed =
-                    hex::decode(&tx.vin[input_index].txid).expect("DECODING: FAILED");
+            for (input_index, input) in inputs {
+                let mut txid_bytes_reversed = hex::decode(&input.txid)?;
                 txid_bytes_reversed.reverse();
 
                 trimmed_tx.extend_from_slice(&txid_bytes_reversed);
-                trimmed_tx.extend(&tx.vin[input_index].vout.to_le_bytes());
+                trimmed_tx.extend(&input.vout.to_le_bytes());
 
-                // PUSHING THE INPUTS IN THE BYTE SEQUENCE
+                // ONLY THE INPUT BEING SIGNED CARRIES THE SCRIPT CODE
                 if input_index == tx_input_index {
-                    // PUSHING THE REDEEM SCRIPT IN PLACE OF SCRIPT PUB KEY
-                    let scriptsig_asm = tx.vin[input_index]
-                        .scriptsig_asm
-                        .clone()
-                        .unwrap_or("SCRIPT SIG ASM: MISSING".to_string());
-
-                    let scriptsig_asm_slices: Vec<&str> =
-                        scriptsig_asm.split_whitespace().collect();
-
-                    let redeem_script = scriptsig_asm_slices
-                        .last()
-                        .cloned()
-                        .expect("STACK UNDERFLOW");
-
-                    let redeem_script_bytes = hex::decode(redeem_script)?;
-
-                    trimmed_tx.push(redeem_script_bytes.len().try_into()?);
-                    trimmed_tx.extend_from_slice(&redeem_script_bytes);
+                    trimmed_tx.extend(compact_size::encode(script_code.len()));
+                    trimmed_tx.extend_from_slice(script_code);
+                    trimmed_tx.extend(&input.sequence.to_le_bytes());
                 } else {
-                    trimmed_tx.push(0 as u8);
+                    trimmed_tx.push(0);
+
+                    // NONE AND SINGLE LET THE OTHER INPUTS UPDATE THEIR SEQUENCE
+                    if output_type == SIGHASH_NONE || output_type == SIGHASH_SINGLE {
+                        trimmed_tx.extend(&0u32.to_le_bytes());
+                    } else {
+                        trimmed_tx.extend(&input.sequence.to_le_bytes());
+                    }
                 }
-                trimmed_tx.extend(&tx.vin[input_index].sequence.to_le_bytes());
             }
 
             // OUTPUTS
-            trimmed_tx.push(tx.vout.len() as u8);
+            match output_type {
+                SIGHASH_NONE => trimmed_tx.extend(compact_size::encode(0)),
+
+                // EVERY OUTPUT BEFORE THE MATCHING ONE IS BLANKED: VALUE -1 AND AN EMPTY SCRIPT
+                SIGHASH_SINGLE => {
+                    trimmed_tx.extend(compact_size::encode(tx_input_index + 1));
 
-            // PUSHING THE OUTPUTS IN THE BYTE SEQUENCE
-            for tx_ouput in tx.vout.iter() {
-                let script_pubkey_bytes =
-                    hex::decode(tx_ouput.scriptpubkey.clone()).expect("DECODING FAILED");
+                    for _ in 0..tx_input_index {
+                        trimmed_tx.extend(u64::MAX.to_le_bytes());
+                        trimmed_tx.push(0);
+                    }
+                    match tx.vout.get(tx_input_index) {
+                        Some(output) => trimmed_tx.extend(serialise_output(output)?),
+                        None => return Err(ScriptError::new(ScriptErrorKind::Sighash).into()),
+                    }
+                }
 
-                trimmed_tx.extend(tx_ouput.value.to_le_bytes());
-                trimmed_tx.push(script_pubkey_bytes.len().try_into()?);
-                trimmed_tx.extend_from_slice(&script_pubkey_bytes);
+                _ => {
+                    trimmed_tx.extend(compact_size::encode(tx.vout.len()));
+                    trimmed_tx.extend(serialise_outputs(tx)?);
+                }
             }
+
             trimmed_tx.extend(&tx.locktime.to_le_bytes());
         }
 
-        // FOR SEGWIT_TXS
-        if input_type == "P2SH-P2WPKH" {
-            trimmed_tx.extend(&tx.version.to_le_bytes());
+        // FOR SEGWIT TXS (BIP143)
+        SigVersion::WitnessV0 => {
+            let input = &tx.vin[tx_input_index];
+            let zero_hash = vec![0u8; 32];
 
-            // PUSHING HASHPREVOUTS AND HASHSEQUENCE
-            let mut prevouts: Vec<u8> = Vec::new();
-            let mut sequence: Vec<u8> = Vec::new();
-            for input_index in 0..tx.vin.len() {
-                let mut txid_bytes_reversed = hex::decode(&tx.vin[input_index].txid)?;
-                txid_bytes_reversed.reverse();
-
-                prevouts.extend_from_slice(&txid_bytes_reversed);
-                prevouts.extend(&tx.vin[input_index].vout.to_le_bytes());
+            trimmed_tx.extend(&tx.version.to_le_bytes());
 
-                sequence.extend(&tx.vin[input_index].sequence.to_le_bytes());
+            // PUSHING HASHPREVOUTS AND HASHSEQUENCE, ZEROED WHEN THEY ARE NOT SIGNED
+            if anyone_can_pay {
+                trimmed_tx.extend_from_slice(&zero_hash);
+            } else {
+                trimmed_tx.extend_from_slice(&hash_prevouts(tx)?);
             }
-            let hashprevouts = double_sha256(&prevouts);
-            let hashsequence = double_sha256(&sequence);
-
-            trimmed_tx.extend_from_slice(&hashprevouts);
-            trimmed_tx.extend_from_slice(&hashsequence);
 
-            // OUTPOINTS FOR THE INPUT BEING VERIFIED
-
-            // SUBPARTS :-
-
-            // PUSING THE REVERSED TXID
-            let mut txid_bytes_reversed_sig = hex::decode(&tx.vin[tx_input_index].txid)?;
-            txid_bytes_reversed_sig.reverse();
-
-            trimmed_tx.extend_from_slice(&txid_bytes_reversed_sig);
-            trimmed_tx.extend(tx.vin[tx_input_index].vout.to_le_bytes());
-
-            // EXTRACTING THE REDEEM SCRIPT FROM THE INNER REDEEM SCRIPT ASM
-            let inner_redeemscript_asm = tx.vin[tx_input_index]
-                .inner_redeemscript_asm
-                .clone()
-                .unwrap_or("REDEEM SCRIPT: MISSING".to_string());
-
-            let inner_redeemscript_asm_slices: Vec<&str> =
-                inner_redeemscript_asm.split_whitespace().collect();
-            let redeem_script = inner_redeemscript_asm_slices
-                .last()
-                .cloned()
-                .unwrap_or("REDEEM SCRIPT: MISSING");
-
-            // CREATING AND PUSHING THE SCRIPT CODE
-            let scrip_code = format!("{}{}{}", "1976a914", redeem_script, "88ac");
-            let script_code_bytes = hex::decode(&scrip_code)?;
-
-            trimmed_tx.extend_from_slice(&script_code_bytes);
-
-            // PUSHING THE AMOUNT
-            trimmed_tx.extend(tx.vin[tx_input_index].prevout.value.to_le_bytes());
-
-            // PUSHING THE SEQUENCE
-            trimmed_tx.extend(tx.vin[tx_input_index].sequence.to_le_bytes());
+            if anyone_can_pay || output_type == SIGHASH_NONE || output_type == SIGHASH_SINGLE {
+                trimmed_tx.extend_from_slice(&zero_hash);
+            } else {
+                trimmed_tx.extend_from_slice(&hash_sequence(tx));
+            }
 
-            // PUSHING THE OUTPUTS IN THE SEQUENCE
+            // OUTPOINT FOR THE INPUT BEING VERIFIED
+            let mut txid_bytes_reversed = hex::decode(&input.txid)?;
+            txid_bytes_reversed.reverse();
 
-            let mut outputs: Vec<u8> = Vec::new();
-            for output in tx.vout.iter() {
-                outputs.extend(output.value.to_le_bytes());
+            trimmed_tx.extend_from_slice(&txid_bytes_reversed);
+            trimmed_tx.extend(input.vout.to_le_bytes());
 
-                let scriptpubkey_bytes = hex::decode(&output.scriptpubkey)?;
-                outputs.push(scriptpubkey_bytes.len().try_into()?);
-                outputs.extend_from_slice(&scriptpubkey_bytes);
+            // SCRIPT CODE
+            trimmed_tx.extend(compact_size::encode(script_code.len()));
+            trimmed_tx.extend_from_slice(script_code);
+
+            // PUSHING THE AMOUNT AND THE SEQUENCE
+            trimmed_tx.extend(input.prevout.value.to_le_bytes());
+            trimmed_tx.extend(input.sequence.to_le_bytes());
+
+            // PUSHING HASHOUTPUTS, SINGLE ONLY COMMITS TO THE MATCHING OUTPUT IF THERE IS ONE
+            match output_type {
+                SIGHASH_NONE => trimmed_tx.extend_from_slice(&zero_hash),
+                SIGHASH_SINGLE => match tx.vout.get(tx_input_index) {
+                    Some(output) => trimmed_tx.extend(double_sha256(&serialise_output(output)?)),
+                    None => trimmed_tx.extend_from_slice(&zero_hash),
+                },
+                _ => trimmed_tx.extend_from_slice(&hash_outputs(tx)?),
             }
 
-            let hash_outputs = double_sha256(&outputs);
-
-            trimmed_tx.extend_from_slice(&hash_outputs);
-
             // PUSHING THE LOCKTIME
             trimmed_tx.extend(tx.locktime.to_le_bytes());
         }
 
-        if input_type == "P2SH-P2WSH" {
-            trimmed_tx.extend(&tx.version.to_le_bytes());
-
-            // PUSHING HASHPREVOUTS AND HASHSEQUENCE
-            let mut prevouts: Vec<u8> = Vec::new();
-            let mut sequence: Vec<u8> = Vec::new();
-            for input_index in 0..tx.vin.len() {
-                let mut txid_bytes_reversed = hex::decode(&tx.vin[input_index].txid)?;
-                txid_bytes_reversed.reverse();
-
-                prevouts.extend_from_slice(&txid_bytes_reversed);
-                prevouts.extend(&tx.vin[input_index].vout.to_le_bytes());
-
-                sequence.extend(&tx.vin[input_index].sequence.to_le_bytes());
-            }
-            let hashprevouts = double_sha256(&prevouts);
-            let hashsequence = double_sha256(&sequence);
-
-            trimmed_tx.extend_from_slice(&hashprevouts);
-            trimmed_tx.extend_from_slice(&hashsequence);
-
-            // OUTPOINTS FOR THE INPUT BEING VERIFIED
-
-            // SUBPARTS :-
-
-            // PUSING THE REVERSED TXID
-            let mut txid_bytes_reversed_sig = hex::decode(&tx.vin[tx_input_index].txid)?;
-            txid_bytes_reversed_sig.reverse();
-
-            trimmed_tx.extend_from_slice(&txid_bytes_reversed_sig);
-            trimmed_tx.extend(tx.vin[tx_input_index].vout.to_le_bytes());
-
-            // SCRIPT CODE
-
-            // EXTRACTING THE REDEEM SCRIPT FROM THE WITNESS
-            let witness_script_hex = tx.vin[tx_input_index]
-                .witness
-                .clone()
-                .expect("WITNESS: MISSING")
-                .last()
-                .cloned()
-                .expect("WITNESS SCRIPT: MISSING");
+        // TAPSCRIPT SIGNATURES ARE SCHNORR, SEE p2tr::taproot_sighash
+        SigVersion::Tapscript => {
+            return Err(ScriptError::new(ScriptErrorKind::Sighash).into());
+        }
+    }
+    Ok(trimmed_tx)
+}
 
-            let script_code_bytes = hex::decode(&witness_script_hex)?;
+// THE DEFAULT POLICY WITHOUT THE RULE, WHAT THE TESTS CHECK AGAINST
+#[cfg(test)]
+pub fn verify_tx(tx: Transaction) -> Result<bool> {
+    Ok(check_tx(&tx).is_ok())
+}
 
-            trimmed_tx.push(script_code_bytes.len().try_into()?);
-            trimmed_tx.extend_from_slice(&script_code_bytes);
+// SAME CHECKS AS `verify_tx`, BUT SAYING WHICH ONE FAILED
+#[cfg(test)]
+pub fn check_tx(tx: &Transaction) -> std::result::Result<(), Rejection> {
+    check_tx_with(tx, &Policy::default()).map_err(|(_, rejection)| rejection)
+}
 
-            // PUSHING THE AMOUNT
-            trimmed_tx.extend(tx.vin[tx_input_index].prevout.value.to_le_bytes());
+// CONSENSUS FIRST, THEN EVERY RULE `policy` HAS SWITCHED ON, AND WHICH OF THE TWO REJECTED IT
+pub fn check_tx_with(tx: &Transaction, policy: &Policy) -> std::result::Result<(), (Rule, Rejection)> {
+    // SHAPE AND AMOUNTS CHECK
+    check_transaction(tx).map_err(|rejection| (Rule::Consensus, rejection))?;
 
-            // PUSHING THE SEQUENCE
-            trimmed_tx.extend(tx.vin[tx_input_index].sequence.to_le_bytes());
+    if let Err(rejection) = policy.check(tx) {
+        // A POLICY RULE MAY CATCH IT FIRST, BUT ONE CONSENSUS REJECTS TOO IS REPORTED AS CONSENSUS
+        check_scripts(tx, ScriptFlags::CONSENSUS).map_err(|rejection| (Rule::Consensus, rejection))?;
 
-            // PUSHING THE OUTPUTS IN THE SEQUENCE
+        // ONE THAT CANNOT EVEN BE SERIALISED IS MALFORMED
+        if let Rejection::Unserialisable { .. } = rejection {
+            return Err((Rule::Consensus, rejection));
+        }
+        return Err((Rule::Policy, rejection));
+    }
 
-            let mut outputs: Vec<u8> = Vec::new();
-            for output in tx.vout.iter() {
-                outputs.extend(output.value.to_le_bytes());
+    match check_scripts(tx, policy.script_flags) {
+        Ok(()) => Ok(()),
 
-                let scriptpubkey_bytes = hex::decode(&output.scriptpubkey)?;
-                outputs.push(scriptpubkey_bytes.len().try_into()?);
-                outputs.extend_from_slice(&scriptpubkey_bytes);
+        // A SCRIPT THAT ONLY FAILS UNDER THE STRICTER FLAGS IS A POLICY REJECTION, OTHERWISE THE
+        // CONSENSUS FAILURE IS THE REAL REASON (A BAD SIGNATURE, NOT NULLFAIL)
+        Err(rejection) if policy.script_flags != ScriptFlags::CONSENSUS => {
+            match check_scripts(tx, ScriptFlags::CONSENSUS) {
+                Ok(()) => Err((Rule::Policy, rejection)),
+                Err(consensus) => Err((Rule::Consensus, consensus)),
             }
-
-            let hash_outputs = double_sha256(&outputs);
-
-            trimmed_tx.extend_from_slice(&hash_outputs);
-
-            // PUSHING THE LOCKTIME
-            trimmed_tx.extend(tx.locktime.to_le_bytes());
         }
+        Err(rejection) => Err((Rule::Consensus, rejection)),
+    }
+}
 
-        if input_type == "P2WPKH" {
-            // println!("P2WPKH");
-            trimmed_tx.extend(&tx.version.to_le_bytes());
-
-            // PUSHING HASHPREVOUTS AND HASHSEQUENCE
-            let mut prevouts: Vec<u8> = Vec::new();
-            let mut sequence: Vec<u8> = Vec::new();
-            for input_index in 0..tx.vin.len() {
-                let mut txid_bytes_reversed = hex::decode(&tx.vin[input_index].txid)?;
-                txid_bytes_reversed.reverse();
-
-                prevouts.extend_from_slice(&txid_bytes_reversed);
-                prevouts.extend(&tx.vin[input_index].vout.to_le_bytes());
-
-                sequence.extend(&tx.vin[input_index].sequence.to_le_bytes());
+// EVERY INPUT IS CHECKED AGAINST THE TYPE OF THE OUTPUT IT SPENDS
+fn check_scripts(tx: &Transaction, flags: ScriptFlags) -> std::result::Result<(), Rejection> {
+    for input_index in 0..tx.vin.len() {
+        let script_type = tx.vin[input_index].prevout.scriptpubkey_type.as_str();
+        let result = match script_type {
+            "p2pkh" => input_verification_p2pkh(tx.clone(), input_index, flags),
+            "p2sh" => input_verification_p2sh(input_index, tx.clone(), flags),
+            "v0_p2wpkh" => input_verification_p2wpkh(input_index, tx.clone(), flags),
+            "v0_p2wsh" => input_verification_p2wsh(input_index, tx.clone(), flags),
+            "v1_p2tr" => input_verification_p2tr(input_index, tx.clone(), flags),
+            _ => {
+                return Err(Rejection::UnsupportedScriptType { input: input_index, script_type: script_type.to_string() })
             }
-            let hashprevouts = double_sha256(&prevouts);
-            let hashsequence = double_sha256(&sequence);
-
-            trimmed_tx.extend_from_slice(&hashprevouts);
-            trimmed_tx.extend_from_slice(&hashsequence);
-
-            // OUTPOINTS FOR THE INPUT BEING VERIFIED
-
-            // SUBPARTS :-
-
-            // PUSING THE REVERSED TXID
-            let mut txid_bytes_reversed_sig = hex::decode(&tx.vin[tx_input_index].txid)?;
-            txid_bytes_reversed_sig.reverse();
-
-            trimmed_tx.extend_from_slice(&txid_bytes_reversed_sig);
-            trimmed_tx.extend(tx.vin[tx_input_index].vout.to_le_bytes());
-
-            // SCRIPT CODE
+        };
 
-            // EXTRACTING THE PUBLIC KEY HASH FROM THE SCRIPT PUB KEY ASM
-            let scriptpubkey_asm = tx.vin[tx_input_index].prevout.scriptpubkey_asm.clone();
+        match result {
+            Ok(true) => {}
 
-            let scriptpubkey_slices: Vec<&str> = scriptpubkey_asm.split_whitespace().collect();
-
-            // script-code 0x1976a914{20-byte-pubkey-hash}88ac
-
-            let pubkey_hash = scriptpubkey_slices
-                .last()
-                .cloned()
-                .unwrap_or("SCRIPT PUB KEY: MISSING");
-            let script_code_hex = format!("{}{}{}", "1976a914", pubkey_hash, "88ac");
-
-            let script_code_bytes = hex::decode(&script_code_hex)?;
-
-            trimmed_tx.extend_from_slice(&script_code_bytes);
-
-            // PUSHING THE AMOUNT
-            trimmed_tx.extend(tx.vin[tx_input_index].prevout.value.to_le_bytes());
-
-            // PUSHING THE SEQUENCE
-            trimmed_tx.extend(tx.vin[tx_input_index].sequence.to_le_bytes());
-
-            // PUSHING THE OUTPUTS IN THE SEQUENCE
-
-            let mut outputs: Vec<u8> = Vec::new();
-            for output in tx.vout.iter() {
-                outputs.extend(output.value.to_le_bytes());
-
-                let scriptpubkey_bytes = hex::decode(&output.scriptpubkey)?;
-                outputs.push(scriptpubkey_bytes.len().try_into()?);
-                outputs.extend_from_slice(&scriptpubkey_bytes);
+            // THESE END IN OP_CHECKSIG, SO A FALSE RESULT IS A SIGNATURE THAT DID NOT VERIFY
+            Ok(false) if script_type == "p2pkh" || script_type == "v0_p2wpkh" => {
+                return Err(Rejection::BadSignature { input: input_index });
             }
 
-            let hash_outputs = double_sha256(&outputs);
-
-            trimmed_tx.extend_from_slice(&hash_outputs);
+            Ok(false) => {
+                return Err(Rejection::ScriptFailed { input: input_index, opcode: None, error: "EvalFalse".to_string() });
+            }
 
-            // PUSHING THE LOCKTIME
-            trimmed_tx.extend(tx.locktime.to_le_bytes());
+            Err(error) => {
+                return Err(Rejection::from_input_error(input_index, &error));
+            }
         }
+    }
+    Ok(())
+}
 
-        if input_type == "P2WSH" {
-            // println!("P2WSH");
-            trimmed_tx.extend(&tx.version.to_le_bytes());
-
-            // PUSHING HASHPREVOUTS AND HASHSEQUENCE
-            let mut prevouts: Vec<u8> = Vec::new();
-            let mut sequence: Vec<u8> = Vec::new();
-            for input_index in 0..tx.vin.len() {
-                let mut txid_bytes_reversed = hex::decode(&tx.vin[input_index].txid)?;
-                txid_bytes_reversed.reverse();
-
-                prevouts.extend_from_slice(&txid_bytes_reversed);
-                prevouts.extend(&tx.vin[input_index].vout.to_le_bytes());
-
-                sequence.extend(&tx.vin[input_index].sequence.to_le_bytes());
-            }
-            let hashprevouts = double_sha256(&prevouts);
-            let hashsequence = double_sha256(&sequence);
+// WHAT CONSENSUS ASKS OF EVERY TRANSACTION BEFORE ANY SCRIPT RUNS, AS IN BITCOIN CORE'S `CheckTransaction`
+fn check_transaction(tx: &Transaction) -> std::result::Result<(), Rejection> {
+    if tx.vin.is_empty() {
+        return Err(Rejection::NoInputs);
+    }
+    if tx.vout.is_empty() {
+        return Err(Rejection::NoOutputs);
+    }
 
-            trimmed_tx.extend_from_slice(&hashprevouts);
-            trimmed_tx.extend_from_slice(&hashsequence);
+    // THE SAME OUTPUT CANNOT BE SPENT TWICE, NOT EVEN BY ONE TRANSACTION
+    let mut outpoints: HashSet<OutPoint> = HashSet::new();
+    for (input_index, input) in tx.vin.iter().enumerate() {
+        let outpoint = input.outpoint();
+        if outpoints.contains(&outpoint) {
+            return Err(Rejection::DuplicateInput { input: input_index, outpoint: outpoint.to_string() });
+        }
+        outpoints.insert(outpoint);
+    }
 
-            let mut txid_bytes_reversed_sig = hex::decode(&tx.vin[tx_input_index].txid)?;
-            txid_bytes_reversed_sig.reverse();
+    amounts_check(tx)
+}
 
-            trimmed_tx.extend_from_slice(&txid_bytes_reversed_sig);
-            trimmed_tx.extend(tx.vin[tx_input_index].vout.to_le_bytes());
+// THE CONSENSUS PART OF THE AMOUNTS, ZERO VALUES AND THE FEE FLOOR ARE POLICY
+fn amounts_check(tx: &Transaction) -> std::result::Result<(), Rejection> {
+    let mut s_sats: u64 = 0;
+    let mut r_sats: u64 = 0;
 
-            let witness = tx.vin[tx_input_index].witness.clone().unwrap();
+    // EVERY VALUE AND BOTH TOTALS STAY WITHIN MAX_MONEY, SO NOTHING BELOW CAN OVERFLOW
+    for input_index in 0..tx.vin.len() {
+        s_sats = money_add(s_sats, tx.vin[input_index].prevout.value)?;
+    }
 
-            let witness_script = witness.last().cloned().unwrap();
+    for output_index in 0..tx.vout.len() {
+        r_sats = money_add(r_sats, tx.vout[output_index].value)?;
+    }
 
-            let script_code_bytes = hex::decode(&witness_script)?;
+    if r_sats > s_sats {
+        return Err(Rejection::OutputsExceedInputs { inputs: s_sats, outputs: r_sats });
+    }
+    Ok(())
+}
 
-            trimmed_tx.push(script_code_bytes.len().try_into()?);
-            trimmed_tx.extend_from_slice(&script_code_bytes);
+fn money_add(total: u64, value: u64) -> std::result::Result<u64, Rejection> {
+    match total.checked_add(value) {
+        Some(sum) if value <= MAX_MONEY && sum <= MAX_MONEY => Ok(sum),
+        _ => Err(Rejection::ValueOutOfRange { value }),
+    }
+}
 
-            trimmed_tx.extend(tx.vin[tx_input_index].prevout.value.to_le_bytes());
+// EVERY TRANSACTION THAT PASSED VALIDATION, SERIALISED ONCE SO BLOCK ASSEMBLY CAN USE IT DIRECTLY,
+// AND WHY EVERY OTHER ONE WAS LEFT OUT
+pub struct ValidMempool {
+    pub entries: Vec<TxEntry>,
+    pub rejections: RejectionReport,
+}
 
-            trimmed_tx.extend(tx.vin[tx_input_index].sequence.to_le_bytes());
+impl ValidMempool {
+    // Reads a valid set written earlier by `write`, the rejections are not kept on disk
+    pub fn read(dir: &Path) -> Result<ValidMempool> {
+        Ok(ValidMempool { entries: create_txid_tx_map(dir)?, rejections: RejectionReport::default() })
+    }
 
-            let mut outputs: Vec<u8> = Vec::new();
-            for output in tx.vout.iter() {
-                outputs.extend(output.value.to_le_bytes());
+    // Writes one JSON file per transaction, named like the mempool files, replacing what an earlier run left.
+    // Only files named like ours are removed, and never in the mempool the set was validated from.
+    pub fn write(&self, dir: &Path, mempool_dir: &Path) -> Result<()> {
+        if dir.exists() && fs::canonicalize(dir)? == fs::canonicalize(mempool_dir)? {
+            return Err(failure::err_msg(format!("VALID MEMPOOL {} IS THE MEMPOOL ITSELF", dir.display())));
+        }
 
-                let scriptpubkey_bytes = hex::decode(&output.scriptpubkey)?;
-                outputs.push(scriptpubkey_bytes.len().try_into()?);
-                outputs.extend_from_slice(&scriptpubkey_bytes);
+        fs::create_dir_all(dir)?;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let written_by_us = stem.len() == 64 && stem.bytes().all(|byte| byte.is_ascii_hexdigit());
+            if written_by_us && path.extension().is_some_and(|extension| extension == "json") {
+                fs::remove_file(path)?;
             }
+        }
 
-            let hash_outputs = double_sha256(&outputs);
-
-            trimmed_tx.extend_from_slice(&hash_outputs);
-
-            trimmed_tx.extend(tx.locktime.to_le_bytes());
+        for (txid, transaction, _, _, _) in self.entries.iter() {
+            let file_name = hex::encode(Sha256::digest(hex::decode(txid)?));
+            fs::write(dir.join(format!("{}.json", file_name)), serde_json::to_string(transaction)?)?;
         }
+        Ok(())
     }
-    Ok(trimmed_tx)
 }
 
-pub fn verify_tx(tx: Transaction) -> Result<bool> {
-    let _p2pkh = "p2pkh".to_string();
-    let _p2sh = "p2sh".to_string();
-    let _p2wpkh = "v0_p2wpkh".to_string();
-    let _p2wsh = "v0_p2wsh".to_string();
-    let _p2tr = "v1_p2tr".to_string();
-
-    let tx_type = tx.vin[0].prevout.scriptpubkey_type.clone();
-    let mut v_result = false;
+// Validates one transaction and serialises it once, block assembly reuses the entry. Without the
+// standardness rules nothing may have serialised it yet, one that cannot be is rejected like any other.
+fn accept_tx(transaction: Transaction, policy: &Policy) -> std::result::Result<TxEntry, (Rule, Rejection)> {
+    check_tx_with(&transaction, policy)?;
+    tx_entry(transaction).map_err(|error| (Rule::Consensus, Rejection::Unserialisable { error: error.to_string() }))
+}
 
-    // GAS FEES CHECK
-    if gas_fees_check(&tx) != true {
-        return Ok(false);
+// Validates every transaction in `mempool_dir` against consensus and `policy`, then settles spends of
+// the same output under BIP125. With a UTXO set every input has to spend a coin in it or an output of
+// another mempool transaction, and a coinbase output has to be mature at `height`, otherwise the
+// embedded prevouts are trusted.
+pub fn all_transaction_verification(mempool_dir: &Path, policy: &Policy, utxos: Option<&UtxoSet>, height: u32) -> Result<ValidMempool> {
+    if !mempool_dir.is_dir() {
+        return Err(failure::err_msg(format!("MEMPOOL DIRECTORY {} NOT FOUND", mempool_dir.display())));
     }
 
-    for input_index in 0..tx.vin.len() {
-        if tx.vin[input_index].prevout.scriptpubkey_type != tx_type {
-            return Ok(false);
+    let mut files: HashMap<String, String> = HashMap::new(); // Txid to mempool file, for the report
+    let mut entries: Vec<TxEntry> = Vec::new();
+    let mut rejections = RejectionReport::default();
+
+    // SORTED SO THE RESULT DOES NOT DEPEND ON THE ORDER THE FILESYSTEM LISTS THE FILES IN
+    for entry in WalkDir::new(mempool_dir).sort_by_file_name().into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
         }
-    }
+        let file = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
 
-    if tx_type == _p2pkh {
-        for input_index in 0..tx.vin.len() {
-            match input_verification_p2pkh(tx.clone(), input_index) {
-                Ok(false) => {
-                    return Ok(false);
-                }
-                Ok(true) => {
-                    v_result = true;
-                }
-                Err(_) => {
-                    return Ok(false);
-                }
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(error) => {
+                rejections.push(file, Rule::Consensus, Rejection::Unreadable { error: error.to_string() });
+                continue;
             }
+        };
+        let transaction = match serde_json::from_str::<Transaction>(&contents) {
+            Ok(transaction) => transaction,
+            Err(error) => {
+                rejections.push(file, Rule::Consensus, Rejection::MalformedJson { error: error.to_string() });
+                continue;
+            }
+        };
+
+        match accept_tx(transaction, policy) {
+            Ok(entry) => {
+                files.insert(entry.0.clone(), file);
+                entries.push(entry);
+            }
+            Err((rule, rejection)) => rejections.push(file, rule, rejection),
         }
     }
- 
-    if tx_type == _p2wpkh {
-        for input_index in 0..tx.vin.len() {
-            match input_verification_p2wpkh(input_index, tx.clone()) {
-                Ok(false) => {
-                    return Ok(false);
-                }
 
-                Ok(true) => {
-                    v_result = true;
-                }
-
-                Err(_) => {
-                    return Ok(false);
-                }
+    // ONLY OUTPUTS OF VALID TRANSACTIONS CAN BE SPENT BY ANOTHER ONE
+    let entries = match utxos {
+        Some(utxos) => {
+            let (entries, unspendable) = utxos.check_mempool(entries, height);
+            for (txid, rejection) in unspendable {
+                rejections.push(files[&txid].clone(), Rule::Consensus, rejection);
             }
+            entries
         }
-    }
-    if tx_type == _p2wsh {
-        for input_index in 0..tx.vin.len() {
-            match input_verification_p2wsh(input_index, tx.clone()) {
-                Ok(false) => {
-                    return Ok(false);
-                }
+        None => entries,
+    };
 
-                Ok(true) => {
-                    v_result = true;
-                }
+    // ONLY VALID TRANSACTIONS TAKE PART, AN INVALID ONE CANNOT REPLACE ANYTHING
+    let (entries, conflicts) = resolve_conflicts(entries);
+    for (txid, rejection) in conflicts {
+        rejections.push(files[&txid].clone(), Rule::Policy, rejection);
+    }
+    Ok(ValidMempool { entries, rejections })
+}
 
-                Err(_) => {
-                    return Ok(false);
-                }
-            }
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use secp256k1::{Secp256k1, SecretKey};
+
+    use super::*;
+    use crate::fixtures::load;
+    use crate::transaction::Prevout;
+
+    fn input(txid: &str, vout: u32, sequence: u32, value: u64, scriptpubkey: &str) -> Input {
+        Input {
+            txid: txid.to_string(),
+            vout,
+            prevout: Prevout {
+                scriptpubkey: scriptpubkey.to_string(),
+                scriptpubkey_asm: String::new(),
+                scriptpubkey_type: String::new(),
+                scriptpubkey_address: String::new(),
+                value,
+            },
+            scriptsig: None,
+            scriptsig_asm: None,
+            witness: None,
+            is_coinbase: false,
+            sequence,
+            inner_redeemscript_asm: None,
         }
     }
-    if tx_type == _p2tr {
-        for input in tx.vin.iter() {
-            let witness = input.witness.clone().unwrap();
-            for item in witness {
-                let item_bytes = hex::decode(&item)?;
-                if item_bytes.len() >= 255 {
-                    return Ok(false);
-                }
-            }
+
+    fn output(value: u64, scriptpubkey: &str) -> Output {
+        Output {
+            scriptpubkey: scriptpubkey.to_string(),
+            scriptpubkey_asm: String::new(),
+            scriptpubkey_type: String::new(),
+            scriptpubkey_address: None,
+            value,
         }
+    }
 
-        v_result = true;
+    // SPENDS A P2SH OUTPUT WHOSE REDEEM SCRIPT IS JUST OP_1, NO SIGNATURE HASHES THE TRANSACTION
+    fn unsigned_p2sh_spend() -> Transaction {
+        let scriptpubkey = format!("a914{}87", hex::encode(hash160(&[0x51])));
+        let mut spend = input(&"11".repeat(32), 0, 0xffffffff, 10_000, &scriptpubkey);
+        spend.prevout.scriptpubkey_type = "p2sh".to_string();
+        spend.scriptsig = Some("0151".to_string());
+        Transaction { version: 2, locktime: 0, vin: vec![spend], vout: vec![output(9_000, "51")] }
     }
 
-    Ok(v_result)
-}
+    // SIGNS `tx_input_index` AS A P2PKH SPEND AND RETURNS THE SCRIPT SIG
+    fn sign_p2pkh(tx: &Transaction, tx_input_index: usize, key: &SecretKey, sighash_type: u32) -> Result<String> {
+        let secp = Secp256k1::new();
+        let pubkey = PublicKey::from_secret_key(&secp, key).serialize();
+        let script_code = hex::decode(&tx.vin[tx_input_index].prevout.scriptpubkey)?;
 
+        let sighash = signature_hash(tx, tx_input_index, &script_code, SigVersion::Base, sighash_type)?;
+        let mut signature = secp.sign_ecdsa(&Message::from_digest_slice(&sighash)?, key).serialize_der().to_vec();
+        signature.push(sighash_type as u8);
 
-pub fn verify_tx2(tx: Transaction) -> Result<bool> {
-    let _p2pkh = "p2pkh".to_string();
-    let _p2sh = "p2sh".to_string();
-    let _p2wpkh = "v0_p2wpkh".to_string();
-    let _p2wsh = "v0_p2wsh".to_string();
-    let _p2tr = "v1_p2tr".to_string();
-    let mut s_sats: u64 = 0;
-    let mut r_sats: u64 = 0;
+        let mut scriptsig = script::push_data(&signature);
+        scriptsig.extend(script::push_data(&pubkey));
+        Ok(hex::encode(scriptsig))
+    }
 
-    let tx_type = tx.vin[0].prevout.scriptpubkey_type.clone();
-    let mut v_result = false;
+    #[test]
+    fn test_bip143_native_p2wpkh_vector() -> Result<()> {
+        // FIRST EXAMPLE OF BIP143, THE SECOND INPUT SPENDS A P2WPKH OUTPUT
+        let tx = Transaction {
+            version: 1,
+            locktime: 17,
+            vin: vec![
+                input(
+                    "9f96ade4b41d5433f4eda31e1738ec2b36f6e7d1420d94a6af99801a88f7f7ff",
+                    0,
+                    0xffffffee,
+                    625_000_000,
+                    "2103c9f4836b9a4f77fc0d81f7bcb01b7f1b35916864b9476c241ce9fc198bd25432ac",
+                ),
+                input(
+                    "8ac60eb9575db5b2d987e29f301b5b819ea83a5c6579d282d189cc04b8e151ef",
+                    1,
+                    0xffffffff,
+                    600_000_000,
+                    "00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1",
+                ),
+            ],
+            vout: vec![
+                output(112_340_000, "76a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac"),
+                output(223_450_000, "76a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac"),
+            ],
+        };
+
+        let script_code = hex::decode("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac")?;
+        let sighash = signature_hash(&tx, 1, &script_code, SigVersion::WitnessV0, SIGHASH_ALL)?;
+        assert_eq!(
+            hex::encode(sighash),
+            "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"
+        );
+
+        Ok(())
+    }
 
-    if gas_fees_check(&tx) != true {
-        return Ok(false);
+    #[test]
+    fn test_sighash_flags_in_mempool() -> Result<()> {
+        // P2PKH SIGNED WITH ALL|ANYONECANPAY
+        assert!(verify_tx(load("204ac1129b8c7dd69d0459f57521bf88acbdeb6d57caa84a0b8a391826faf155")?)?);
+        // P2SH MULTISIG WITH A SINGLE|ANYONECANPAY SIGNATURE ON THE THIRD INPUT
+        assert!(verify_tx(load("58ba539524496fefc22b345bd93cadb6decf30327c00527b5d9eb3a02f9c5b5a")?)?);
+        // P2WPKH SIGNED WITH ALL|ANYONECANPAY AND SINGLE|ANYONECANPAY
+        assert!(verify_tx(load("0bfa0482c989e84f5d83e338cff2adaa9bd0bb99e4e276ed7dc913be76f8c543")?)?);
+        assert!(verify_tx(load("004c2dec582638c26fed3d55b2fee8bbf1c2d4b70449b0a3f03faa105ad03f15")?)?);
+        // P2WSH SIGNED WITH NONE|ANYONECANPAY
+        assert!(verify_tx(load("1d792af99801cce57eba95f12d4cff0cc6dcafbb85974931677bbfffa871468f")?)?);
+
+        Ok(())
     }
 
-    if tx_type == _p2pkh {
-        for input_index in 0..tx.vin.len() {
-            match input_verification_p2pkh(tx.clone(), input_index) {
-                Ok(false) => {
-                    return Ok(false);
-                }
-                Ok(true) => {
-                    v_result = true;
-                }
-                Err(_) => {
-                    return Ok(false);
-                }
-            }
-        }
+    #[test]
+    fn test_legacy_none_and_single() -> Result<()> {
+        let key = SecretKey::from_slice(&[0x42; 32])?;
+        let pubkey = PublicKey::from_secret_key(&Secp256k1::new(), &key).serialize();
+        let scriptpubkey = format!("76a914{}88ac", hex::encode(hash160(&pubkey)));
+
+        let mut tx = Transaction {
+            version: 1,
+            locktime: 0,
+            vin: vec![
+                input(&"aa".repeat(32), 0, 0xffffffff, 50_000, &scriptpubkey),
+                input(&"bb".repeat(32), 1, 0xffffffff, 50_000, &scriptpubkey),
+            ],
+            vout: vec![output(90_000, &scriptpubkey)],
+        };
+
+        // THE SECOND INPUT HAS NO MATCHING OUTPUT, ITS SIGHASH_SINGLE SIGNATURE SIGNS ONE
+        let mut one = vec![0u8; 32];
+        one[0] = 1;
+        assert_eq!(signature_hash(&tx, 1, &[], SigVersion::Base, SIGHASH_SINGLE)?, one);
+
+        tx.vin[0].scriptsig = Some(sign_p2pkh(&tx, 0, &key, SIGHASH_NONE)?);
+        tx.vin[1].scriptsig = Some(sign_p2pkh(&tx, 1, &key, SIGHASH_SINGLE)?);
+        assert!(p2pkh::input_verification_p2pkh(tx.clone(), 0, ScriptFlags::CONSENSUS)?);
+        assert!(p2pkh::input_verification_p2pkh(tx.clone(), 1, ScriptFlags::CONSENSUS)?);
+
+        // NEITHER SIGNATURE COMMITS TO THE OUTPUTS OR TO THE OTHER INPUT'S SEQUENCE
+        tx.vout[0].value = 10_000;
+        tx.vin[1].sequence = 0;
+        assert!(p2pkh::input_verification_p2pkh(tx.clone(), 0, ScriptFlags::CONSENSUS)?);
+        assert!(p2pkh::input_verification_p2pkh(tx.clone(), 1, ScriptFlags::CONSENSUS)?);
+
+        // SIGHASH_NONE STILL COMMITS TO THE OTHER OUTPOINTS
+        tx.vin[1].vout = 2;
+        assert!(!p2pkh::input_verification_p2pkh(tx, 0, ScriptFlags::CONSENSUS)?);
+
+        Ok(())
     }
- 
-    if tx_type == _p2wsh {
-        for input_index in 0..tx.vin.len() {
-            match input_verification_p2wsh(input_index, tx.clone()) {
-                Ok(false) => {
-                    return Ok(false);
-                }
-                Ok(true) => {
-                    v_result = true;
-                }
 
-                Err(_) => {
-                    return Ok(false);
-                }
-            }
-        }
+    #[test]
+    fn test_anyonecanpay_single_commits_to_its_output() -> Result<()> {
+        let key = SecretKey::from_slice(&[0x43; 32])?;
+        let pubkey = PublicKey::from_secret_key(&Secp256k1::new(), &key).serialize();
+        let scriptpubkey = format!("76a914{}88ac", hex::encode(hash160(&pubkey)));
+
+        let mut tx = Transaction {
+            version: 2,
+            locktime: 0,
+            vin: vec![input(&"cc".repeat(32), 0, 0xfffffffd, 50_000, &scriptpubkey)],
+            vout: vec![output(40_000, &scriptpubkey)],
+        };
+        tx.vin[0].scriptsig = Some(sign_p2pkh(&tx, 0, &key, SIGHASH_SINGLE | SIGHASH_ANYONECANPAY)?);
+
+        // OTHER PARTIES CAN ADD INPUTS AND OUTPUTS
+        let mut extended = tx.clone();
+        extended.vin.push(input(&"dd".repeat(32), 0, 0, 10_000, &scriptpubkey));
+        extended.vout.push(output(15_000, &scriptpubkey));
+        assert!(p2pkh::input_verification_p2pkh(extended, 0, ScriptFlags::CONSENSUS)?);
+
+        // BUT NOT TOUCH THE MATCHING OUTPUT
+        tx.vout[0].value -= 1;
+        assert!(!p2pkh::input_verification_p2pkh(tx, 0, ScriptFlags::CONSENSUS)?);
+
+        Ok(())
     }
-    if tx_type == _p2tr {
-        for input in tx.vin.iter() {
-            let witness = input.witness.clone().unwrap();
-            for item in witness {
-                let item_bytes = hex::decode(&item)?;
-                if item_bytes.len() <= 255 {
-                    return Ok(false);
-                }
-            }
-        }
-        v_result = true;
+
+    fn is_mixed(tx: &Transaction) -> bool {
+        tx.vin
+            .iter()
+            .any(|input| input.prevout.scriptpubkey_type != tx.vin[0].prevout.scriptpubkey_type)
     }
-    Ok(v_result)
-}
 
-fn gas_fees_check(tx: &Transaction) -> bool {
-    let mut s_sats: u64 = 0;
-    let mut r_sats: u64 = 0;
+    #[test]
+    fn test_verify_p2pkh_and_p2wpkh_inputs() -> Result<()> {
+        let transaction = load("753b075bd84cdc7400acaf8b52cea866f099de2ebb70ea89fc98abe5f1162d5f")?;
+        assert!(is_mixed(&transaction));
+        assert!(verify_tx(transaction.clone())?);
 
-    for input_index in 0..tx.vin.len() {
-        if tx.vin[input_index].prevout.value <= 0 {
-            return false;
-        }
-        s_sats += tx.vin[input_index].prevout.value;
-    }
+        // EACH INPUT IS CHECKED ON ITS OWN, A BAD SEGWIT INPUT FAILS THE WHOLE TX
+        let mut tampered = transaction;
+        tampered.vin[1].prevout.value += 1;
+        assert!(!verify_tx(tampered)?);
 
-    for output_index in 0..tx.vout.len() {
-        if tx.vout[output_index].value <= 0 {
-            return false;
-        }
-        r_sats += tx.vout[output_index].value;
+        Ok(())
     }
 
-    if s_sats - r_sats < 1500 {
-        return false;
-    } else {
-        return true;
+    #[test]
+    fn test_valid_mempool_in_memory_and_on_disk() -> Result<()> {
+        let root = std::env::temp_dir().join(format!("valid_mempool_{}", std::process::id()));
+        let (mempool_dir, valid_dir) = (root.join("mempool"), root.join("valid"));
+        fs::create_dir_all(&mempool_dir)?;
+
+        // TWO VALID TRANSACTIONS, ONE INVALID ONE AND A FILE THAT IS NOT A TRANSACTION AT ALL
+        let valid = [
+            "4e8b1cc2c9ed315153b2457dec94128db0ba0a1dcebc136e187d0c4a0a81ce15",
+            "19175e830784abf518b3ca20319177fcdff20b0bc5253b984a4515e85ec116b4",
+        ];
+        let invalid = "000cb561188c762c81f76976f816829424e2af9e0e491c617b7bf41038df3d35";
+        for txid_hash in valid.iter().chain([&invalid]) {
+            fs::copy(format!("./mempool/{}.json", txid_hash), mempool_dir.join(format!("{}.json", txid_hash)))?;
+        }
+        fs::write(mempool_dir.join("notes.json"), "not a transaction")?;
+
+        let valid_mempool = all_transaction_verification(&mempool_dir, &Policy::default(), None, 900_000)?;
+        let mut txid_hashes: Vec<String> = valid_mempool
+            .entries
+            .iter()
+            .map(|(txid, ..)| hex::encode(Sha256::digest(hex::decode(txid).unwrap())))
+            .collect();
+        txid_hashes.sort();
+        assert_eq!(txid_hashes, vec![valid[1], valid[0]]);
+
+        // BOTH LEFT OUT FILES SAY WHY
+        let mut rejected: Vec<(&str, &str)> = valid_mempool
+            .rejections
+            .rejected
+            .iter()
+            .map(|rejected| (rejected.file.as_str(), rejected.rejection.reason()))
+            .collect();
+        rejected.sort();
+        assert_eq!(rejected, vec![(invalid, "insufficient_fee"), ("notes", "malformed_json")]);
+
+        // WRITING REPLACES WHAT AN EARLIER RUN LEFT BEHIND, AND READS BACK THE SAME ENTRIES
+        fs::create_dir_all(&valid_dir)?;
+        fs::write(valid_dir.join(format!("{}.json", invalid)), "stale")?;
+        fs::write(valid_dir.join("notes.json"), "not ours")?;
+        valid_mempool.write(&valid_dir, &mempool_dir)?;
+        assert!(!valid_dir.join(format!("{}.json", invalid)).exists());
+
+        // A FILE THE WRITER DID NOT NAME IS LEFT ALONE
+        assert_eq!(fs::read_to_string(valid_dir.join("notes.json"))?, "not ours");
+        fs::remove_file(valid_dir.join("notes.json"))?;
+
+        // THE MEMPOOL ITSELF, HOWEVER IT IS SPELLED, IS NEVER WRITTEN TO
+        assert!(valid_mempool.write(&valid_dir.join("../mempool"), &mempool_dir).is_err());
+        assert!(mempool_dir.join(format!("{}.json", invalid)).exists());
+
+        let mut read_back = ValidMempool::read(&valid_dir)?.entries;
+        let mut entries = valid_mempool.entries;
+        read_back.sort_by(|a, b| a.0.cmp(&b.0));
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        for (read, written) in read_back.iter().zip(entries.iter()) {
+            assert_eq!((&read.0, &read.2, read.3, read.4), (&written.0, &written.2, written.3, written.4));
+        }
+        assert_eq!(read_back.len(), 2);
+
+        // WITHOUT POLICY NOTHING LOOKS AT AN OUTPUT SCRIPT, ONE THAT IS NOT HEX ONLY FAILS TO SERIALISE.
+        // THAT FILE IS REJECTED, THE REST OF THE MEMPOOL IS STILL VALIDATED.
+        let mut transaction = unsigned_p2sh_spend();
+        transaction.vout[0].scriptpubkey = "zz".to_string();
+        fs::write(mempool_dir.join("unserialisable.json"), serde_json::to_string(&transaction)?)?;
+        let valid_mempool = all_transaction_verification(&mempool_dir, &Policy::consensus(), None, 900_000)?;
+        assert_eq!(valid_mempool.entries.len(), 3);
+        let rejected = valid_mempool.rejections.rejected.iter().find(|rejected| rejected.file == "unserialisable").unwrap();
+        assert_eq!((rejected.rule, rejected.rejection.reason()), (Rule::Consensus, "unserialisable"));
+
+        // A MISSING MEMPOOL IS AN ERROR, NOT AN EMPTY BLOCK
+        assert!(all_transaction_verification(&root.join("missing"), &Policy::default(), None, 900_000).is_err());
+
+        fs::remove_dir_all(root)?;
+        Ok(())
     }
-}
-
-pub fn all_transaction_verification() -> Result<()> {
-    let mempool_dir = "./mempool";
-
-    let mut spends: HashMap<String, String> = HashMap::new();
 
-    'outer: for entry in WalkDir::new(mempool_dir).into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        if path.is_file() {
-            match fs::read_to_string(path) {
-                Ok(contents) => match serde_json::from_str::<Transaction>(&contents) {
-                    Ok(transaction) => {
-                        for input in &transaction.vin {
-                            let input_key = format!("{}{}", input.txid, input.vout);
-
-                            match spends.get(&input_key) {
-                                Some(existing_txid)
-                                    if path.display().to_string() != *existing_txid =>
-                                {
-                                    continue 'outer;
-                                }
-                                _ => {
-                                    spends.insert(input_key, path.display().to_string());
-                                }
-                            }
-                        }
-                        let result = verify_tx(transaction)?;
-                        if result == true {
-                            if let Some(filename) = path.file_name() {
-                                let valid_mempool_dir = Path::new("./valid-mempool");
-                                let destination_path = valid_mempool_dir.join(filename);
-                                fs::copy(&path, &destination_path)?;
-                            }
-                        }
+    #[test]
+    fn test_verify_mixed_input_types() -> Result<()> {
+        let mut s_count = 0;
+        let mut f_count = 0;
+
+        for entry in fs::read_dir("./mempool")? {
+            let contents = fs::read_to_string(entry?.path())?;
+            if let Ok(transaction) = serde_json::from_str::<Transaction>(&contents) {
+                if is_mixed(&transaction) {
+                    if verify_tx(transaction)? {
+                        s_count += 1;
+                    } else {
+                        f_count += 1;
                     }
-                    Err(_e) => {}
-                },
-                Err(_e) => {}
+                }
             }
         }
+
+        println!("success: {}", s_count);
+        println!("failure: {}", f_count);
+
+        assert!(s_count > f_count);
+
+        Ok(())
     }
-    Ok(())
 }
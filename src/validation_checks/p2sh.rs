@@ -1,357 +1,186 @@
-use std::vec; // Import the standard `vec` module.
-
-use hex; // Import the `hex` crate for hexadecimal encoding and decoding.
-use log::info; // Import the `info` function from the `log` crate for logging purposes.
-
-use crate::validation_checks::hash160; // Import the `hash160` function from the `validation_checks` module.
-use crate::validation_checks::op_checkmultisig; // Import the `op_checkmultisig` function from the `validation_checks` module.
-use crate::validation_checks::op_checksig; // Import the `op_checksig` function from the `validation_checks` module.
+use crate::validation_checks::p2wpkh::script_execution_p2wpkh; // Import the P2WPKH witness execution for nested segwit.
+use crate::validation_checks::p2wsh::script_execution_p2wsh; // Import the P2WSH witness execution for nested segwit.
+use crate::validation_checks::script::{
+    cast_to_bool, push_data, Script, ScriptContext, ScriptError, ScriptErrorKind, ScriptFlags, SigVersion,
+}; // Import the script interpreter.
+use crate::validation_checks::witness_items; // Import the witness decoding helper.
 
 use crate::{error::Result, transaction::Transaction}; // Import the `Result` type and `Transaction` struct from the crate.
 
-pub fn input_verification_p2sh(tx_input_index: usize, tx: Transaction) -> Result<bool> {
-    let scriptpubkey_asm = tx.vin[tx_input_index].prevout.scriptpubkey_asm.clone(); // Clone the script pubkey ASM.
+pub fn input_verification_p2sh(tx_input_index: usize, tx: Transaction, flags: ScriptFlags) -> Result<bool> {
+    let scriptpubkey = Script::from_hex(&tx.vin[tx_input_index].prevout.scriptpubkey)?; // Decode the script pubkey.
 
-    let witness = match tx.vin[tx_input_index].witness.clone() { // Match and clone the witness data.
-        Some(value) => value,
-        None => Vec::new(), // If witness data is None, create an empty Vec.
-    };
+    let witness = witness_items(&tx, tx_input_index)?; // Decode the witness data, empty for legacy spends.
 
-    let scriptsig_asm = match tx.vin[tx_input_index].scriptsig_asm.clone() { // Match and clone the scriptsig ASM.
-        Some(value) => value,
+    let scriptsig = match tx.vin[tx_input_index].scriptsig.clone() { // Match and decode the scriptsig.
+        Some(value) => Script::from_hex(&value)?,
         None => {
-            return Ok(false); // If scriptsig ASM is None, return false.
+            return Ok(false); // If scriptsig is None, return false.
         }
     };
 
-
-    let inner_redeemscript_asm = match tx.vin[tx_input_index].inner_redeemscript_asm.clone() {
-        Some(value) => value,
-        None => {
-            return Ok(false);
-        }
-    };
-
-    Ok(script_execution_p2sh(
-        scriptpubkey_asm,
-        witness,
-        scriptsig_asm,
-        inner_redeemscript_asm,
-        tx,
-        tx_input_index,
-    )?)
+    script_execution_p2sh(&scriptpubkey, witness, &scriptsig, &tx, tx_input_index, flags)
 }
 
 fn script_execution_p2sh(
-    scriptpubkey_asm: String,
-    witness: Vec<String>,
-    scriptsig_asm: String,
-    inner_redeemscript_asm: String,
-    tx: Transaction,
+    scriptpubkey: &Script,
+    witness: Vec<Vec<u8>>,
+    scriptsig: &Script,
+    tx: &Transaction,
     tx_input_index: usize,
+    flags: ScriptFlags,
 ) -> Result<bool> {
-    let mut script_result: bool = false;
-    let input_type: &str;
-
-    if witness.len() == 0 {
-        input_type = "NON_SEGWIT";
-    } else if witness.len() == 2 {
-        input_type = "P2SH-P2WPKH";
-    } else {
-        input_type = "P2SH-P2WSH";
+    // THE SCRIPT SIG OF A P2SH SPEND MAY ONLY PUSH DATA
+    if !scriptsig.is_push_only() {
+        return Err(ScriptError::new(ScriptErrorKind::SigPushOnly).into());
     }
 
+    let ctx = ScriptContext {
+        tx,
+        input_index: tx_input_index,
+        sig_version: SigVersion::Base,
+        tapscript: None,
+        flags,
+    };
+
     let mut stack = Vec::new();
+    scriptsig.execute(&mut stack, &ctx)?;
 
-    let scriptsig_asm_opcodes: Vec<&str> = scriptsig_asm.split_whitespace().collect();
-    for opcode_index in 0..scriptsig_asm_opcodes.len() {
-        let is_pushbytes = scriptsig_asm_opcodes[opcode_index].starts_with("OP_PUSHBYTES");
-        let is_pushdata = scriptsig_asm_opcodes[opcode_index].starts_with("OP_PUSHDATA");
+    // KEEP THE STACK AS THE SCRIPT SIG LEFT IT, THE REDEEM SCRIPT RUNS ON IT
+    let mut redeem_stack = stack.clone();
 
-        match scriptsig_asm_opcodes[opcode_index] {
-            "OP_0" => {
-                stack.push(vec![0 as u8]);
-            }
-            _ if is_pushbytes => {
-                stack.push(hex::decode(&scriptsig_asm_opcodes[opcode_index + 1])?);
-            }
-
-            _ if is_pushdata => {
-                stack.push(hex::decode(&scriptsig_asm_opcodes[opcode_index + 1])?);
-            }
-            _ => continue,
-        }
+    // OP_HASH160 <SCRIPT HASH> OP_EQUAL
+    scriptpubkey.execute(&mut stack, &ctx)?;
+    if !stack.last().map(|top| cast_to_bool(top)).unwrap_or(false) {
+        return Ok(false);
     }
 
-    let scriptpubkey_asm_opcodes: Vec<&str> = scriptpubkey_asm.split_whitespace().collect();
-    for opcode_index in 0..scriptpubkey_asm_opcodes.len() {
-        match scriptpubkey_asm_opcodes[opcode_index] {
-            "OP_HASH160" => {
-                let hash = hash160(&stack.pop().expect("STACK UNDERFLOW: OP_HASH160"));
-                stack.push(hash);
-            }
-            "OP_PUSHBYTES_20" => {
-                stack.push(
-                    hex::decode(&scriptpubkey_asm_opcodes[opcode_index + 1])
-                        .expect("DECODING: FAILED"),
-                );
-            }
-            "OP_EQUAL" => {
-                let a = stack.pop().expect("STACK UNDERFLOW: OP_EQUAL");
-                let b = stack.pop().expect("STACK UNDERFLOW: OP_EQUAL");
+    let redeem_script = match redeem_stack.pop() {
+        Some(bytes) => Script::new(bytes),
+        None => return Ok(false),
+    };
 
-                if a != b {
-                    return Ok(false);
-                }
+    match redeem_script.witness_program() {
+        // NESTED SEGWIT: THE SCRIPT SIG MUST BE EXACTLY THE PUSH OF THE REDEEM SCRIPT
+        Some((version, program)) => {
+            if scriptsig.as_bytes() != push_data(redeem_script.as_bytes()) {
+                return Err(ScriptError::new(ScriptErrorKind::WitnessMalleatedP2sh).into());
             }
-            _ => continue,
-        }
-    }
-
-
-    if input_type == "NON_SEGWIT" {
 
-        let inner_redeemscript_asm_opcodes: Vec<&str> =
-            inner_redeemscript_asm.split_whitespace().collect();
+            match (version, program.len()) {
+                // P2SH-P2WPKH
+                (0, 20) => script_execution_p2wpkh(witness, program, tx, tx_input_index, flags),
 
-        for opcode_index in 0..inner_redeemscript_asm_opcodes.len() {
-            let is_pushbytes =
-                inner_redeemscript_asm_opcodes[opcode_index].starts_with("OP_PUSHBYTES");
+                // P2SH-P2WSH
+                (0, 32) => script_execution_p2wsh(witness, program, tx, tx_input_index, flags),
 
-            let is_pushdata =
-                inner_redeemscript_asm_opcodes[opcode_index].starts_with("OP_PUSHDATA");
+                (0, _) => Err(ScriptError::new(ScriptErrorKind::WitnessProgramWrongLength).into()),
 
-            let is_equal = inner_redeemscript_asm_opcodes[opcode_index].starts_with("OP_EQUAL");
-
-            match inner_redeemscript_asm_opcodes[opcode_index] {
-                "OP_PUSHNUM_2" => stack.push(vec![2u8]),
-                "OP_PUSHNUM_3" => stack.push(vec![3u8]),
-                "OP_PUSHNUM_4" => stack.push(vec![4u8]),
-
-                _ if is_pushbytes => {
-                    stack.push(
-                        hex::decode(&inner_redeemscript_asm_opcodes[opcode_index + 1])
-                            .expect("DECODING: FAILED"),
-                    );
-                }
-
-                _ if is_pushdata => {
-                    stack.push(
-                        hex::decode(&inner_redeemscript_asm_opcodes[opcode_index + 1])
-                            .expect("DECODING: FAILED"),
-                    );
-                }
-
-                "OP_0" => {
-                    stack.push(vec![0 as u8]);
-                }
-
-                "OP_CSV" => {
-                    continue;
-                }
-
-                "OP_DROP" => {
-                    stack.pop().expect("SATCK UNDERFLOW: OP_DROP");
-                }
-
-                "OP_DUP" => stack.push(stack.last().cloned().expect("STACK UNDERFLOW")),
-
-                "OP_HASH160" => {
-                    let pk = stack.pop().expect("STACK UNDERFLOW");
-                    stack.push(hash160(&pk));
-                }
-
-                _ if is_equal => {
-                    let a = stack.pop().expect("STACK UNDERFLOW: OP_EQUALVERIFY");
-                    let b = stack.pop().expect("STACK UNDERFLOW: OP_EQUALVERIFY");
-
-                    if a == b {
-                        script_result = true;
-                    } else {
-                        return Ok(false);
-                    }
-                }
-
-                "OP_CHECKSIGVERIFY" => {
-                    let result = op_checksig(&mut stack, tx.clone(), tx_input_index, input_type)?;
-
-                    if result == false {
-                        return Ok(false);
-                    }
-                }
-
-                "OP_DEPTH" => {
-                    stack.push(vec![0 as u8]);
-                }
-
-                "OP_CHECKSIG" => {
-                    script_result =
-                        op_checksig(&mut stack, tx.clone(), tx_input_index, input_type)?;
-                }
-
-                "OP_CHECKMULTISIG" => {
-                    script_result =
-                        op_checkmultisig(&mut stack, tx.clone(), tx_input_index, input_type)?;
-                }
-                _ => continue,
+                // HIGHER WITNESS VERSIONS ARE RESERVED FOR FUTURE SOFT FORKS
+                _ => Ok(true),
             }
         }
-    }
-
-    if input_type == "P2SH-P2WPKH" {
-        stack.push(hex::decode(&witness[0]).expect("DECODING: FAILED"));
-
-        stack.push(hex::decode(&witness[1]).expect("DECODING: FAILED"));
-        stack.push(stack.last().cloned().expect("STACK UNDERFLOW"));
-        let pk = stack.pop().expect("STACK UNDERFLOW");
-        stack.push(hash160(&pk));
 
-        let inner_redeemscript_opcodes: Vec<&str> =
-            inner_redeemscript_asm.split_whitespace().collect();
-
-        for opcode_index in 0..inner_redeemscript_opcodes.len() {
-            match inner_redeemscript_opcodes[opcode_index] {
-                "OP_0" => info!("SEGWIT VERSION: 0"),
-
-                "OP_PUSHBYTES_20" => {
-                    stack.push(
-                        hex::decode(&inner_redeemscript_opcodes[opcode_index + 1])
-                            .expect("DECODING: FAILED"),
-                    );
-                }
-                _ => continue,
+        // LEGACY P2SH: RUN THE REDEEM SCRIPT ON THE REMAINING STACK
+        None => {
+            if !witness.is_empty() {
+                return Err(ScriptError::new(ScriptErrorKind::WitnessUnexpected).into());
             }
-        }
-
-        let a = stack.pop().expect("STACK UNDERFLOW");
-        let b = stack.pop().expect("STACK UNDERFLOW");
 
-        if a != b {
-            return Ok(false);
+            redeem_script.execute(&mut redeem_stack, &ctx)?;
+            if flags.clean_stack && redeem_stack.len() != 1 {
+                return Err(ScriptError::new(ScriptErrorKind::CleanStack).into());
+            }
+            Ok(redeem_stack.last().map(|top| cast_to_bool(top)).unwrap_or(false))
         }
-
-        script_result = op_checksig(&mut stack, tx.clone(), tx_input_index, input_type)?;
     }
+}
 
-    if input_type == "P2SH-P2WSH" {
-        for index in 0..witness.len() - 1 {
-            stack.push(hex::decode(&witness[index])?);
-        }
-        let witness_script_bytes = hex::decode(&witness.last().cloned().expect("SCRIPT MISSING"))?;
-
-        let mut index = 0;
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use std::fs;
 
-        while index < witness_script_bytes.len() {
-            let opcode = witness_script_bytes[index];
-            index += 1;
+    use super::*;
+    use crate::fixtures::load;
+    use crate::validation_checks::verify_tx;
 
-            match opcode {
-                82 => {
-                    stack.push(vec![2u8]);
-                }
-                _ if opcode <= 75 as u8 && opcode >= 1 as u8 => {
-                    if index + opcode as usize <= witness_script_bytes.len() {
-                        let bytes = witness_script_bytes[index..index + opcode as usize].to_vec();
-                        stack.push(bytes);
-                        index += opcode as usize;
-                    }
-                }
+    fn all_inputs_valid(tx: &Transaction) -> bool {
+        (0..tx.vin.len()).all(|input_index| matches!(input_verification_p2sh(input_index, tx.clone(), ScriptFlags::CONSENSUS), Ok(true)))
+    }
 
-                83 => {
-                    stack.push(vec![3u8]);
-                }
+    #[test]
+    fn test_legacy_p2sh_multisig() -> Result<()> {
+        // 2-OF-3 MULTISIG REDEEM SCRIPT IN THE SCRIPT SIG
+        let tx = load("19175e830784abf518b3ca20319177fcdff20b0bc5253b984a4515e85ec116b4")?;
+        assert!(all_inputs_valid(&tx));
+        assert!(verify_tx(tx)?);
 
-                174 => {
-                    script_result =
-                        op_checkmultisig(&mut stack, tx.clone(), tx_input_index, input_type)?;
+        let tx = load("267370f223608af9a8fcb03f1b5aae1b8672ab21ff4764589c9ea992c21747e4")?;
+        assert!(all_inputs_valid(&tx));
+        assert!(verify_tx(tx)?);
 
-                    if script_result == true {
-                        stack.push(vec![1u8]);
-                    } else {
-                        stack.push(vec![0u8])
-                    }
-                }
-
-                173 => {
-                    let result = op_checksig(&mut stack, tx.clone(), tx_input_index, input_type)?;
+        Ok(())
+    }
 
-                    if result == true {
-                        stack.push(vec![1u8]);
-                    } else {
-                        stack.push(vec![0u8]);
-                    }
+    #[test]
+    fn test_p2sh_p2wpkh() -> Result<()> {
+        let tx = load("019731eeb5a97dee2f5ee4e3dcfe9fdb27602a64d7a305727b616585197f521a")?;
+        assert!(all_inputs_valid(&tx));
+        assert!(verify_tx(tx)?);
 
-                    let top = stack.pop().unwrap();
-                    if top == vec![1u8] {
-                        script_result = true;
-                        continue;
-                    } else {
-                        return Ok(false);
-                    }
-                }
+        Ok(())
+    }
 
-                172 => {
-                    let sig_length = stack[stack.len() - 1].len();
+    #[test]
+    fn test_p2sh_p2wsh() -> Result<()> {
+        let tx = load("1e3e911e4c25ae4b420ea062f238156d5243cc21d01d6ff81f248c944b8d58a4")?;
+        assert!(all_inputs_valid(&tx));
+        assert!(verify_tx(tx)?);
 
-                    if sig_length <= 75 && sig_length >= 70 {
-                        script_result =
-                            op_checksig(&mut stack, tx.clone(), tx_input_index, input_type)?;
+        Ok(())
+    }
 
-                        if script_result == true {
-                            stack.push(vec![1u8]);
-                        } else {
-                            stack.push(vec![0u8])
-                        }
-                    } else {
-                        stack.push(vec![0u8]);
-                    }
-                }
+    #[test]
+    fn test_p2sh_tampered_spends_fail() -> Result<()> {
+        // A DIFFERENT PUBLIC KEY HASH IN THE REDEEM SCRIPT NO LONGER MATCHES THE SCRIPT HASH
+        let mut tx = load("019731eeb5a97dee2f5ee4e3dcfe9fdb27602a64d7a305727b616585197f521a")?;
+        let scriptsig = tx.vin[0].scriptsig.clone().unwrap().replace("839aed", "839aee");
+        tx.vin[0].scriptsig = Some(scriptsig);
+        assert!(!all_inputs_valid(&tx));
+        assert!(!verify_tx(tx)?);
+
+        // A WITNESS SIGNATURE THAT DOES NOT COMMIT TO THE SPENT AMOUNT
+        let mut tx = load("1e3e911e4c25ae4b420ea062f238156d5243cc21d01d6ff81f248c944b8d58a4")?;
+        tx.vin[0].prevout.value += 1;
+        assert!(!all_inputs_valid(&tx));
+
+        Ok(())
+    }
 
-                100 => {
-                    // OP_NOTIF
-                    if stack.last().cloned().unwrap_or(vec![254u8]) == vec![0u8] {
+    #[test]
+    fn test_script_execution_p2sh() -> Result<()> {
+        let mut s_count = 0;
+        let mut f_count = 0;
+
+        for entry in fs::read_dir("./mempool")? {
+            let contents = fs::read_to_string(entry?.path())?;
+            if let Ok(transaction) = serde_json::from_str::<Transaction>(&contents) {
+                if transaction.vin.iter().all(|input| input.prevout.scriptpubkey_type == "p2sh") {
+                    if all_inputs_valid(&transaction) {
+                        s_count += 1;
                     } else {
-                        if witness_script_bytes[index] <= 75 {
-                            if witness_script_bytes[index] >= 1 {
-                                index += witness_script_bytes[index] as usize;
-                            }
-                        } else if witness_script_bytes[index] == 103 {
-                            // EXECUTE THE NEXT STATEMENT
-                        } else if witness_script_bytes[index] == 104 {
-                            stack.pop();
-                            continue;
-                        }
+                        f_count += 1;
                     }
                 }
+            }
+        }
 
-                99 => {
-                    // OP_IF
-                    if stack.last().cloned().unwrap_or(vec![254u8]) == vec![1u8] {
-                    } else {
-                        if witness_script_bytes[index] <= 75 {
-                            if witness_script_bytes[index] >= 1 {
-                                index += witness_script_bytes[index] as usize;
-                            }
-                        } else if witness_script_bytes[index] == 103 {
-                            // EXECUTE THE NEXT STATEMENT
-                        } else if witness_script_bytes[index] == 104 {
-                            stack.pop();
-                            continue;
-                        }
-                    }
-                }
+        println!("success: {}", s_count);
+        println!("failure: {}", f_count);
 
-                115 => {
-                    // OP_IFDUP
-                    if stack.last().cloned().unwrap_or(vec![254u8]) != vec![0u8] {
-                        stack.push(stack.last().cloned().expect("STACK UNDERFLOW"))
-                    }
-                }
+        assert!(s_count > f_count);
 
-                _ => continue,
-            }
-        }
+        Ok(())
     }
-    Ok(script_result)
 }
-
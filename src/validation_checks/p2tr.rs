@@ -0,0 +1,539 @@
+// OPERATE ON THE P2TR (BIP341) INPUTS
+use secp256k1::{schnorr::Signature, Message, Parity, Scalar, XOnlyPublicKey};
+
+use crate::validation_checks::opcodes::is_op_success; // Importing the OP_SUCCESSx table
+use crate::validation_checks::script::{
+    Script, ScriptContext, ScriptError, ScriptErrorKind, ScriptFlags, SigVersion, TapscriptExecution,
+    MAX_STACK_SIZE,
+}; // Importing the script interpreter
+use crate::validation_checks::{
+    execute_witness_stack, secp, serialise_output, serialise_outputs, serialise_prevouts,
+    serialise_sequences, single_sha256, witness_items, SIGHASH_ALL, SIGHASH_ANYONECANPAY,
+    SIGHASH_SINGLE,
+}; // Importing shared helpers from validation_checks module
+
+use crate::{compact_size, error::Result, transaction::Transaction}; // Importing Result type and Transaction struct from crate
+
+// LEAF VERSION OF BIP342 TAPSCRIPTS
+pub const TAPROOT_LEAF_TAPSCRIPT: u8 = 0xc0;
+const TAPROOT_LEAF_MASK: u8 = 0xfe;
+
+// CONTROL BLOCK: <LEAF VERSION | PARITY> <INTERNAL KEY> <0..128 MERKLE PATH NODES>
+const TAPROOT_CONTROL_BASE_SIZE: usize = 33;
+const TAPROOT_CONTROL_NODE_SIZE: usize = 32;
+const TAPROOT_CONTROL_MAX_NODE_COUNT: usize = 128;
+
+// FIRST BYTE OF THE OPTIONAL LAST WITNESS ITEM RESERVED FOR FUTURE USE
+const ANNEX_TAG: u8 = 0x50;
+
+// EVERY TAPSCRIPT SIGNATURE CHECK SPENDS 50 OF THE 50 + WITNESS SIZE BUDGET
+pub const VALIDATION_WEIGHT_PER_SIGOP_PASSED: i64 = 50;
+const VALIDATION_WEIGHT_OFFSET: i64 = 50;
+
+pub fn input_verification_p2tr(tx_input_index: usize, tx: Transaction, flags: ScriptFlags) -> Result<bool> {
+    let witness = witness_items(&tx, tx_input_index)?; // Extracting witness data from transaction input
+
+    if !tx.vin[tx_input_index].scriptsig.clone().unwrap_or_default().is_empty() { // Checking if scriptsig is not empty
+        return Err(ScriptError::new(ScriptErrorKind::WitnessMalleated).into()); // Native segwit inputs must have an empty scriptsig
+    }
+
+    let scriptpubkey = Script::from_hex(&tx.vin[tx_input_index].prevout.scriptpubkey)?; // Decoding the scriptpubkey
+    let output_key = match scriptpubkey.witness_program() { // Extracting the 32 byte output key
+        Some((1, program)) if program.len() == 32 => program.to_vec(),
+        _ => return Ok(false),
+    };
+
+    script_execution_p2tr(witness, &output_key, &tx, tx_input_index, flags)
+}
+
+pub fn script_execution_p2tr(
+    mut witness: Vec<Vec<u8>>,
+    output_key: &[u8],
+    tx: &Transaction,
+    tx_input_index: usize,
+    flags: ScriptFlags,
+) -> Result<bool> {
+    if witness.is_empty() {
+        return Err(ScriptError::new(ScriptErrorKind::WitnessProgramWitnessEmpty).into());
+    }
+
+    // STRIP THE ANNEX, SIGNATURES STILL COMMIT TO IT
+    let annex = match witness.last() {
+        Some(last) if witness.len() >= 2 && last.first() == Some(&ANNEX_TAG) => witness.pop(),
+        _ => None,
+    };
+
+    // KEY PATH: A SINGLE SCHNORR SIGNATURE FOR THE OUTPUT KEY
+    if witness.len() == 1 {
+        let valid = check_schnorr_signature(&witness[0], output_key, tx, tx_input_index, annex.as_deref(), None)
+            .map_err(ScriptError::new)?;
+        if !valid {
+            return Err(ScriptError::new(ScriptErrorKind::SchnorrSig).into());
+        }
+        return Ok(true);
+    }
+
+    // SCRIPT PATH: <SCRIPT INPUTS..> <SCRIPT> <CONTROL BLOCK>
+    let control_block = witness.pop().unwrap_or_default();
+    let script_bytes = witness.pop().unwrap_or_default();
+
+    if control_block.len() < TAPROOT_CONTROL_BASE_SIZE
+        || control_block.len() > TAPROOT_CONTROL_BASE_SIZE + TAPROOT_CONTROL_NODE_SIZE * TAPROOT_CONTROL_MAX_NODE_COUNT
+        || !(control_block.len() - TAPROOT_CONTROL_BASE_SIZE).is_multiple_of(TAPROOT_CONTROL_NODE_SIZE)
+    {
+        return Err(ScriptError::new(ScriptErrorKind::TaprootWrongControlSize).into());
+    }
+
+    let leaf_version = control_block[0] & TAPROOT_LEAF_MASK;
+    let leaf_hash = tapleaf_hash(leaf_version, &script_bytes);
+
+    if !verify_taproot_commitment(&control_block, output_key, &leaf_hash) {
+        return Err(ScriptError::new(ScriptErrorKind::WitnessProgramMismatch).into());
+    }
+
+    // UNKNOWN LEAF VERSIONS ARE RESERVED FOR FUTURE SOFT FORKS
+    if leaf_version != TAPROOT_LEAF_TAPSCRIPT {
+        return Ok(true);
+    }
+
+    // ANY OP_SUCCESSx MAKES THE SCRIPT SUCCEED, AS LONG AS EVERYTHING BEFORE IT DECODES
+    let tapscript = Script::new(script_bytes);
+    for instruction in tapscript.instructions() {
+        if is_op_success(instruction?.opcode) {
+            return Ok(true);
+        }
+    }
+
+    if witness.len() > MAX_STACK_SIZE {
+        return Err(ScriptError::new(ScriptErrorKind::StackSize).into());
+    }
+
+    let ctx = ScriptContext {
+        tx,
+        input_index: tx_input_index,
+        sig_version: SigVersion::Tapscript,
+        tapscript: Some(TapscriptExecution {
+            leaf_hash,
+            annex,
+            validation_weight: VALIDATION_WEIGHT_OFFSET + witness_size(tx, tx_input_index)? as i64,
+        }),
+        flags,
+    };
+
+    // THE REMAINING WITNESS ITEMS ARE THE INITIAL STACK
+    execute_witness_stack(&tapscript, witness, &ctx)
+}
+
+// VERIFIES A 64 OR 65 BYTE BIP340 SIGNATURE, RETURNS AN ERROR IF IT IS MALFORMED
+pub fn check_schnorr_signature(
+    signature: &[u8],
+    pubkey: &[u8],
+    tx: &Transaction,
+    tx_input_index: usize,
+    annex: Option<&[u8]>,
+    leaf: Option<(&[u8], u32)>, // Tapleaf hash and last executed OP_CODESEPARATOR position
+) -> std::result::Result<bool, ScriptErrorKind> {
+    // A MISSING SIGHASH BYTE MEANS SIGHASH_DEFAULT, AN EXPLICIT 0x00 IS NOT ALLOWED
+    let (sig_bytes, hash_type) = match signature.len() {
+        64 => (signature, 0x00),
+        65 if signature[64] != 0x00 => (&signature[..64], signature[64]),
+        65 => return Err(ScriptErrorKind::SchnorrSigHashtype),
+        _ => return Err(ScriptErrorKind::SchnorrSigSize),
+    };
+
+    if !matches!(hash_type, 0x00..=0x03 | 0x81..=0x83) {
+        return Err(ScriptErrorKind::SchnorrSigHashtype);
+    }
+
+    let pubkey = match XOnlyPublicKey::from_slice(pubkey) {
+        Ok(pubkey) => pubkey,
+        Err(_) => return Ok(false),
+    };
+
+    let sig = match Signature::from_slice(sig_bytes) {
+        Ok(sig) => sig,
+        Err(_) => return Ok(false),
+    };
+
+    let sighash = taproot_sighash(tx, tx_input_index, hash_type, annex, leaf).map_err(|_| ScriptErrorKind::SchnorrSig)?;
+    let msg = Message::from_digest_slice(&sighash).map_err(|_| ScriptErrorKind::SchnorrSig)?;
+
+    Ok(secp().verify_schnorr(&sig, &msg, &pubkey).is_ok())
+}
+
+// BIP341 SIGNATURE MESSAGE, HASHED UNDER THE "TapSighash" TAG
+pub fn taproot_sighash(
+    tx: &Transaction,
+    tx_input_index: usize,
+    hash_type: u8,
+    annex: Option<&[u8]>,
+    leaf: Option<(&[u8], u32)>,
+) -> Result<Vec<u8>> {
+    let input = &tx.vin[tx_input_index];
+    let output_type = if hash_type == 0x00 { SIGHASH_ALL } else { hash_type as u32 & 0x03 };
+    let anyone_can_pay = hash_type as u32 & SIGHASH_ANYONECANPAY != 0;
+
+    // SIGHASH EPOCH
+    let mut sig_msg: Vec<u8> = vec![0x00];
+
+    // CONTROL
+    sig_msg.push(hash_type);
+    sig_msg.extend(tx.version.to_le_bytes());
+    sig_msg.extend(tx.locktime.to_le_bytes());
+
+    // TRANSACTION DATA, SINGLE SHA256 UNLIKE BIP143
+    if !anyone_can_pay {
+        let mut amounts: Vec<u8> = Vec::new();
+        let mut scriptpubkeys: Vec<u8> = Vec::new();
+        for spent in tx.vin.iter() {
+            amounts.extend(spent.prevout.value.to_le_bytes());
+
+            let scriptpubkey = hex::decode(&spent.prevout.scriptpubkey)?;
+            scriptpubkeys.extend(compact_size::encode(scriptpubkey.len()));
+            scriptpubkeys.extend_from_slice(&scriptpubkey);
+        }
+
+        sig_msg.extend(single_sha256(&serialise_prevouts(tx)?));
+        sig_msg.extend(single_sha256(&amounts));
+        sig_msg.extend(single_sha256(&scriptpubkeys));
+        sig_msg.extend(single_sha256(&serialise_sequences(tx)));
+    }
+
+    if output_type == SIGHASH_ALL {
+        sig_msg.extend(single_sha256(&serialise_outputs(tx)?));
+    }
+
+    // DATA ABOUT THIS INPUT
+    let spend_type = (leaf.is_some() as u8) << 1 | annex.is_some() as u8;
+    sig_msg.push(spend_type);
+
+    if anyone_can_pay {
+        let mut txid_bytes_reversed = hex::decode(&input.txid)?;
+        txid_bytes_reversed.reverse();
+
+        let scriptpubkey = hex::decode(&input.prevout.scriptpubkey)?;
+
+        sig_msg.extend_from_slice(&txid_bytes_reversed);
+        sig_msg.extend(input.vout.to_le_bytes());
+        sig_msg.extend(input.prevout.value.to_le_bytes());
+        sig_msg.extend(compact_size::encode(scriptpubkey.len()));
+        sig_msg.extend_from_slice(&scriptpubkey);
+        sig_msg.extend(input.sequence.to_le_bytes());
+    } else {
+        sig_msg.extend((tx_input_index as u32).to_le_bytes());
+    }
+
+    if let Some(annex) = annex {
+        let mut serialised_annex = compact_size::encode(annex.len());
+        serialised_annex.extend_from_slice(annex);
+        sig_msg.extend(single_sha256(&serialised_annex));
+    }
+
+    // DATA ABOUT THIS OUTPUT
+    if output_type == SIGHASH_SINGLE {
+        let output = match tx.vout.get(tx_input_index) {
+            Some(output) => output,
+            None => return Err(ScriptError::new(ScriptErrorKind::SchnorrSig).into()),
+        };
+        sig_msg.extend(single_sha256(&serialise_output(output)?));
+    }
+
+    // BIP342 EXTENSION: TAPLEAF HASH, KEY VERSION AND CODESEPARATOR POSITION
+    if let Some((leaf_hash, codeseparator_position)) = leaf {
+        sig_msg.extend_from_slice(leaf_hash);
+        sig_msg.push(0x00);
+        sig_msg.extend(codeseparator_position.to_le_bytes());
+    }
+
+    Ok(tagged_hash("TapSighash", &sig_msg))
+}
+
+// SHA256(SHA256(TAG) || SHA256(TAG) || DATA)
+pub fn tagged_hash(tag: &str, data: &[u8]) -> Vec<u8> {
+    let tag_hash = single_sha256(tag.as_bytes());
+
+    let mut preimage = Vec::with_capacity(64 + data.len());
+    preimage.extend_from_slice(&tag_hash);
+    preimage.extend_from_slice(&tag_hash);
+    preimage.extend_from_slice(data);
+    single_sha256(&preimage)
+}
+
+pub fn tapleaf_hash(leaf_version: u8, script: &[u8]) -> Vec<u8> {
+    let mut leaf = vec![leaf_version];
+    leaf.extend(compact_size::encode(script.len()));
+    leaf.extend_from_slice(script);
+    tagged_hash("TapLeaf", &leaf)
+}
+
+// CHILDREN ARE SORTED SO THE MERKLE PATH DOES NOT NEED TO SAY LEFT OR RIGHT
+pub fn tapbranch_hash(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+
+    let mut branch = left.to_vec();
+    branch.extend_from_slice(right);
+    tagged_hash("TapBranch", &branch)
+}
+
+// THE OUTPUT KEY MUST BE THE INTERNAL KEY TWEAKED WITH THE MERKLE ROOT THE LEAF HASHES UP TO
+fn verify_taproot_commitment(control_block: &[u8], output_key: &[u8], leaf_hash: &[u8]) -> bool {
+    let internal_key = match XOnlyPublicKey::from_slice(&control_block[1..TAPROOT_CONTROL_BASE_SIZE]) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+    let output_key = match XOnlyPublicKey::from_slice(output_key) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+
+    let mut merkle_root = leaf_hash.to_vec();
+    for node in control_block[TAPROOT_CONTROL_BASE_SIZE..].chunks(TAPROOT_CONTROL_NODE_SIZE) {
+        merkle_root = tapbranch_hash(&merkle_root, node);
+    }
+
+    let mut tweak_preimage = internal_key.serialize().to_vec();
+    tweak_preimage.extend_from_slice(&merkle_root);
+    let tweak: [u8; 32] = match tagged_hash("TapTweak", &tweak_preimage).try_into() {
+        Ok(tweak) => tweak,
+        Err(_) => return false,
+    };
+    let tweak = match Scalar::from_be_bytes(tweak) {
+        Ok(tweak) => tweak,
+        Err(_) => return false,
+    };
+
+    let parity = match Parity::from_u8(control_block[0] & 0x01) {
+        Ok(parity) => parity,
+        Err(_) => return false,
+    };
+
+    internal_key.tweak_add_check(secp(), &output_key, parity, tweak)
+}
+
+// SERIALISED SIZE OF THE INPUT'S WHOLE WITNESS, INCLUDING THE ANNEX
+fn witness_size(tx: &Transaction, tx_input_index: usize) -> Result<usize> {
+    let witness = witness_items(tx, tx_input_index)?;
+
+    let mut size = compact_size::encoded_len(witness.len());
+    for item in witness.iter() {
+        size += compact_size::encoded_len(item.len()) + item.len();
+    }
+    Ok(size)
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use secp256k1::{Keypair, Secp256k1};
+
+    use super::*;
+    use crate::fixtures::load;
+    use crate::transaction::{Input, Output, Prevout};
+    use crate::validation_checks::opcodes::{OP_1, OP_CHECKSIG, OP_CHECKSIGADD, OP_NUMEQUAL};
+    use crate::validation_checks::verify_tx;
+
+    // 2-OF-2 TAPSCRIPT: <PK1> OP_CHECKSIG <PK2> OP_CHECKSIGADD OP_2 OP_NUMEQUAL
+    // SPENT THROUGH THE SCRIPT PATH, RETURNS THE TX AND THE TWO SIGNATURES
+    fn checksigadd_spend() -> Result<(Transaction, Vec<u8>, Vec<u8>)> {
+        let secp = Secp256k1::new();
+        let internal = Keypair::from_seckey_slice(&secp, &[0x11; 32])?;
+        let key_1 = Keypair::from_seckey_slice(&secp, &[0x22; 32])?;
+        let key_2 = Keypair::from_seckey_slice(&secp, &[0x33; 32])?;
+
+        let mut leaf_script = vec![0x20];
+        leaf_script.extend(key_1.x_only_public_key().0.serialize());
+        leaf_script.push(OP_CHECKSIG);
+        leaf_script.push(0x20);
+        leaf_script.extend(key_2.x_only_public_key().0.serialize());
+        leaf_script.extend([OP_CHECKSIGADD, OP_1 + 1, OP_NUMEQUAL]);
+
+        // A SINGLE LEAF IS ITS OWN MERKLE ROOT
+        let leaf_hash = tapleaf_hash(TAPROOT_LEAF_TAPSCRIPT, &leaf_script);
+        let (internal_key, _) = internal.x_only_public_key();
+        let mut tweak_preimage = internal_key.serialize().to_vec();
+        tweak_preimage.extend_from_slice(&leaf_hash);
+        let tweak = Scalar::from_be_bytes(tagged_hash("TapTweak", &tweak_preimage).try_into().unwrap())?;
+        let (output_key, parity) = internal_key.add_tweak(&secp, &tweak)?;
+
+        let mut control_block = vec![TAPROOT_LEAF_TAPSCRIPT | parity.to_u8()];
+        control_block.extend(internal_key.serialize());
+
+        let mut tx = Transaction {
+            version: 2,
+            locktime: 0,
+            vin: vec![Input {
+                txid: "11".repeat(32),
+                vout: 0,
+                prevout: Prevout {
+                    scriptpubkey: format!("5120{}", hex::encode(output_key.serialize())),
+                    scriptpubkey_asm: String::new(),
+                    scriptpubkey_type: "v1_p2tr".to_string(),
+                    scriptpubkey_address: String::new(),
+                    value: 100_000,
+                },
+                scriptsig: Some(String::new()),
+                scriptsig_asm: None,
+                witness: None,
+                is_coinbase: false,
+                sequence: 0xffffffff,
+                inner_redeemscript_asm: None,
+            }],
+            vout: vec![Output {
+                scriptpubkey: format!("5120{}", hex::encode(internal_key.serialize())),
+                scriptpubkey_asm: String::new(),
+                scriptpubkey_type: "v1_p2tr".to_string(),
+                scriptpubkey_address: None,
+                value: 90_000,
+            }],
+        };
+
+        let sighash = taproot_sighash(&tx, 0, 0x00, None, Some((&leaf_hash, u32::MAX)))?;
+        let msg = Message::from_digest_slice(&sighash)?;
+        let sig_1 = secp.sign_schnorr_no_aux_rand(&msg, &key_1).as_ref().to_vec();
+        let sig_2 = secp.sign_schnorr_no_aux_rand(&msg, &key_2).as_ref().to_vec();
+
+        // THE FIRST KEY IS CHECKED FIRST, SO ITS SIGNATURE SITS ON TOP
+        tx.vin[0].witness = Some(vec![
+            hex::encode(&sig_2),
+            hex::encode(&sig_1),
+            hex::encode(&leaf_script),
+            hex::encode(&control_block),
+        ]);
+
+        Ok((tx, sig_1, sig_2))
+    }
+
+    #[test]
+    fn test_key_path_spend() -> Result<()> {
+        let tx = load("001035505afbf143e51bd667099190943a38eee20092bb691e72eaa44992b2f7")?;
+        assert!(input_verification_p2tr(0, tx.clone(), ScriptFlags::CONSENSUS)?);
+
+        // THE SIGNATURE COMMITS TO THE AMOUNTS OF EVERY INPUT
+        let mut tampered = tx;
+        tampered.vin[0].prevout.value += 1;
+        assert!(input_verification_p2tr(0, tampered, ScriptFlags::CONSENSUS).is_err());
+
+        // EXPLICIT SIGHASH_ALL (65 BYTE SIGNATURE)
+        let tx = load("075e4dbdc88720c4f5b37b15de136046d6d0aa0f857e35322e4e9f56b72821af")?;
+        assert!(input_verification_p2tr(0, tx, ScriptFlags::CONSENSUS)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_script_path_spend() -> Result<()> {
+        let tx = load("14b986b362085c558a1c0c120ec657f9c2c97693a2251d81eeebde119d5921a1")?;
+        assert!(input_verification_p2tr(0, tx.clone(), ScriptFlags::CONSENSUS)?);
+
+        // FLIPPING THE PARITY BIT BREAKS THE TWEAK CHECK
+        let mut tampered = tx;
+        let mut witness = tampered.vin[0].witness.clone().unwrap();
+        let mut control_block = hex::decode(witness.last().unwrap())?;
+        control_block[0] ^= 0x01;
+        *witness.last_mut().unwrap() = hex::encode(control_block);
+        tampered.vin[0].witness = Some(witness);
+        assert!(input_verification_p2tr(0, tampered, ScriptFlags::CONSENSUS).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksigadd() -> Result<()> {
+        let (tx, _, sig_2) = checksigadd_spend()?;
+        assert!(input_verification_p2tr(0, tx.clone(), ScriptFlags::CONSENSUS)?);
+        assert!(verify_tx(tx.clone())?);
+
+        // AN EMPTY SIGNATURE ADDS NOTHING, 1 != 2
+        let mut one_of_two = tx.clone();
+        one_of_two.vin[0].witness.as_mut().unwrap()[0] = String::new();
+        assert!(!input_verification_p2tr(0, one_of_two, ScriptFlags::CONSENSUS)?);
+
+        // A NON-EMPTY INVALID SIGNATURE FAILS THE SCRIPT OUTRIGHT
+        let mut swapped = tx.clone();
+        swapped.vin[0].witness.as_mut().unwrap()[1] = hex::encode(&sig_2);
+        assert!(input_verification_p2tr(0, swapped, ScriptFlags::CONSENSUS).is_err());
+
+        // A 65 BYTE SIGNATURE WITH AN EXPLICIT 0x00 SIGHASH BYTE IS NOT ALLOWED
+        let mut explicit_default = tx;
+        explicit_default.vin[0].witness.as_mut().unwrap()[0].push_str("00");
+        assert!(input_verification_p2tr(0, explicit_default, ScriptFlags::CONSENSUS).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tapscript_rules() -> Result<()> {
+        let (tx, _, _) = checksigadd_spend()?;
+        let ctx = ScriptContext {
+            tx: &tx,
+            input_index: 0,
+            sig_version: SigVersion::Tapscript,
+            tapscript: Some(TapscriptExecution { leaf_hash: vec![0; 32], annex: None, validation_weight: 0 }),
+            flags: ScriptFlags::CONSENSUS,
+        };
+
+        // MINIMALIF: OP_2 OP_IF OP_ENDIF
+        let mut stack = Vec::new();
+        let error = Script::from_hex("526368")?.execute(&mut stack, &ctx).unwrap_err();
+        assert_eq!(error.kind, ScriptErrorKind::TapscriptMinimalIf);
+
+        // OP_CHECKMULTISIG IS GONE: OP_0 OP_0 OP_0 OP_CHECKMULTISIG
+        let mut stack = Vec::new();
+        let error = Script::from_hex("000000ae")?.execute(&mut stack, &ctx).unwrap_err();
+        assert_eq!(error.kind, ScriptErrorKind::TapscriptCheckMultisig);
+
+        // NO SIGOP BUDGET LEFT FOR A NON-EMPTY SIGNATURE: <SIG> <UNKNOWN 33 BYTE KEY> OP_CHECKSIG
+        let mut stack = vec![vec![1; 64], vec![2; 33]];
+        let error = Script::from_hex("ac")?.execute(&mut stack, &ctx).unwrap_err();
+        assert_eq!(error.kind, ScriptErrorKind::TapscriptValidationWeight);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tagged_hashes() {
+        // BIP341 WALLET TEST VECTOR: SCRIPT "20 <KEY> AC" WITH LEAF VERSION 0xc0
+        let script = hex::decode("20d85a959b0290bf19bb89ed43c916be835475d013da4b362117393e25a48229b8ac").unwrap();
+        assert_eq!(
+            hex::encode(tapleaf_hash(TAPROOT_LEAF_TAPSCRIPT, &script)),
+            "5b75adecf53548f3ec6ad7d78383bf84cc57b55a3127c72b9a2481752dd88b21"
+        );
+
+        // BRANCH HASHES DO NOT DEPEND ON THE ORDER OF THE CHILDREN
+        let a = [0x01; 32];
+        let b = [0x02; 32];
+        assert_eq!(tapbranch_hash(&a, &b), tapbranch_hash(&b, &a));
+    }
+
+    #[test]
+    fn test_script_execution_p2tr() -> Result<()> {
+        let mut s_count = 0;
+        let mut f_count = 0;
+
+        for entry in fs::read_dir("./mempool")? {
+            let contents = fs::read_to_string(entry?.path())?;
+            if let Ok(transaction) = serde_json::from_str::<Transaction>(&contents) {
+                if transaction.vin.iter().all(|input| input.prevout.scriptpubkey_type == "v1_p2tr") {
+                    let result = (0..transaction.vin.len()).all(|input_index| {
+                        matches!(input_verification_p2tr(input_index, transaction.clone(), ScriptFlags::CONSENSUS), Ok(true))
+                    });
+
+                    if result {
+                        s_count += 1;
+                    } else {
+                        f_count += 1;
+                    }
+                }
+            }
+        }
+
+        println!("success: {}", s_count);
+        println!("failure: {}", f_count);
+
+        assert!(s_count > f_count);
+
+        Ok(())
+    }
+}
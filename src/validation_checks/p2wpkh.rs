@@ -1,43 +1,43 @@
-use hex; // Importing hex library for hexadecimal encoding and decoding
-
-use crate::validation_checks::op_checksig; // Importing op_checksig function from validation_checks module
+use crate::validation_checks::opcodes::{OP_CHECKSIG, OP_DUP, OP_EQUALVERIFY, OP_HASH160}; // Importing the opcodes of the implied script code
+use crate::validation_checks::script::{Script, ScriptError, ScriptErrorKind, ScriptFlags}; // Importing the script interpreter
+use crate::validation_checks::{execute_witness_script, witness_items}; // Importing witness helpers from validation_checks module
 
 use crate::{error::Result, transaction::Transaction}; // Importing Result type and Transaction struct from crate
 
-pub fn input_verification_p2wpkh(tx_input_index: usize, tx: Transaction) -> Result<bool> {
-    let witness = match tx.vin[tx_input_index].witness.clone() { // Extracting witness data from transaction input
-        Some(value) => value, // If witness exists, assign it to witness variable
-        None => Vec::new(), // If witness does not exist, create an empty vector
+pub fn input_verification_p2wpkh(tx_input_index: usize, tx: Transaction, flags: ScriptFlags) -> Result<bool> {
+    let witness = witness_items(&tx, tx_input_index)?; // Extracting witness data from transaction input
+
+    if !tx.vin[tx_input_index].scriptsig.clone().unwrap_or_default().is_empty() { // Checking if scriptsig is not empty
+        return Err(ScriptError::new(ScriptErrorKind::WitnessMalleated).into()); // Native segwit inputs must have an empty scriptsig
+    }
+
+    let scriptpubkey = Script::from_hex(&tx.vin[tx_input_index].prevout.scriptpubkey)?; // Decoding the scriptpubkey
+    let pubkey_hash = match scriptpubkey.witness_program() { // Extracting the 20 byte witness program
+        Some((0, program)) if program.len() == 20 => program.to_vec(),
+        _ => return Ok(false),
     };
 
-    Ok(script_execution_p2wpkh(witness, tx, tx_input_index)?) // Calling script_execution_p2wpkh function with witness data
+    script_execution_p2wpkh(witness, &pubkey_hash, &tx, tx_input_index, flags) // Calling script_execution_p2wpkh function with witness data
 }
 
-fn script_execution_p2wpkh(
-    witness: Vec<String>, // Witness data containing signature and public key
-    tx: Transaction,
+pub fn script_execution_p2wpkh(
+    witness: Vec<Vec<u8>>, // Witness data containing signature and public key
+    pubkey_hash: &[u8], // Witness program
+    tx: &Transaction,
     tx_input_index: usize,
+    flags: ScriptFlags,
 ) -> Result<bool> {
-    if witness.len() == 0 { // Checking if witness is empty
-        return Ok(false); // Returning false if witness is empty
+    if witness.len() != 2 { // Checking that the witness holds exactly a signature and a public key
+        return Err(ScriptError::new(ScriptErrorKind::WitnessProgramMismatch).into());
     }
 
-    if tx.vin[tx_input_index].scriptsig.clone().unwrap().len() != 0 { // Checking if scriptsig is not empty
-        return Ok(false); // Returning false if scriptsig is not empty
-    }
-
-    let input_type = "P2WPKH"; // Setting input type as P2WPKH
-
-    let mut stack = Vec::new(); // Initializing stack for script execution
+    // SCRIPT CODE := OP_DUP OP_HASH160 <20-byte-pubkey-hash> OP_EQUALVERIFY OP_CHECKSIG
+    let mut script_code = vec![OP_DUP, OP_HASH160, 0x14];
+    script_code.extend_from_slice(pubkey_hash);
+    script_code.extend([OP_EQUALVERIFY, OP_CHECKSIG]);
 
-    // PUSHING COMPONENTS OF THE WITNESS IN THE STACK := SIGNATURE AND PUBLIC KEY
-    stack.push(hex::decode(&witness[0])?); // Decoding and pushing signature to the stack
-    stack.push(hex::decode(&witness[1])?); // Decoding and pushing public key to the stack
-
-    // OP_CHECKSIG
-    let script_result = op_checksig(&mut stack, tx.clone(), tx_input_index, input_type)?; // Calling op_checksig function
-
-    Ok(script_result) // Returning script execution result
+    // THE WITNESS ITEMS ARE THE INITIAL STACK
+    execute_witness_script(&Script::new(script_code), witness, tx, tx_input_index, flags)
 }
 
 // TO TEST MY CODE DURING DEVELOPMENT
@@ -51,8 +51,8 @@ mod test {
 
     #[test]
     fn test_script_execution_p2wpkh() -> Result<()> {
-        // let mut s_count = 0;
-        // let mut f_count = 0;
+        let mut s_count = 0;
+        let mut f_count = 0;
         let mempool_dir = "./mempool"; // Path to mempool directory
         for entry in WalkDir::new(mempool_dir).into_iter().filter_map(|e| e.ok()) { // Iterating over mempool directory
             let path = entry.path(); // Getting path of current file
@@ -61,20 +61,19 @@ mod test {
                     Ok(contents) => {
                         match serde_json::from_str::<Transaction>(&contents) { // Parsing JSON into Transaction struct
                             Ok(transaction) => {
-                                let all_p2sh = transaction.vin.iter().all(|input| { // Checking if all inputs are of type v0_p2wpkh
-                                    input.prevout.scriptpubkey_type == "v0_p2wpkh".to_string()
+                                let all_p2wpkh = transaction.vin.iter().all(|input| { // Checking if all inputs are of type v0_p2wpkh
+                                    input.prevout.scriptpubkey_type == "v0_p2wpkh"
                                 });
-                                if all_p2sh { // Proceeding if all inputs are of type v0_p2wpkh
-                                    let result = script_execution_p2wpkh( // Calling script_execution_p2wpkh function
-                                        transaction.vin[0].witness.clone().unwrap(),
-                                        transaction,
-                                        0,
-                                    )?;
-
-                                    if result == true { // Handling script execution result
+                                if all_p2wpkh { // Proceeding if all inputs are of type v0_p2wpkh
+                                    let result = (0..transaction.vin.len()).all(|input_index| {
+                                        matches!(input_verification_p2wpkh(input_index, transaction.clone(), ScriptFlags::CONSENSUS), Ok(true))
+                                    });
+
+                                    if result { // Handling script execution result
+                                        s_count += 1;
                                     } else {
+                                        f_count += 1;
                                     }
-
                                 }
                             }
                             Err(_e) => {
@@ -85,6 +84,12 @@ mod test {
                 }
             }
         }
+
+        println!("success: {}", s_count);
+        println!("failure: {}", f_count);
+
+        assert!(s_count > f_count);
+
         Ok(()) // Returning Ok result
     }
 
@@ -99,8 +104,7 @@ mod test {
         // Deserialize JSON into Rust data structures
         let transaction: Transaction = serde_json::from_str(&data)?;
 
-        let tx = transaction.clone();
-        let result = script_execution_p2wpkh(tx.vin[0].witness.clone().unwrap(), tx, 0)?;
+        let result = input_verification_p2wpkh(0, transaction, ScriptFlags::CONSENSUS)?;
 
         println!("{}", result);
 
@@ -0,0 +1,306 @@
+// RELAY POLICY: BITCOIN CORE STYLE STANDARDNESS ON TOP OF CONSENSUS
+//
+// A TRANSACTION THAT BREAKS ONE OF THESE RULES COULD STILL BE MINED, WE JUST DON'T TAKE IT. EVERY
+// RULE CAN BE SWITCHED OFF BY NAME, `Policy::consensus()` SWITCHES THEM ALL OFF.
+use crate::block_mine::serialise_tx::serialise_tx; // Importing the serialisation for the weight
+use crate::compact_size; // Importing the CompactSize encoding for output sizes
+use crate::transaction::Transaction; // Importing the Transaction struct
+
+use super::opcodes::{OP_1, OP_16, OP_CHECKMULTISIG, OP_RETURN}; // Importing the opcodes standard outputs are made of
+use super::rejection::Rejection; // Importing the rejection reasons
+use super::script::{Script, ScriptFlags, MAX_SCRIPT_SIZE}; // Importing the script parser and interpreter flags
+
+// THE CHALLENGE'S ABSOLUTE FEE FLOOR
+pub const MIN_FEE: u64 = 1500;
+
+// BITCOIN CORE DEFAULTS, FEERATES IN SATS PER 1000 VBYTES
+pub const MIN_RELAY_FEERATE: u64 = 1000;
+pub const DUST_RELAY_FEERATE: u64 = 3000;
+pub const MAX_STANDARD_TX_WEIGHT: usize = 400_000;
+pub const MAX_OP_RETURN_RELAY: usize = 83; // Whole script: OP_RETURN, a push opcode and 80 bytes
+pub const MAX_STANDARD_MULTISIG_KEYS: usize = 3;
+
+// THE NAMES `disable` ACCEPTS, IN THE ORDER THE RULES ARE CHECKED
+pub const POLICY_RULES: [&str; 12] = [
+    "zero-value",
+    "min-fee",
+    "min-relay-fee",
+    "max-weight",
+    "standard-outputs",
+    "op-return",
+    "dust",
+    "low-s",
+    "strict-encoding",
+    "null-fail",
+    "minimal-if",
+    "clean-stack",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    pub zero_value: bool, // No input or output worth nothing
+    pub min_fee: Option<u64>, // Absolute fee in sats
+    pub min_relay_feerate: Option<u64>,
+    pub max_weight: Option<usize>,
+    pub standard_outputs: bool, // Only output types Bitcoin Core relays
+    pub max_op_return: Option<usize>, // Largest OP_RETURN script, and at most one of them
+    pub dust_relay_feerate: Option<u64>, // Outputs costing more to spend than they are worth at this feerate
+    pub script_flags: ScriptFlags,
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy {
+            zero_value: true,
+            min_fee: Some(MIN_FEE),
+            min_relay_feerate: Some(MIN_RELAY_FEERATE),
+            max_weight: Some(MAX_STANDARD_TX_WEIGHT),
+            standard_outputs: true,
+            max_op_return: Some(MAX_OP_RETURN_RELAY),
+            dust_relay_feerate: Some(DUST_RELAY_FEERATE),
+            script_flags: ScriptFlags::STANDARD,
+        }
+    }
+}
+
+impl Policy {
+    // NOTHING BUT CONSENSUS, E.G. TO CHECK A BLOCK SOMEONE ELSE MINED
+    pub fn consensus() -> Policy {
+        Policy {
+            zero_value: false,
+            min_fee: None,
+            min_relay_feerate: None,
+            max_weight: None,
+            standard_outputs: false,
+            max_op_return: None,
+            dust_relay_feerate: None,
+            script_flags: ScriptFlags::CONSENSUS,
+        }
+    }
+
+    // Switches one rule off by its name in POLICY_RULES, "all" switches every rule off
+    pub fn disable(&mut self, rule: &str) -> bool {
+        match rule {
+            "all" => *self = Policy::consensus(),
+            "zero-value" => self.zero_value = false,
+            "min-fee" => self.min_fee = None,
+            "min-relay-fee" => self.min_relay_feerate = None,
+            "max-weight" => self.max_weight = None,
+            "standard-outputs" => self.standard_outputs = false,
+            "op-return" => self.max_op_return = None,
+            "dust" => self.dust_relay_feerate = None,
+            "low-s" => self.script_flags.low_s = false,
+            "strict-encoding" => self.script_flags.strict_encoding = false,
+            "null-fail" => self.script_flags.null_fail = false,
+            "minimal-if" => self.script_flags.minimal_if = false,
+            "clean-stack" => self.script_flags.clean_stack = false,
+            _ => return false,
+        }
+        true
+    }
+
+    // Every rule that does not need the scripts executed, those run with `script_flags` afterwards
+    pub fn check(&self, tx: &Transaction) -> Result<(), Rejection> {
+        if self.zero_value {
+            if let Some(input) = tx.vin.iter().position(|input| input.prevout.value == 0) {
+                return Err(Rejection::ZeroValueInput { input });
+            }
+            if let Some(output) = tx.vout.iter().position(|output| output.value == 0) {
+                return Err(Rejection::ZeroValueOutput { output });
+            }
+        }
+
+        // CONSENSUS ALREADY KEPT BOTH TOTALS IN RANGE AND THE OUTPUTS BELOW THE INPUTS
+        let inputs: u64 = tx.vin.iter().map(|input| input.prevout.value).sum();
+        let outputs: u64 = tx.vout.iter().map(|output| output.value).sum();
+        let fee = inputs.saturating_sub(outputs);
+        if let Some(minimum) = self.min_fee {
+            if fee < minimum {
+                return Err(Rejection::InsufficientFee { fee, minimum });
+            }
+        }
+
+        if self.min_relay_feerate.is_some() || self.max_weight.is_some() {
+            let weight = match serialise_tx(tx) {
+                Ok((_, _, weight, _)) => weight,
+                Err(error) => return Err(Rejection::Unserialisable { error: error.to_string() }),
+            };
+            if let Some(maximum) = self.max_weight {
+                if weight > maximum {
+                    return Err(Rejection::WeightAboveStandard { weight, maximum });
+                }
+            }
+            if let Some(minimum) = self.min_relay_feerate {
+                let vsize = weight.div_ceil(4) as u64;
+                if fee * 1000 < minimum * vsize {
+                    return Err(Rejection::FeerateBelowMinimum { fee, vsize, minimum });
+                }
+            }
+        }
+
+        let mut op_returns = 0;
+        for (output_index, output) in tx.vout.iter().enumerate() {
+            let script = match hex::decode(&output.scriptpubkey) {
+                Ok(bytes) => Script::new(bytes),
+                Err(error) => return Err(Rejection::Unserialisable { error: error.to_string() }),
+            };
+
+            if self.standard_outputs && !is_standard_output(&script) {
+                return Err(Rejection::NonStandardOutput {
+                    output: output_index,
+                    script_type: script.script_type().to_string(),
+                });
+            }
+
+            if let Some(maximum) = self.max_op_return {
+                if script.as_bytes().first() == Some(&OP_RETURN) {
+                    op_returns += 1;
+                    if op_returns > 1 {
+                        return Err(Rejection::MultipleOpReturns { count: op_returns });
+                    }
+                    if script.as_bytes().len() > maximum {
+                        return Err(Rejection::OpReturnTooLarge {
+                            output: output_index,
+                            size: script.as_bytes().len(),
+                            maximum,
+                        });
+                    }
+                }
+            }
+
+            if let Some(feerate) = self.dust_relay_feerate {
+                let threshold = dust_threshold(&script, feerate);
+                if output.value < threshold {
+                    return Err(Rejection::Dust { output: output_index, value: output.value, threshold });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// THE TYPES `IsStandard` ACCEPTS: THE USUAL TEMPLATES, BARE MULTISIG UP TO 3 KEYS, OP_RETURN (ITS
+// SIZE IS A SEPARATE RULE) AND WITNESS PROGRAMS OF VERSIONS NOBODY HAS DEFINED YET
+pub fn is_standard_output(script: &Script) -> bool {
+    match script.script_type() {
+        "p2pkh" | "p2sh" | "p2pk" | "v0_p2wpkh" | "v0_p2wsh" | "v1_p2tr" | "op_return" => true,
+        _ => matches!(script.witness_program(), Some((version, _)) if version > 0) || is_bare_multisig(script),
+    }
+}
+
+// OP_M <PUBKEY>.. OP_N OP_CHECKMULTISIG WITH 1 <= M <= N <= 3
+fn is_bare_multisig(script: &Script) -> bool {
+    let Ok(instructions) = script.instructions().collect::<Result<Vec<_>, _>>() else {
+        return false;
+    };
+    let [first, keys @ .., last_n, checkmultisig] = instructions.as_slice() else {
+        return false;
+    };
+    if checkmultisig.opcode != OP_CHECKMULTISIG || !(OP_1..=OP_16).contains(&first.opcode) {
+        return false;
+    }
+    if !(OP_1..=OP_16).contains(&last_n.opcode) {
+        return false;
+    }
+    let (m, n) = ((first.opcode - OP_1 + 1) as usize, (last_n.opcode - OP_1 + 1) as usize);
+    let valid_keys = keys.iter().all(|key| matches!(key.push_data, Some(data) if data.len() == 33 || data.len() == 65));
+    valid_keys && keys.len() == n && m <= n && n <= MAX_STANDARD_MULTISIG_KEYS
+}
+
+// WHAT SPENDING THE OUTPUT WOULD COST AT `feerate`, THE SAME SIZES AS `GetDustThreshold`
+pub fn dust_threshold(script: &Script, feerate: u64) -> u64 {
+    let bytes = script.as_bytes();
+    if bytes.first() == Some(&OP_RETURN) || bytes.len() > MAX_SCRIPT_SIZE {
+        return 0; // Unspendable, so never dust
+    }
+
+    let output_size = 8 + compact_size::encoded_len(bytes.len()) + bytes.len();
+
+    // OUTPOINT, SCRIPT SIG LENGTH, SEQUENCE, AND A 107 BYTE SIGNATURE AND KEY (DISCOUNTED IN A WITNESS)
+    let input_size = if script.witness_program().is_some() { 32 + 4 + 1 + 107 / 4 + 4 } else { 32 + 4 + 1 + 107 + 4 };
+    feerate * (output_size + input_size) as u64 / 1000
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::Result;
+    use crate::fixtures::load;
+
+    // THREE P2PKH INPUTS PAYING 8268 SATS IN FEES
+    const P2PKH: &str = "01f16e8312f9c882e869d31a3ab386b94a38f6091f7e947c6f2ed2b3389f4406";
+
+    fn script(script_hex: &str) -> Script {
+        Script::from_hex(script_hex).unwrap()
+    }
+
+    #[test]
+    fn test_standard_outputs_and_dust() -> Result<()> {
+        let policy = Policy::default();
+        let tx = load(P2PKH)?;
+        assert_eq!(policy.check(&tx), Ok(()));
+
+        // THE WELL KNOWN THRESHOLDS: 546 FOR P2PKH, 294 FOR P2WPKH AND 330 FOR P2TR
+        assert_eq!(dust_threshold(&script(&format!("76a914{}88ac", "00".repeat(20))), 3000), 546);
+        assert_eq!(dust_threshold(&script(&format!("0014{}", "00".repeat(20))), 3000), 294);
+        assert_eq!(dust_threshold(&script(&format!("5120{}", "00".repeat(32))), 3000), 330);
+        assert_eq!(dust_threshold(&script("6a0100"), 3000), 0);
+
+        let mut dust = tx.clone();
+        dust.vout[0].scriptpubkey = format!("0014{}", "00".repeat(20));
+        dust.vout[0].value = 293;
+        assert_eq!(policy.check(&dust), Err(Rejection::Dust { output: 0, value: 293, threshold: 294 }));
+
+        // 1-OF-2 BARE MULTISIG IS STANDARD, 1-OF-4 IS NOT, NEITHER IS AN OP_TRUE OUTPUT
+        let key = format!("21{}", "02".repeat(33));
+        assert!(is_standard_output(&script(&format!("51{}{}52ae", key, key))));
+        assert!(!is_standard_output(&script(&format!("51{}{}{}{}54ae", key, key, key, key))));
+        assert!(!is_standard_output(&script("51")));
+        assert!(is_standard_output(&script(&format!("5220{}", "00".repeat(32)))));
+
+        let mut nonstandard = tx.clone();
+        nonstandard.vout[0].scriptpubkey = "51".to_string();
+        assert_eq!(
+            policy.check(&nonstandard),
+            Err(Rejection::NonStandardOutput { output: 0, script_type: "unknown".to_string() })
+        );
+
+        // 81 BYTES OF DATA IS ONE TOO MANY
+        let mut op_return = tx.clone();
+        op_return.vout[0].scriptpubkey = format!("6a4c51{}", "00".repeat(81));
+        assert_eq!(policy.check(&op_return), Err(Rejection::OpReturnTooLarge { output: 0, size: 84, maximum: 83 }));
+
+        // EVERY RULE SWITCHED OFF ONE BY ONE
+        let mut relaxed = Policy::default();
+        for rule in POLICY_RULES {
+            assert!(relaxed.disable(rule));
+        }
+        assert_eq!(relaxed, Policy::consensus());
+        assert!(!relaxed.disable("no-such-rule"));
+        for tx in [dust, nonstandard, op_return] {
+            assert_eq!(relaxed.check(&tx), Ok(()));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fee_and_weight_rules() -> Result<()> {
+        let tx = load(P2PKH)?;
+        let (_, _, weight, _) = serialise_tx(&tx)?;
+        let vsize = weight.div_ceil(4) as u64;
+
+        let mut policy = Policy { min_fee: None, min_relay_feerate: Some(8268 * 1000 / vsize + 1), ..Policy::default() };
+        assert_eq!(
+            policy.check(&tx),
+            Err(Rejection::FeerateBelowMinimum { fee: 8268, vsize, minimum: 8268 * 1000 / vsize + 1 })
+        );
+
+        policy.min_relay_feerate = None;
+        policy.max_weight = Some(weight - 1);
+        assert_eq!(policy.check(&tx), Err(Rejection::WeightAboveStandard { weight, maximum: weight - 1 }));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,433 @@
+// WHY A MEMPOOL TRANSACTION WAS LEFT OUT, AND THE REPORT THAT COLLECTS THEM
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::block_mine::subsidy::MAX_MONEY; // Importing the money supply cap
+use crate::error::Result; // Importing Result type from the crate
+use crate::utxo::COINBASE_MATURITY; // Importing the coinbase maturity depth
+
+use super::opcodes::opcode_name; // Importing opcode names for the report
+use super::script::{ScriptError, ScriptErrorKind}; // Importing the script failures
+use super::timelock::LOCKTIME_THRESHOLD; // Importing the height and time locktime boundary
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum Rejection {
+    Unreadable { error: String },
+    MalformedJson { error: String },
+    ZeroValueInput { input: usize },
+    ZeroValueOutput { output: usize },
+    ValueOutOfRange { value: u64 },
+    OutputsExceedInputs { inputs: u64, outputs: u64 },
+    InsufficientFee { fee: u64, minimum: u64 },
+    UnsupportedScriptType { input: usize, script_type: String },
+    ScriptFailed { input: usize, opcode: Option<String>, error: String },
+    BadSignature { input: usize },
+    MalformedInput { input: usize, error: String },
+    DoubleSpend { outpoint: String, spent_by: String },
+    NonFinalLockTime { locktime: u32, height: u32, median_time_past: u32 },
+    RelativeHeightLock { input: usize, unlocks_at: u64, height: u32 },
+    RelativeTimeLock { input: usize, unlocks_after: u64, median_time_past: u32 },
+    ParentHeldOut { parent: String },
+    NonStandardOutput { output: usize, script_type: String },
+    Dust { output: usize, value: u64, threshold: u64 },
+    WeightAboveStandard { weight: usize, maximum: usize },
+    OpReturnTooLarge { output: usize, size: usize, maximum: usize },
+    MultipleOpReturns { count: usize },
+    FeerateBelowMinimum { fee: u64, vsize: u64, minimum: u64 },
+    Unserialisable { error: String },
+    ReplacedBy { replacement: String },
+    ParentConflicted { parent: String },
+    ReplacementAddsUnconfirmed { input: usize },
+    ReplacementFeeTooLow { fee: u64, replaced_fees: u64 },
+    ReplacementFeerateTooLow { additional_fee: u64, required: u64 },
+    TooManyReplacements { count: usize, maximum: usize },
+    MissingInput { input: usize, outpoint: String },
+    PrevoutMismatch { input: usize, outpoint: String },
+    NoInputs,
+    NoOutputs,
+    DuplicateInput { input: usize, outpoint: String },
+    PrematureCoinbaseSpend { input: usize, outpoint: String, depth: u32 },
+}
+
+// WHETHER NO NODE WOULD ACCEPT THE TRANSACTION, OR ONLY WE DON'T (SEE `Policy`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    Consensus,
+    Policy,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::Consensus => write!(f, "CONSENSUS"),
+            Rule::Policy => write!(f, "POLICY"),
+        }
+    }
+}
+
+impl Rejection {
+    // Sorts an input's failure into a signature, script or decoding problem
+    pub fn from_input_error(input: usize, error: &failure::Error) -> Rejection {
+        match error.downcast_ref::<ScriptError>() {
+            Some(ScriptError {
+                kind:
+                    ScriptErrorKind::CheckSigVerify | ScriptErrorKind::CheckMultisigVerify | ScriptErrorKind::SchnorrSig,
+                ..
+            }) => Rejection::BadSignature { input },
+            Some(script_error) => Rejection::ScriptFailed {
+                input,
+                opcode: script_error.opcode.map(opcode_name),
+                error: format!("{:?}", script_error.kind),
+            },
+            None => Rejection::MalformedInput { input, error: error.to_string() },
+        }
+    }
+
+    // THE SNAKE CASE TAG THE JSON REPORT USES
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Rejection::Unreadable { .. } => "unreadable",
+            Rejection::MalformedJson { .. } => "malformed_json",
+            Rejection::ZeroValueInput { .. } => "zero_value_input",
+            Rejection::ZeroValueOutput { .. } => "zero_value_output",
+            Rejection::ValueOutOfRange { .. } => "value_out_of_range",
+            Rejection::OutputsExceedInputs { .. } => "outputs_exceed_inputs",
+            Rejection::InsufficientFee { .. } => "insufficient_fee",
+            Rejection::UnsupportedScriptType { .. } => "unsupported_script_type",
+            Rejection::ScriptFailed { .. } => "script_failed",
+            Rejection::BadSignature { .. } => "bad_signature",
+            Rejection::MalformedInput { .. } => "malformed_input",
+            Rejection::DoubleSpend { .. } => "double_spend",
+            Rejection::NonFinalLockTime { .. } => "non_final_lock_time",
+            Rejection::RelativeHeightLock { .. } => "relative_height_lock",
+            Rejection::RelativeTimeLock { .. } => "relative_time_lock",
+            Rejection::ParentHeldOut { .. } => "parent_held_out",
+            Rejection::NonStandardOutput { .. } => "non_standard_output",
+            Rejection::Dust { .. } => "dust",
+            Rejection::WeightAboveStandard { .. } => "weight_above_standard",
+            Rejection::OpReturnTooLarge { .. } => "op_return_too_large",
+            Rejection::MultipleOpReturns { .. } => "multiple_op_returns",
+            Rejection::FeerateBelowMinimum { .. } => "feerate_below_minimum",
+            Rejection::Unserialisable { .. } => "unserialisable",
+            Rejection::ReplacedBy { .. } => "replaced_by",
+            Rejection::ParentConflicted { .. } => "parent_conflicted",
+            Rejection::ReplacementAddsUnconfirmed { .. } => "replacement_adds_unconfirmed",
+            Rejection::ReplacementFeeTooLow { .. } => "replacement_fee_too_low",
+            Rejection::ReplacementFeerateTooLow { .. } => "replacement_feerate_too_low",
+            Rejection::TooManyReplacements { .. } => "too_many_replacements",
+            Rejection::MissingInput { .. } => "missing_input",
+            Rejection::PrevoutMismatch { .. } => "prevout_mismatch",
+            Rejection::NoInputs => "no_inputs",
+            Rejection::NoOutputs => "no_outputs",
+            Rejection::DuplicateInput { .. } => "duplicate_input",
+            Rejection::PrematureCoinbaseSpend { .. } => "premature_coinbase_spend",
+        }
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::Unreadable { error } => write!(f, "UNREADABLE FILE: {}", error),
+            Rejection::MalformedJson { error } => write!(f, "MALFORMED JSON: {}", error),
+            Rejection::ZeroValueInput { input } => write!(f, "INPUT {} SPENDS A ZERO VALUE OUTPUT", input),
+            Rejection::ZeroValueOutput { output } => write!(f, "OUTPUT {} HAS ZERO VALUE", output),
+            Rejection::ValueOutOfRange { value } => {
+                write!(f, "VALUE OF {} SATS TAKES A TOTAL PAST THE {} SATS MONEY SUPPLY", value, MAX_MONEY)
+            }
+            Rejection::OutputsExceedInputs { inputs, outputs } => {
+                write!(f, "OUTPUTS OF {} SATS EXCEED INPUTS OF {} SATS", outputs, inputs)
+            }
+            Rejection::InsufficientFee { fee, minimum } => {
+                write!(f, "FEE OF {} SATS IS BELOW THE {} SATS MINIMUM", fee, minimum)
+            }
+            Rejection::UnsupportedScriptType { input, script_type } => {
+                write!(f, "INPUT {} SPENDS UNSUPPORTED SCRIPT TYPE {}", input, script_type)
+            }
+            Rejection::ScriptFailed { input, opcode: Some(opcode), error } => {
+                write!(f, "INPUT {} SCRIPT FAILED: {} AT {}", input, error, opcode)
+            }
+            Rejection::ScriptFailed { input, opcode: None, error } => {
+                write!(f, "INPUT {} SCRIPT FAILED: {}", input, error)
+            }
+            Rejection::BadSignature { input } => write!(f, "INPUT {} SIGNATURE DOES NOT VERIFY", input),
+            Rejection::MalformedInput { input, error } => write!(f, "INPUT {} IS MALFORMED: {}", input, error),
+            Rejection::DoubleSpend { outpoint, spent_by } => {
+                write!(f, "OUTPOINT {} IS ALREADY SPENT BY {}", outpoint, spent_by)
+            }
+            Rejection::NonFinalLockTime { locktime, height, .. } if *locktime < LOCKTIME_THRESHOLD => {
+                write!(f, "LOCKTIME HEIGHT {} IS NOT BELOW THE BLOCK HEIGHT {}", locktime, height)
+            }
+            Rejection::NonFinalLockTime { locktime, median_time_past, .. } => {
+                write!(f, "LOCKTIME {} IS NOT BEFORE THE MEDIAN TIME PAST {}", locktime, median_time_past)
+            }
+            Rejection::RelativeHeightLock { input, unlocks_at, height } => {
+                write!(f, "INPUT {} IS LOCKED UNTIL HEIGHT {}, THE BLOCK HEIGHT IS {}", input, unlocks_at, height)
+            }
+            Rejection::RelativeTimeLock { input, unlocks_after, median_time_past } => {
+                write!(
+                    f,
+                    "INPUT {} IS LOCKED UNTIL AFTER {}, THE MEDIAN TIME PAST IS {}",
+                    input, unlocks_after, median_time_past
+                )
+            }
+            Rejection::ParentHeldOut { parent } => write!(f, "SPENDS {} WHICH IS HELD OUT", parent),
+            Rejection::NonStandardOutput { output, script_type } => {
+                write!(f, "OUTPUT {} HAS NON STANDARD SCRIPT TYPE {}", output, script_type)
+            }
+            Rejection::Dust { output, value, threshold } => {
+                write!(f, "OUTPUT {} OF {} SATS IS BELOW THE {} SATS DUST THRESHOLD", output, value, threshold)
+            }
+            Rejection::WeightAboveStandard { weight, maximum } => {
+                write!(f, "WEIGHT OF {} IS ABOVE THE {} STANDARD MAXIMUM", weight, maximum)
+            }
+            Rejection::OpReturnTooLarge { output, size, maximum } => {
+                write!(f, "OUTPUT {} OP_RETURN SCRIPT OF {} BYTES IS ABOVE THE {} BYTES MAXIMUM", output, size, maximum)
+            }
+            Rejection::MultipleOpReturns { count } => write!(f, "{} OP_RETURN OUTPUTS, ONLY ONE IS STANDARD", count),
+            Rejection::FeerateBelowMinimum { fee, vsize, minimum } => {
+                write!(f, "FEE OF {} SATS FOR {} VBYTES IS BELOW THE {} SATS/KVB MINIMUM", fee, vsize, minimum)
+            }
+            Rejection::Unserialisable { error } => write!(f, "CANNOT BE SERIALISED: {}", error),
+            Rejection::ReplacedBy { replacement } => write!(f, "REPLACED BY {}", replacement),
+            Rejection::ParentConflicted { parent } => write!(f, "SPENDS {} WHICH LOST A CONFLICT", parent),
+            Rejection::ReplacementAddsUnconfirmed { input } => {
+                write!(f, "INPUT {} IS AN UNCONFIRMED OUTPUT THE REPLACED TRANSACTIONS DID NOT SPEND", input)
+            }
+            Rejection::ReplacementFeeTooLow { fee, replaced_fees } => {
+                write!(f, "FEE OF {} SATS IS BELOW THE {} SATS IT WOULD REPLACE", fee, replaced_fees)
+            }
+            Rejection::ReplacementFeerateTooLow { additional_fee, required } => {
+                write!(f, "PAYS {} SATS MORE THAN IT REPLACES, {} SATS ARE NEEDED TO RELAY IT", additional_fee, required)
+            }
+            Rejection::TooManyReplacements { count, maximum } => {
+                write!(f, "WOULD REPLACE {} TRANSACTIONS, AT MOST {} ARE ALLOWED", count, maximum)
+            }
+            Rejection::MissingInput { input, outpoint } => {
+                write!(f, "INPUT {} SPENDS {} WHICH IS NEITHER IN THE UTXO SET NOR CREATED BY A VALID MEMPOOL TRANSACTION", input, outpoint)
+            }
+            Rejection::PrevoutMismatch { input, outpoint } => {
+                write!(f, "INPUT {} CLAIMS A VALUE OR SCRIPT {} DOES NOT HAVE", input, outpoint)
+            }
+            Rejection::NoInputs => write!(f, "TRANSACTION HAS NO INPUTS"),
+            Rejection::NoOutputs => write!(f, "TRANSACTION HAS NO OUTPUTS"),
+            Rejection::DuplicateInput { input, outpoint } => {
+                write!(f, "INPUT {} SPENDS {} WHICH AN EARLIER INPUT ALREADY SPENDS", input, outpoint)
+            }
+            Rejection::PrematureCoinbaseSpend { input, outpoint, depth } => {
+                write!(f, "INPUT {} SPENDS COINBASE OUTPUT {} ONLY {} BLOCKS DEEP, IT NEEDS {}", input, outpoint, depth, COINBASE_MATURITY)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+// ONE LEFT OUT TRANSACTION, NAMED BY ITS MEMPOOL FILE SINCE MALFORMED ONES HAVE NO TXID
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RejectedTx {
+    pub file: String,
+    pub rule: Rule,
+    #[serde(flatten)]
+    pub rejection: Rejection,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RejectionReport {
+    pub rejected: Vec<RejectedTx>,
+}
+
+impl RejectionReport {
+    pub fn push(&mut self, file: String, rule: Rule, rejection: Rejection) {
+        self.rejected.push(RejectedTx { file, rule, rejection });
+    }
+
+    // HOW MANY TRANSACTIONS EACH KIND OF RULE LEFT OUT
+    pub fn rule_counts(&self) -> BTreeMap<Rule, usize> {
+        let mut counts = BTreeMap::new();
+        for rejected in self.rejected.iter() {
+            *counts.entry(rejected.rule).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    // HOW MANY TRANSACTIONS WERE LEFT OUT FOR EACH REASON
+    pub fn counts(&self) -> BTreeMap<&'static str, usize> {
+        let mut counts = BTreeMap::new();
+        for rejected in self.rejected.iter() {
+            *counts.entry(rejected.rejection.reason()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+// ONE LINE PER TRANSACTION, THEN THE TOTALS PER KIND OF RULE AND PER REASON
+impl fmt::Display for RejectionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for rejected in self.rejected.iter() {
+            writeln!(f, "REJECTED {} ({}): {}", rejected.file, rejected.rule, rejected.rejection)?;
+        }
+        write!(f, "REJECTED: {} TRANSACTIONS", self.rejected.len())?;
+        for (rule, count) in self.rule_counts() {
+            write!(f, "\n    {}: {}", rule, count)?;
+        }
+        for (reason, count) in self.counts() {
+            write!(f, "\n    {}: {}", reason, count)?;
+        }
+        Ok(())
+    }
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::load;
+    use crate::validation_checks::policy::Policy;
+    use crate::validation_checks::{check_tx, check_tx_with};
+
+    // THREE P2PKH INPUTS PAYING 8268 SATS IN FEES, AND ONE P2WPKH INPUT
+    const P2PKH: &str = "01f16e8312f9c882e869d31a3ab386b94a38f6091f7e947c6f2ed2b3389f4406";
+    const P2WPKH: &str = "4e8b1cc2c9ed315153b2457dec94128db0ba0a1dcebc136e187d0c4a0a81ce15";
+
+    // VALID SIGNATURES, BUT A 1220 SAT FEE AND A ZERO VALUE FIRST OUTPUT
+    const LOW_FEE: &str = "000cb561188c762c81f76976f816829424e2af9e0e491c617b7bf41038df3d35";
+    const ZERO_VALUE: &str = "01e6eaaa132d7fc131482f7458ddb7458d15de4d0edef746fcf2cc78ef494a0b";
+
+    #[test]
+    fn test_rejection_reasons() -> Result<()> {
+        let p2pkh = load(P2PKH)?;
+        assert_eq!(check_tx(&p2pkh), Ok(()));
+
+        assert_eq!(check_tx(&load(ZERO_VALUE)?), Err(Rejection::ZeroValueOutput { output: 0 }));
+        assert_eq!(check_tx(&load(LOW_FEE)?), Err(Rejection::InsufficientFee { fee: 1220, minimum: 1500 }));
+
+        let inputs: u64 = p2pkh.vin.iter().map(|input| input.prevout.value).sum();
+        let mut tx = p2pkh.clone();
+        tx.vout[1].value += 8268 + 1;
+        assert_eq!(check_tx(&tx), Err(Rejection::OutputsExceedInputs { inputs, outputs: inputs + 1 }));
+
+        // VALUES PAST THE MONEY SUPPLY ARE REJECTED BEFORE THEY CAN OVERFLOW THE TOTALS
+        let mut tx = p2pkh.clone();
+        tx.vin[0].prevout.value = u64::MAX;
+        tx.vin[1].prevout.value = u64::MAX;
+        assert_eq!(check_tx(&tx), Err(Rejection::ValueOutOfRange { value: u64::MAX }));
+
+        let mut tx = p2pkh.clone();
+        tx.vout[0].value = MAX_MONEY;
+        assert_eq!(check_tx(&tx), Err(Rejection::ValueOutOfRange { value: tx.vout[1].value }));
+
+        // ONE SAT MOVED TO THE FEE STILL BREAKS EVERY SIGNATURE
+        let mut tx = p2pkh.clone();
+        tx.vout[1].value -= 1;
+        assert_eq!(check_tx(&tx), Err(Rejection::BadSignature { input: 0 }));
+
+        let mut tx = load(P2WPKH)?;
+        tx.vout[1].value -= 1;
+        assert_eq!(check_tx(&tx), Err(Rejection::BadSignature { input: 0 }));
+
+        // THE PUBKEY NO LONGER HASHES TO WHAT THE SECOND INPUT'S OUTPUT LOCKS TO
+        let mut tx = p2pkh.clone();
+        tx.vin[1].prevout.scriptpubkey.replace_range(6..8, "00");
+        assert_eq!(
+            check_tx(&tx),
+            Err(Rejection::ScriptFailed {
+                input: 1,
+                opcode: Some("OP_EQUALVERIFY".to_string()),
+                error: "EqualVerify".to_string()
+            })
+        );
+
+        let mut tx = p2pkh.clone();
+        tx.vin[2].prevout.scriptpubkey_type = "multisig".to_string();
+        assert_eq!(
+            check_tx(&tx),
+            Err(Rejection::UnsupportedScriptType { input: 2, script_type: "multisig".to_string() })
+        );
+
+        let mut tx = p2pkh;
+        tx.vin[0].scriptsig = Some("zz".to_string());
+        assert!(matches!(check_tx(&tx), Err(Rejection::MalformedInput { input: 0, .. })));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_consensus_or_policy() -> Result<()> {
+        let p2pkh = load(P2PKH)?;
+
+        // A FEE BELOW THE FLOOR IS OUR CHOICE, SPENDING MORE THAN THE INPUTS IS NOT
+        let tx = load(LOW_FEE)?;
+        assert_eq!(
+            check_tx_with(&tx, &Policy::default()),
+            Err((Rule::Policy, Rejection::InsufficientFee { fee: 1220, minimum: 1500 }))
+        );
+        assert_eq!(check_tx_with(&tx, &Policy::consensus()), Ok(()));
+
+        // LOWERING THE FEE BREAKS THE SIGNATURES, CONSENSUS SAYS SO EVEN THOUGH THE FEE FLOOR CATCHES IT FIRST
+        let mut tx = p2pkh.clone();
+        tx.vout[1].value += 8268 - 1000;
+        assert_eq!(check_tx_with(&tx, &Policy::default()), Err((Rule::Consensus, Rejection::BadSignature { input: 0 })));
+        assert_eq!(check_tx_with(&tx, &Policy::consensus()), Err((Rule::Consensus, Rejection::BadSignature { input: 0 })));
+
+        let mut tx = p2pkh.clone();
+        tx.vout[1].value += 8268 + 1;
+        assert!(matches!(check_tx_with(&tx, &Policy::default()), Err((Rule::Consensus, Rejection::OutputsExceedInputs { .. }))));
+
+        // NO INPUTS, NO OUTPUTS OR THE SAME OUTPUT SPENT TWICE NEVER GET AS FAR AS THE POLICY OR THE SCRIPTS
+        let mut tx = p2pkh.clone();
+        tx.vin.clear();
+        assert_eq!(check_tx_with(&tx, &Policy::consensus()), Err((Rule::Consensus, Rejection::NoInputs)));
+        let mut tx = p2pkh.clone();
+        tx.vout.clear();
+        assert_eq!(check_tx_with(&tx, &Policy::consensus()), Err((Rule::Consensus, Rejection::NoOutputs)));
+        let mut tx = p2pkh.clone();
+        tx.vin[2] = tx.vin[0].clone();
+        assert_eq!(
+            check_tx_with(&tx, &Policy::default()),
+            Err((Rule::Consensus, Rejection::DuplicateInput { input: 2, outpoint: tx.vin[0].outpoint().to_string() }))
+        );
+
+        // A BROKEN SIGNATURE STAYS A CONSENSUS FAILURE EVEN THOUGH NULLFAIL CATCHES IT FIRST
+        let mut tx = load(P2WPKH)?;
+        tx.vout[1].value -= 1;
+        assert_eq!(check_tx_with(&tx, &Policy::default()), Err((Rule::Consensus, Rejection::BadSignature { input: 0 })));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_report_formats() -> Result<()> {
+        let mut report = RejectionReport::default();
+        report.push("aa".to_string(), Rule::Consensus, Rejection::BadSignature { input: 1 });
+        report.push("bb".to_string(), Rule::Consensus, Rejection::MalformedJson { error: "EOF".to_string() });
+        report.push("cc".to_string(), Rule::Policy, Rejection::Dust { output: 0, value: 100, threshold: 546 });
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()?)?;
+        assert_eq!(
+            json["rejected"][0],
+            serde_json::json!({ "file": "aa", "rule": "consensus", "reason": "bad_signature", "input": 1 })
+        );
+        assert_eq!(json["rejected"][1]["reason"], "malformed_json");
+        assert_eq!(json["rejected"][2]["rule"], "policy");
+
+        assert_eq!(
+            report.to_string(),
+            "REJECTED aa (CONSENSUS): INPUT 1 SIGNATURE DOES NOT VERIFY\n\
+             REJECTED bb (CONSENSUS): MALFORMED JSON: EOF\n\
+             REJECTED cc (POLICY): OUTPUT 0 OF 100 SATS IS BELOW THE 546 SATS DUST THRESHOLD\n\
+             REJECTED: 3 TRANSACTIONS\n    CONSENSUS: 2\n    POLICY: 1\n    bad_signature: 1\n    dust: 1\n    \
+             malformed_json: 1"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1392 @@
+// BYTE LEVEL SCRIPT INTERPRETER SHARED BY EVERY INPUT TYPE
+use std::fmt;
+
+use ripemd::Ripemd160;
+use secp256k1::ecdsa::Signature;
+use sha1::Sha1;
+use sha2::Digest;
+
+use crate::error::Result;
+use crate::transaction::Transaction;
+
+use super::opcodes::*;
+use super::p2tr::{check_schnorr_signature, VALIDATION_WEIGHT_PER_SIGOP_PASSED};
+use super::timelock::{
+    LOCKTIME_THRESHOLD, SEQUENCE_FINAL, SEQUENCE_LOCKTIME_DISABLE_FLAG, SEQUENCE_LOCKTIME_MASK,
+    SEQUENCE_LOCKTIME_TYPE_FLAG,
+};
+use super::{check_ecdsa_signature, double_sha256, hash160, single_sha256, SIGHASH_ANYONECANPAY, SIGHASH_ALL, SIGHASH_SINGLE};
+
+// CONSENSUS LIMITS
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+pub const MAX_STACK_SIZE: usize = 1000;
+pub const MAX_PUBKEYS_PER_MULTISIG: i64 = 20;
+
+// THE SIGNATURE HASHING RULES THE SCRIPT IS EXECUTED UNDER
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigVersion {
+    Base,
+    WitnessV0,
+    Tapscript,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptErrorKind {
+    OpReturn,
+    ScriptSize,
+    PushSize,
+    OpCount,
+    StackSize,
+    SigCount,
+    PubkeyCount,
+    Verify,
+    EqualVerify,
+    CheckSigVerify,
+    CheckMultisigVerify,
+    NumEqualVerify,
+    BadOpcode,
+    DisabledOpcode,
+    InvalidStackOperation,
+    InvalidAltstackOperation,
+    UnbalancedConditional,
+    NumOverflow,
+    BadPush,
+    SigDer,
+    SigNullDummy,
+    SigPushOnly,
+    Sighash,
+    WitnessProgramWrongLength,
+    WitnessProgramWitnessEmpty,
+    WitnessProgramMismatch,
+    WitnessMalleated,
+    WitnessMalleatedP2sh,
+    WitnessUnexpected,
+    CleanStack,
+    SchnorrSig,
+    SchnorrSigSize,
+    SchnorrSigHashtype,
+    TaprootWrongControlSize,
+    TapscriptValidationWeight,
+    TapscriptCheckMultisig,
+    TapscriptMinimalIf,
+    TapscriptEmptyPubkey,
+    NegativeLockTime,
+    UnsatisfiedLockTime,
+    SigHighS,
+    SigHashType,
+    PubkeyType,
+    NullFail,
+    MinimalIf,
+}
+
+// A SCRIPT FAILURE TOGETHER WITH THE OPCODE THAT WAS BEING EXECUTED
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    pub kind: ScriptErrorKind,
+    pub opcode: Option<u8>,
+}
+
+impl ScriptError {
+    pub fn new(kind: ScriptErrorKind) -> ScriptError {
+        ScriptError { kind, opcode: None }
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.opcode {
+            Some(opcode) => write!(f, "SCRIPT ERROR: {:?} AT {}", self.kind, opcode_name(opcode)),
+            None => write!(f, "SCRIPT ERROR: {:?}", self.kind),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl From<ScriptErrorKind> for ScriptError {
+    fn from(kind: ScriptErrorKind) -> ScriptError {
+        ScriptError::new(kind)
+    }
+}
+
+// STANDARDNESS RULES THE INTERPRETER CAN ENFORCE ON TOP OF CONSENSUS, ALL OFF FOR CONSENSUS CHECKS
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScriptFlags {
+    pub low_s: bool,           // ECDSA S values in the lower half of the curve order
+    pub strict_encoding: bool, // Defined sighash types and well formed public keys
+    pub null_fail: bool,       // A failed signature check must have been given an empty signature
+    pub minimal_if: bool,      // Witness v0 OP_IF arguments are exactly empty or 0x01
+    pub clean_stack: bool,     // Legacy and P2SH spends leave exactly one item, as witness spends must
+}
+
+impl ScriptFlags {
+    pub const CONSENSUS: ScriptFlags =
+        ScriptFlags { low_s: false, strict_encoding: false, null_fail: false, minimal_if: false, clean_stack: false };
+    pub const STANDARD: ScriptFlags =
+        ScriptFlags { low_s: true, strict_encoding: true, null_fail: true, minimal_if: true, clean_stack: true };
+}
+
+// THE TRANSACTION INPUT A SCRIPT IS BEING EXECUTED FOR
+pub struct ScriptContext<'a> {
+    pub tx: &'a Transaction,
+    pub input_index: usize,
+    pub sig_version: SigVersion,
+    pub tapscript: Option<TapscriptExecution>,
+    pub flags: ScriptFlags,
+}
+
+// WHAT A TAPSCRIPT SIGNATURE COMMITS TO BEYOND THE TRANSACTION, AND ITS SIGOP BUDGET
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TapscriptExecution {
+    pub leaf_hash: Vec<u8>,
+    pub annex: Option<Vec<u8>>,
+    pub validation_weight: i64,
+}
+
+// A SINGLE PARSED OPERATION: THE OPCODE AND, FOR PUSHES, THE PUSHED BYTES
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction<'a> {
+    pub opcode: u8,
+    pub push_data: Option<&'a [u8]>,
+}
+
+pub struct Instructions<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Instructions<'a> {
+    // BYTE OFFSET OF THE NEXT INSTRUCTION
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = std::result::Result<Instruction<'a>, ScriptError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let opcode = *self.bytes.get(self.position)?;
+        self.position += 1;
+
+        let push_len = match opcode {
+            0x01..=OP_PUSHBYTES_75 => opcode as usize,
+            OP_PUSHDATA1 | OP_PUSHDATA2 | OP_PUSHDATA4 => {
+                let width = match opcode {
+                    OP_PUSHDATA1 => 1,
+                    OP_PUSHDATA2 => 2,
+                    _ => 4,
+                };
+                let Some(len_bytes) = self.bytes.get(self.position..self.position + width) else {
+                    self.position = self.bytes.len();
+                    return Some(Err(ScriptError { kind: ScriptErrorKind::BadPush, opcode: Some(opcode) }));
+                };
+                self.position += width;
+                len_bytes.iter().rev().fold(0usize, |acc, byte| (acc << 8) | *byte as usize)
+            }
+            OP_0 => return Some(Ok(Instruction { opcode, push_data: Some(&[]) })),
+            _ => return Some(Ok(Instruction { opcode, push_data: None })),
+        };
+
+        match self.bytes.get(self.position..self.position.saturating_add(push_len)) {
+            Some(data) => {
+                self.position += push_len;
+                Some(Ok(Instruction { opcode, push_data: Some(data) }))
+            }
+            None => {
+                self.position = self.bytes.len();
+                Some(Err(ScriptError { kind: ScriptErrorKind::BadPush, opcode: Some(opcode) }))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Script {
+    bytes: Vec<u8>,
+}
+
+impl Script {
+    pub fn new(bytes: Vec<u8>) -> Script {
+        Script { bytes }
+    }
+
+    pub fn from_hex(script_hex: &str) -> Result<Script> {
+        Ok(Script::new(hex::decode(script_hex)?))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn instructions(&self) -> Instructions<'_> {
+        Instructions { bytes: &self.bytes, position: 0 }
+    }
+
+    // ONLY PUSH OPERATIONS (OP_0..OP_16, OP_1NEGATE AND DATA PUSHES)
+    pub fn is_push_only(&self) -> bool {
+        self.instructions().all(|instruction| match instruction {
+            Ok(instruction) => instruction.opcode <= OP_16,
+            Err(_) => false,
+        })
+    }
+
+    // SIGNATURE OPERATIONS THE WAY `GetSigOpCount` COUNTS THEM. CHECKMULTISIG COUNTS AS ITS KEY COUNT
+    // WHEN `accurate` AND AN OP_1..OP_16 COMES RIGHT BEFORE IT, OTHERWISE AS THE 20 KEY MAXIMUM.
+    // COUNTING STOPS AT A BROKEN PUSH, LIKE IT DOES IN BITCOIN CORE.
+    pub fn sigop_count(&self, accurate: bool) -> usize {
+        let mut count = 0;
+        let mut last_opcode = None;
+        for instruction in self.instructions() {
+            let Ok(instruction) = instruction else {
+                break;
+            };
+            match instruction.opcode {
+                OP_CHECKSIG | OP_CHECKSIGVERIFY => count += 1,
+                OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => match last_opcode {
+                    Some(n @ OP_1..=OP_16) if accurate => count += (n - OP_1 + 1) as usize,
+                    _ => count += MAX_PUBKEYS_PER_MULTISIG as usize,
+                },
+                _ => {}
+            }
+            last_opcode = Some(instruction.opcode);
+        }
+        count
+    }
+
+    // RETURNS THE WITNESS VERSION AND PROGRAM IF THE SCRIPT IS A SEGWIT OUTPUT
+    pub fn witness_program(&self) -> Option<(u8, &[u8])> {
+        if self.bytes.len() < 4 || self.bytes.len() > 42 {
+            return None;
+        }
+        let version = match self.bytes[0] {
+            OP_0 => 0,
+            OP_1..=OP_16 => self.bytes[0] - OP_1 + 1,
+            _ => return None,
+        };
+        if self.bytes[1] as usize + 2 != self.bytes.len() {
+            return None;
+        }
+        Some((version, &self.bytes[2..]))
+    }
+
+    // ESPLORA STYLE ASM, E.G. "OP_DUP OP_HASH160 OP_PUSHBYTES_20 <HEX> OP_EQUALVERIFY OP_CHECKSIG"
+    pub fn to_asm(&self) -> String {
+        let mut asm: Vec<String> = Vec::new();
+        for instruction in self.instructions() {
+            match instruction {
+                Ok(Instruction { opcode: OP_0, .. }) => asm.push(opcode_name(OP_0)),
+                Ok(Instruction { opcode, push_data: Some(data) }) => {
+                    asm.push(format!("{} {}", opcode_name(opcode), hex::encode(data)))
+                }
+                Ok(Instruction { opcode, push_data: None }) => asm.push(opcode_name(opcode)),
+                Err(_) => {
+                    asm.push("<push past end>".to_string());
+                    break;
+                }
+            }
+        }
+        asm.join(" ")
+    }
+
+    // OUTPUT TYPE NAMES AS THEY APPEAR IN THE MEMPOOL JSON
+    pub fn script_type(&self) -> &'static str {
+        let bytes = self.as_bytes();
+        match self.witness_program() {
+            Some((0, program)) if program.len() == 20 => return "v0_p2wpkh",
+            Some((0, program)) if program.len() == 32 => return "v0_p2wsh",
+            Some((1, program)) if program.len() == 32 => return "v1_p2tr",
+            _ => {}
+        }
+
+        match bytes {
+            [OP_DUP, OP_HASH160, 0x14, .., OP_EQUALVERIFY, OP_CHECKSIG] if bytes.len() == 25 => "p2pkh",
+            [OP_HASH160, 0x14, .., OP_EQUAL] if bytes.len() == 23 => "p2sh",
+            [0x21, .., OP_CHECKSIG] if bytes.len() == 35 => "p2pk",
+            [0x41, .., OP_CHECKSIG] if bytes.len() == 67 => "p2pk",
+            [OP_RETURN, ..] => "op_return",
+            _ => "unknown",
+        }
+    }
+
+    // RUNS THE SCRIPT ON TOP OF THE GIVEN STACK
+    pub fn execute(
+        &self,
+        stack: &mut Vec<Vec<u8>>,
+        ctx: &ScriptContext,
+    ) -> std::result::Result<(), ScriptError> {
+        // TAPSCRIPTS ARE ONLY BOUNDED BY THE BLOCK WEIGHT
+        if ctx.sig_version != SigVersion::Tapscript && self.bytes.len() > MAX_SCRIPT_SIZE {
+            return Err(ScriptErrorKind::ScriptSize.into());
+        }
+
+        let mut interpreter = Interpreter {
+            script: self,
+            ctx,
+            altstack: Vec::new(),
+            condition_stack: Vec::new(),
+            op_count: 0,
+            codeseparator_position: 0,
+            opcode_position: 0,
+            codeseparator_opcode: u32::MAX,
+            validation_weight: ctx.tapscript.as_ref().map(|tapscript| tapscript.validation_weight).unwrap_or(0),
+        };
+
+        let mut instructions = self.instructions();
+        while let Some(instruction) = instructions.next() {
+            let instruction = instruction?;
+            interpreter
+                .step(stack, &instruction, instructions.position())
+                .map_err(|kind| ScriptError { kind, opcode: Some(instruction.opcode) })?;
+            interpreter.opcode_position += 1;
+        }
+
+        if !interpreter.condition_stack.is_empty() {
+            return Err(ScriptErrorKind::UnbalancedConditional.into());
+        }
+        Ok(())
+    }
+}
+
+// INTERPRETER STATE THAT LIVES FOR ONE SCRIPT EXECUTION
+struct Interpreter<'a, 'b> {
+    script: &'a Script,
+    ctx: &'a ScriptContext<'b>,
+    altstack: Vec<Vec<u8>>,
+    condition_stack: Vec<bool>,
+    op_count: usize,
+    codeseparator_position: usize,
+    opcode_position: u32, // Index of the current opcode, tapscript signatures commit to it
+    codeseparator_opcode: u32,
+    validation_weight: i64,
+}
+
+type StepResult = std::result::Result<(), ScriptErrorKind>;
+
+impl<'a, 'b> Interpreter<'a, 'b> {
+    fn executing(&self) -> bool {
+        self.condition_stack.iter().all(|branch| *branch)
+    }
+
+    fn step(&mut self, stack: &mut Vec<Vec<u8>>, instruction: &Instruction, next_position: usize) -> StepResult {
+        let opcode = instruction.opcode;
+        let executing = self.executing();
+
+        if let Some(data) = instruction.push_data {
+            if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                return Err(ScriptErrorKind::PushSize);
+            }
+            if executing {
+                stack.push(data.to_vec());
+            }
+            return self.check_stack_size(stack);
+        }
+
+        if opcode > OP_16 && self.ctx.sig_version != SigVersion::Tapscript {
+            self.op_count += 1;
+            if self.op_count > MAX_OPS_PER_SCRIPT {
+                return Err(ScriptErrorKind::OpCount);
+            }
+        }
+
+        // DISABLED OPCODES FAIL EVEN IN AN UNEXECUTED BRANCH
+        if matches!(
+            opcode,
+            OP_CAT | OP_SUBSTR | OP_LEFT | OP_RIGHT | OP_INVERT | OP_AND | OP_OR | OP_XOR
+                | OP_2MUL | OP_2DIV | OP_MUL | OP_DIV | OP_MOD | OP_LSHIFT | OP_RSHIFT
+        ) {
+            return Err(ScriptErrorKind::DisabledOpcode);
+        }
+
+        if !executing && !(OP_IF..=OP_ENDIF).contains(&opcode) {
+            return Ok(());
+        }
+
+        match opcode {
+            OP_1NEGATE | OP_1..=OP_16 => {
+                let value = opcode as i64 - (OP_1 as i64 - 1);
+                stack.push(encode_num(value));
+            }
+
+            OP_NOP | OP_NOP1 | OP_NOP4..=OP_NOP10 => {}
+
+            // BIP65 AND BIP112 LEAVE THE ARGUMENT ON THE STACK. IT MAY TAKE FIVE BYTES, A LOCKTIME OR
+            // SEQUENCE WITH THE TOP BIT SET DOES NOT FIT IN A FOUR BYTE SCRIPT NUMBER.
+            OP_CHECKLOCKTIMEVERIFY => {
+                let lock_time = decode_num(peek(stack, 1)?, 5)?;
+                if lock_time < 0 {
+                    return Err(ScriptErrorKind::NegativeLockTime);
+                }
+                self.check_lock_time(lock_time)?;
+            }
+
+            OP_CHECKSEQUENCEVERIFY => {
+                let sequence = decode_num(peek(stack, 1)?, 5)?;
+                if sequence < 0 {
+                    return Err(ScriptErrorKind::NegativeLockTime);
+                }
+
+                // WITH THE DISABLE FLAG SET IT STAYS A NOP, KEPT FOR FUTURE SOFT FORKS
+                if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG as i64 == 0 {
+                    self.check_sequence(sequence)?;
+                }
+            }
+
+            OP_IF | OP_NOTIF => {
+                let mut branch = false;
+                if executing {
+                    let top = stack.pop().ok_or(ScriptErrorKind::UnbalancedConditional)?;
+
+                    // TAPSCRIPT BRANCHES ON EXACTLY EMPTY OR 0x01 (MINIMALIF), WITNESS V0 ONLY BY POLICY
+                    let minimal = top.is_empty() || top == [1];
+                    if self.ctx.sig_version == SigVersion::Tapscript && !minimal {
+                        return Err(ScriptErrorKind::TapscriptMinimalIf);
+                    }
+                    if self.ctx.flags.minimal_if && self.ctx.sig_version == SigVersion::WitnessV0 && !minimal {
+                        return Err(ScriptErrorKind::MinimalIf);
+                    }
+                    branch = cast_to_bool(&top);
+                    if opcode == OP_NOTIF {
+                        branch = !branch;
+                    }
+                }
+                self.condition_stack.push(branch);
+            }
+
+            OP_ELSE => {
+                let last = self.condition_stack.last_mut().ok_or(ScriptErrorKind::UnbalancedConditional)?;
+                *last = !*last;
+            }
+
+            OP_ENDIF => {
+                self.condition_stack.pop().ok_or(ScriptErrorKind::UnbalancedConditional)?;
+            }
+
+            OP_VERIFY => {
+                let top = pop(stack)?;
+                if !cast_to_bool(&top) {
+                    return Err(ScriptErrorKind::Verify);
+                }
+            }
+
+            OP_RETURN => return Err(ScriptErrorKind::OpReturn),
+
+            OP_TOALTSTACK => {
+                let top = pop(stack)?;
+                self.altstack.push(top);
+            }
+
+            OP_FROMALTSTACK => {
+                let top = self.altstack.pop().ok_or(ScriptErrorKind::InvalidAltstackOperation)?;
+                stack.push(top);
+            }
+
+            OP_2DROP => {
+                pop(stack)?;
+                pop(stack)?;
+            }
+
+            OP_2DUP => {
+                let a = peek(stack, 2)?.clone();
+                let b = peek(stack, 1)?.clone();
+                stack.push(a);
+                stack.push(b);
+            }
+
+            OP_3DUP => {
+                let a = peek(stack, 3)?.clone();
+                let b = peek(stack, 2)?.clone();
+                let c = peek(stack, 1)?.clone();
+                stack.push(a);
+                stack.push(b);
+                stack.push(c);
+            }
+
+            OP_2OVER => {
+                let a = peek(stack, 4)?.clone();
+                let b = peek(stack, 3)?.clone();
+                stack.push(a);
+                stack.push(b);
+            }
+
+            OP_2ROT => {
+                require(stack, 6)?;
+                let index = stack.len() - 6;
+                let a = stack.remove(index);
+                let b = stack.remove(index);
+                stack.push(a);
+                stack.push(b);
+            }
+
+            OP_2SWAP => {
+                require(stack, 4)?;
+                let len = stack.len();
+                stack.swap(len - 4, len - 2);
+                stack.swap(len - 3, len - 1);
+            }
+
+            OP_IFDUP => {
+                let top = peek(stack, 1)?.clone();
+                if cast_to_bool(&top) {
+                    stack.push(top);
+                }
+            }
+
+            OP_DEPTH => {
+                stack.push(encode_num(stack.len() as i64));
+            }
+
+            OP_DROP => {
+                pop(stack)?;
+            }
+
+            OP_DUP => {
+                let top = peek(stack, 1)?.clone();
+                stack.push(top);
+            }
+
+            OP_NIP => {
+                require(stack, 2)?;
+                let index = stack.len() - 2;
+                stack.remove(index);
+            }
+
+            OP_OVER => {
+                let item = peek(stack, 2)?.clone();
+                stack.push(item);
+            }
+
+            OP_PICK | OP_ROLL => {
+                let n = decode_num(&pop(stack)?, 4)?;
+                if n < 0 || n as usize >= stack.len() {
+                    return Err(ScriptErrorKind::InvalidStackOperation);
+                }
+                let index = stack.len() - 1 - n as usize;
+                let item = if opcode == OP_ROLL {
+                    stack.remove(index)
+                } else {
+                    stack[index].clone()
+                };
+                stack.push(item);
+            }
+
+            OP_ROT => {
+                require(stack, 3)?;
+                let index = stack.len() - 3;
+                let item = stack.remove(index);
+                stack.push(item);
+            }
+
+            OP_SWAP => {
+                require(stack, 2)?;
+                let len = stack.len();
+                stack.swap(len - 2, len - 1);
+            }
+
+            OP_TUCK => {
+                require(stack, 2)?;
+                let top = peek(stack, 1)?.clone();
+                let index = stack.len() - 2;
+                stack.insert(index, top);
+            }
+
+            OP_SIZE => {
+                let size = peek(stack, 1)?.len();
+                stack.push(encode_num(size as i64));
+            }
+
+            OP_EQUAL | OP_EQUALVERIFY => {
+                let a = pop(stack)?;
+                let b = pop(stack)?;
+                let equal = a == b;
+                if opcode == OP_EQUALVERIFY {
+                    if !equal {
+                        return Err(ScriptErrorKind::EqualVerify);
+                    }
+                } else {
+                    stack.push(encode_bool(equal));
+                }
+            }
+
+            OP_1ADD | OP_1SUB | OP_NEGATE | OP_ABS | OP_NOT | OP_0NOTEQUAL => {
+                let n = decode_num(&pop(stack)?, 4)?;
+                let result = match opcode {
+                    OP_1ADD => n + 1,
+                    OP_1SUB => n - 1,
+                    OP_NEGATE => -n,
+                    OP_ABS => n.abs(),
+                    OP_NOT => (n == 0) as i64,
+                    _ => (n != 0) as i64,
+                };
+                stack.push(encode_num(result));
+            }
+
+            OP_ADD | OP_SUB | OP_BOOLAND | OP_BOOLOR | OP_NUMEQUAL | OP_NUMEQUALVERIFY
+            | OP_NUMNOTEQUAL | OP_LESSTHAN | OP_GREATERTHAN | OP_LESSTHANOREQUAL
+            | OP_GREATERTHANOREQUAL | OP_MIN | OP_MAX => {
+                require(stack, 2)?;
+                let b = decode_num(&pop(stack)?, 4)?;
+                let a = decode_num(&pop(stack)?, 4)?;
+                let result = match opcode {
+                    OP_ADD => a + b,
+                    OP_SUB => a - b,
+                    OP_BOOLAND => (a != 0 && b != 0) as i64,
+                    OP_BOOLOR => (a != 0 || b != 0) as i64,
+                    OP_NUMEQUAL | OP_NUMEQUALVERIFY => (a == b) as i64,
+                    OP_NUMNOTEQUAL => (a != b) as i64,
+                    OP_LESSTHAN => (a < b) as i64,
+                    OP_GREATERTHAN => (a > b) as i64,
+                    OP_LESSTHANOREQUAL => (a <= b) as i64,
+                    OP_GREATERTHANOREQUAL => (a >= b) as i64,
+                    OP_MIN => a.min(b),
+                    _ => a.max(b),
+                };
+                if opcode == OP_NUMEQUALVERIFY {
+                    if result == 0 {
+                        return Err(ScriptErrorKind::NumEqualVerify);
+                    }
+                } else {
+                    stack.push(encode_num(result));
+                }
+            }
+
+            OP_WITHIN => {
+                require(stack, 3)?;
+                let max = decode_num(&pop(stack)?, 4)?;
+                let min = decode_num(&pop(stack)?, 4)?;
+                let x = decode_num(&pop(stack)?, 4)?;
+                stack.push(encode_bool(min <= x && x < max));
+            }
+
+            OP_RIPEMD160 | OP_SHA1 | OP_SHA256 | OP_HASH160 | OP_HASH256 => {
+                let top = pop(stack)?;
+                let hash = match opcode {
+                    OP_RIPEMD160 => Ripemd160::digest(&top).to_vec(),
+                    OP_SHA1 => Sha1::digest(&top).to_vec(),
+                    OP_SHA256 => single_sha256(&top),
+                    OP_HASH160 => hash160(&top),
+                    _ => double_sha256(&top),
+                };
+                stack.push(hash);
+            }
+
+            OP_CODESEPARATOR => {
+                self.codeseparator_position = next_position;
+                self.codeseparator_opcode = self.opcode_position;
+            }
+
+            OP_CHECKSIG | OP_CHECKSIGVERIFY if self.ctx.sig_version == SigVersion::Tapscript => {
+                require(stack, 2)?;
+                let pubkey = pop(stack)?;
+                let signature = pop(stack)?;
+
+                let valid = self.check_schnorr(&signature, &pubkey)?;
+
+                if opcode == OP_CHECKSIGVERIFY {
+                    if !valid {
+                        return Err(ScriptErrorKind::CheckSigVerify);
+                    }
+                } else {
+                    stack.push(encode_bool(valid));
+                }
+            }
+
+            // <SIG> <N> <PUBKEY> OP_CHECKSIGADD -> <N + 1 IF THE SIGNATURE IS VALID>
+            OP_CHECKSIGADD if self.ctx.sig_version == SigVersion::Tapscript => {
+                require(stack, 3)?;
+                let pubkey = pop(stack)?;
+                let n = decode_num(&pop(stack)?, 4)?;
+                let signature = pop(stack)?;
+
+                let valid = self.check_schnorr(&signature, &pubkey)?;
+                stack.push(encode_num(n + valid as i64));
+            }
+
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY if self.ctx.sig_version == SigVersion::Tapscript => {
+                return Err(ScriptErrorKind::TapscriptCheckMultisig);
+            }
+
+            OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+                require(stack, 2)?;
+                let pubkey = pop(stack)?;
+                let signature = pop(stack)?;
+
+                let script_code = self.script_code(&[&signature]);
+                let valid = self.check_sig(&signature, &pubkey, &script_code)?;
+                if !valid && self.ctx.flags.null_fail && !signature.is_empty() {
+                    return Err(ScriptErrorKind::NullFail);
+                }
+
+                if opcode == OP_CHECKSIGVERIFY {
+                    if !valid {
+                        return Err(ScriptErrorKind::CheckSigVerify);
+                    }
+                } else {
+                    stack.push(encode_bool(valid));
+                }
+            }
+
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                let valid = self.check_multisig(stack)?;
+
+                if opcode == OP_CHECKMULTISIGVERIFY {
+                    if !valid {
+                        return Err(ScriptErrorKind::CheckMultisigVerify);
+                    }
+                } else {
+                    stack.push(encode_bool(valid));
+                }
+            }
+
+            // OP_RESERVED, OP_VER, OP_VERIF, OP_VERNOTIF, OP_RESERVED1/2 AND UNDEFINED OPCODES
+            _ => return Err(ScriptErrorKind::BadOpcode),
+        }
+
+        self.check_stack_size(stack)
+    }
+
+    // CHECKED AFTER EVERY INSTRUCTION, PUSHES INCLUDED, SO A SCRIPT CANNOT END WITH TOO MUCH ON THE STACKS
+    fn check_stack_size(&self, stack: &[Vec<u8>]) -> StepResult {
+        if stack.len() + self.altstack.len() > MAX_STACK_SIZE {
+            return Err(ScriptErrorKind::StackSize);
+        }
+        Ok(())
+    }
+
+    // THE PART OF THE SCRIPT THAT IS SIGNED: EVERYTHING AFTER THE LAST EXECUTED OP_CODESEPARATOR
+    fn script_code(&self, signatures: &[&[u8]]) -> Vec<u8> {
+        let mut script_code = self.script.as_bytes()[self.codeseparator_position..].to_vec();
+
+        // LEGACY SCRIPTS CANNOT SIGN THEIR OWN SIGNATURES, NOR ANY OP_CODESEPARATOR LEFT IN THE SCRIPT CODE
+        if self.ctx.sig_version == SigVersion::Base {
+            for signature in signatures {
+                script_code = find_and_delete(&script_code, &push_data(signature));
+            }
+            script_code = remove_codeseparators(&script_code);
+        }
+        script_code
+    }
+
+    fn check_sig(&self, signature: &[u8], pubkey: &[u8], script_code: &[u8]) -> std::result::Result<bool, ScriptErrorKind> {
+        let flags = self.ctx.flags;
+        if !signature.is_empty() {
+            if !is_valid_signature_encoding(signature) {
+                return Err(ScriptErrorKind::SigDer);
+            }
+            if flags.low_s && !is_low_der_signature(signature) {
+                return Err(ScriptErrorKind::SigHighS);
+            }
+            if flags.strict_encoding && !is_defined_hashtype_signature(signature) {
+                return Err(ScriptErrorKind::SigHashType);
+            }
+        }
+        if flags.strict_encoding && !is_valid_pubkey_encoding(pubkey, self.ctx.sig_version) {
+            return Err(ScriptErrorKind::PubkeyType);
+        }
+        if signature.is_empty() {
+            return Ok(false);
+        }
+
+        check_ecdsa_signature(
+            signature,
+            pubkey,
+            self.ctx.tx,
+            self.ctx.input_index,
+            script_code,
+            self.ctx.sig_version,
+        )
+        .map_err(|_| ScriptErrorKind::Sighash)
+    }
+
+    // BIP65: THE TRANSACTION'S NLOCKTIME MUST BE OF THE SAME KIND AS THE SCRIPT'S AND AT LEAST AS LATE
+    fn check_lock_time(&self, lock_time: i64) -> StepResult {
+        let tx_lock_time = self.ctx.tx.locktime as i64;
+        let threshold = LOCKTIME_THRESHOLD as i64;
+        if (tx_lock_time < threshold) != (lock_time < threshold) || lock_time > tx_lock_time {
+            return Err(ScriptErrorKind::UnsatisfiedLockTime);
+        }
+
+        // A FINAL SEQUENCE WOULD LET THE INPUT SKIP THE NLOCKTIME CHECK ALTOGETHER
+        let input = self.ctx.tx.vin.get(self.ctx.input_index).ok_or(ScriptErrorKind::UnsatisfiedLockTime)?;
+        if input.sequence == SEQUENCE_FINAL {
+            return Err(ScriptErrorKind::UnsatisfiedLockTime);
+        }
+        Ok(())
+    }
+
+    // BIP112: THE INPUT'S OWN BIP68 LOCK MUST BE OF THE SAME KIND AS THE SCRIPT'S AND AT LEAST AS LONG
+    fn check_sequence(&self, sequence: i64) -> StepResult {
+        let input = self.ctx.tx.vin.get(self.ctx.input_index).ok_or(ScriptErrorKind::UnsatisfiedLockTime)?;
+        let tx_sequence = input.sequence as i64;
+
+        // BIP68 HAS TO BE IN FORCE FOR THE INPUT, OTHERWISE ITS SEQUENCE LOCKS NOTHING
+        if self.ctx.tx.version < 2 || tx_sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG as i64 != 0 {
+            return Err(ScriptErrorKind::UnsatisfiedLockTime);
+        }
+
+        let mask = (SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK) as i64;
+        let (tx_masked, masked) = (tx_sequence & mask, sequence & mask);
+        let type_flag = SEQUENCE_LOCKTIME_TYPE_FLAG as i64;
+        if (tx_masked & type_flag) != (masked & type_flag) || masked > tx_masked {
+            return Err(ScriptErrorKind::UnsatisfiedLockTime);
+        }
+        Ok(())
+    }
+
+    // BIP342 SIGNATURE CHECK: AN EMPTY SIGNATURE IS FALSE, ANY OTHER FAILURE ENDS THE SCRIPT
+    fn check_schnorr(&mut self, signature: &[u8], pubkey: &[u8]) -> std::result::Result<bool, ScriptErrorKind> {
+        if pubkey.is_empty() {
+            return Err(ScriptErrorKind::TapscriptEmptyPubkey);
+        }
+        if signature.is_empty() {
+            return Ok(false);
+        }
+
+        self.validation_weight -= VALIDATION_WEIGHT_PER_SIGOP_PASSED;
+        if self.validation_weight < 0 {
+            return Err(ScriptErrorKind::TapscriptValidationWeight);
+        }
+
+        // PUBLIC KEYS OF OTHER SIZES ARE RESERVED FOR FUTURE UPGRADES AND ALWAYS PASS
+        if pubkey.len() != 32 {
+            return Ok(true);
+        }
+
+        let tapscript = self.ctx.tapscript.as_ref().ok_or(ScriptErrorKind::SchnorrSig)?;
+        let leaf = (tapscript.leaf_hash.as_slice(), self.codeseparator_opcode);
+        let valid = check_schnorr_signature(
+            signature,
+            pubkey,
+            self.ctx.tx,
+            self.ctx.input_index,
+            tapscript.annex.as_deref(),
+            Some(leaf),
+        )?;
+        if !valid {
+            return Err(ScriptErrorKind::SchnorrSig);
+        }
+        Ok(true)
+    }
+
+    fn check_multisig(&mut self, stack: &mut Vec<Vec<u8>>) -> std::result::Result<bool, ScriptErrorKind> {
+        // <DUMMY> <SIG_1> .. <SIG_M> <M> <PUBKEY_1> .. <PUBKEY_N> <N>
+        let n_keys = decode_num(&pop(stack)?, 4)?;
+        if !(0..=MAX_PUBKEYS_PER_MULTISIG).contains(&n_keys) {
+            return Err(ScriptErrorKind::PubkeyCount);
+        }
+        self.op_count += n_keys as usize;
+        if self.op_count > MAX_OPS_PER_SCRIPT {
+            return Err(ScriptErrorKind::OpCount);
+        }
+        require(stack, n_keys as usize)?;
+        let mut pubkeys = stack.split_off(stack.len() - n_keys as usize);
+
+        let n_signatures = decode_num(&pop(stack)?, 4)?;
+        if n_signatures < 0 || n_signatures > n_keys {
+            return Err(ScriptErrorKind::SigCount);
+        }
+        require(stack, n_signatures as usize)?;
+        let mut signatures = stack.split_off(stack.len() - n_signatures as usize);
+
+        // THE OFF-BY-ONE DUMMY ELEMENT MUST BE EMPTY (NULLDUMMY)
+        let dummy = pop(stack)?;
+        if !dummy.is_empty() {
+            return Err(ScriptErrorKind::SigNullDummy);
+        }
+
+        let signature_refs: Vec<&[u8]> = signatures.iter().map(|sig| sig.as_slice()).collect();
+        let script_code = self.script_code(&signature_refs);
+
+        // SIGNATURES HAVE TO MATCH THE PUBLIC KEYS IN ORDER, TOP OF THE STACK FIRST
+        pubkeys.reverse();
+        signatures.reverse();
+
+        let mut key_index = 0;
+        let mut sig_index = 0;
+        let mut valid = true;
+        while sig_index < signatures.len() {
+            if signatures.len() - sig_index > pubkeys.len() - key_index {
+                valid = false;
+                break;
+            }
+            if self.check_sig(&signatures[sig_index], &pubkeys[key_index], &script_code)? {
+                sig_index += 1;
+            }
+            key_index += 1;
+        }
+
+        if !valid && self.ctx.flags.null_fail && signatures.iter().any(|signature| !signature.is_empty()) {
+            return Err(ScriptErrorKind::NullFail);
+        }
+        Ok(valid)
+    }
+}
+
+fn require(stack: &[Vec<u8>], depth: usize) -> StepResult {
+    if stack.len() < depth {
+        return Err(ScriptErrorKind::InvalidStackOperation);
+    }
+    Ok(())
+}
+
+fn pop(stack: &mut Vec<Vec<u8>>) -> std::result::Result<Vec<u8>, ScriptErrorKind> {
+    stack.pop().ok_or(ScriptErrorKind::InvalidStackOperation)
+}
+
+// RETURNS THE ITEM `depth` POSITIONS FROM THE TOP (1 IS THE TOP)
+fn peek(stack: &[Vec<u8>], depth: usize) -> std::result::Result<&Vec<u8>, ScriptErrorKind> {
+    require(stack, depth)?;
+    Ok(&stack[stack.len() - depth])
+}
+
+// ANY NON-ZERO BYTE IS TRUE, EXCEPT A LONE SIGN BIT IN THE LAST BYTE (NEGATIVE ZERO)
+pub fn cast_to_bool(bytes: &[u8]) -> bool {
+    for (index, byte) in bytes.iter().enumerate() {
+        if *byte != 0 {
+            return !(index == bytes.len() - 1 && *byte == 0x80);
+        }
+    }
+    false
+}
+
+fn encode_bool(value: bool) -> Vec<u8> {
+    if value {
+        vec![1u8]
+    } else {
+        Vec::new()
+    }
+}
+
+// LITTLE ENDIAN SIGN-MAGNITUDE NUMBER ENCODING
+pub fn encode_num(value: i64) -> Vec<u8> {
+    let mut result = Vec::new();
+    if value == 0 {
+        return result;
+    }
+
+    let negative = value < 0;
+    let mut magnitude = value.unsigned_abs();
+    while magnitude > 0 {
+        result.push((magnitude & 0xff) as u8);
+        magnitude >>= 8;
+    }
+
+    let last = result.len() - 1;
+    if result[last] & 0x80 != 0 {
+        result.push(if negative { 0x80 } else { 0x00 });
+    } else if negative {
+        result[last] |= 0x80;
+    }
+    result
+}
+
+pub fn decode_num(bytes: &[u8], max_len: usize) -> std::result::Result<i64, ScriptErrorKind> {
+    if bytes.len() > max_len {
+        return Err(ScriptErrorKind::NumOverflow);
+    }
+    if bytes.is_empty() {
+        return Ok(0);
+    }
+
+    let mut value: i64 = 0;
+    for (index, byte) in bytes.iter().enumerate() {
+        value |= (*byte as i64) << (8 * index);
+    }
+
+    let sign_bit = 0x80i64 << (8 * (bytes.len() - 1));
+    if value & sign_bit != 0 {
+        return Ok(-(value & !sign_bit));
+    }
+    Ok(value)
+}
+
+// ENCODES `data` AS THE SMALLEST PUSH OPERATION THAT CAN CARRY IT
+pub fn push_data(data: &[u8]) -> Vec<u8> {
+    let mut script = Vec::with_capacity(data.len() + 5);
+    match data.len() {
+        0..=75 => script.push(data.len() as u8),
+        76..=0xff => {
+            script.push(OP_PUSHDATA1);
+            script.push(data.len() as u8);
+        }
+        0x100..=0xffff => {
+            script.push(OP_PUSHDATA2);
+            script.extend((data.len() as u16).to_le_bytes());
+        }
+        _ => {
+            script.push(OP_PUSHDATA4);
+            script.extend((data.len() as u32).to_le_bytes());
+        }
+    }
+    script.extend_from_slice(data);
+    script
+}
+
+// REMOVES EVERY OCCURRENCE OF `pattern` THAT STARTS ON AN OPCODE BOUNDARY
+fn find_and_delete(script: &[u8], pattern: &[u8]) -> Vec<u8> {
+    if pattern.is_empty() {
+        return script.to_vec();
+    }
+
+    let mut result = Vec::with_capacity(script.len());
+    let mut instructions = Instructions { bytes: script, position: 0 };
+    let mut copied_until = 0;
+
+    loop {
+        let mut position = instructions.position();
+        result.extend_from_slice(&script[copied_until..position]);
+        while script[position..].starts_with(pattern) {
+            position += pattern.len();
+        }
+        copied_until = position;
+
+        instructions.position = position;
+        match instructions.next() {
+            Some(Ok(_)) => {}
+            _ => break,
+        }
+    }
+    result.extend_from_slice(&script[copied_until..]);
+    result
+}
+
+// SAME AS BITCOIN CORE'S `SerializeScriptCode`: EVERY OP_CODESEPARATOR OPCODE GOES, PUSHED BYTES THAT
+// HAPPEN TO EQUAL IT STAY. AN UNPARSABLE TAIL IS KEPT AS IT IS.
+fn remove_codeseparators(script: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(script.len());
+    let mut instructions = Instructions { bytes: script, position: 0 };
+    let mut copied_from = 0;
+
+    loop {
+        let position = instructions.position();
+        match instructions.next() {
+            Some(Ok(instruction)) if instruction.opcode == OP_CODESEPARATOR => {
+                result.extend_from_slice(&script[copied_from..position]);
+                copied_from = instructions.position();
+            }
+            Some(Ok(_)) => {}
+            _ => break,
+        }
+    }
+    result.extend_from_slice(&script[copied_from..]);
+    result
+}
+
+// BIP66 STRICT DER ENCODING CHECK (INCLUDING THE TRAILING SIGHASH BYTE)
+pub fn is_valid_signature_encoding(sig: &[u8]) -> bool {
+    if sig.len() < 9 || sig.len() > 73 {
+        return false;
+    }
+    if sig[0] != 0x30 || sig[1] as usize != sig.len() - 3 {
+        return false;
+    }
+
+    let len_r = sig[3] as usize;
+    if 5 + len_r >= sig.len() {
+        return false;
+    }
+    let len_s = sig[5 + len_r] as usize;
+    if len_r + len_s + 7 != sig.len() {
+        return false;
+    }
+
+    if sig[2] != 0x02 || len_r == 0 || sig[4] & 0x80 != 0 {
+        return false;
+    }
+    if len_r > 1 && sig[4] == 0x00 && sig[5] & 0x80 == 0 {
+        return false;
+    }
+
+    if sig[len_r + 4] != 0x02 || len_s == 0 || sig[len_r + 6] & 0x80 != 0 {
+        return false;
+    }
+    if len_s > 1 && sig[len_r + 6] == 0x00 && sig[len_r + 7] & 0x80 == 0 {
+        return false;
+    }
+    true
+}
+
+// S AT MOST HALF THE CURVE ORDER, THE OTHER OF THE TWO VALID S VALUES IS A MALLEATED COPY
+pub fn is_low_der_signature(sig: &[u8]) -> bool {
+    let Some((_, der)) = sig.split_last() else {
+        return false;
+    };
+    match Signature::from_der_lax(der) {
+        Ok(signature) => {
+            let mut normalised = signature;
+            normalised.normalize_s();
+            normalised == signature
+        }
+        Err(_) => false,
+    }
+}
+
+pub fn is_defined_hashtype_signature(sig: &[u8]) -> bool {
+    match sig.last() {
+        Some(sighash_type) => (SIGHASH_ALL..=SIGHASH_SINGLE).contains(&(*sighash_type as u32 & !SIGHASH_ANYONECANPAY)),
+        None => false,
+    }
+}
+
+// COMPRESSED OR UNCOMPRESSED, AND ONLY COMPRESSED INSIDE WITNESS V0 SCRIPTS
+pub fn is_valid_pubkey_encoding(pubkey: &[u8], sig_version: SigVersion) -> bool {
+    match pubkey {
+        [0x02 | 0x03, ..] => pubkey.len() == 33,
+        [0x04, ..] => pubkey.len() == 65 && sig_version != SigVersion::WitnessV0,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+
+    use super::*;
+    use crate::transaction::{Input, Prevout};
+    use crate::validation_checks::signature_hash;
+
+    fn dummy_tx() -> Transaction {
+        Transaction {
+            version: 2,
+            locktime: 0,
+            vin: vec![Input {
+                txid: "00".repeat(32),
+                vout: 0,
+                prevout: Prevout {
+                    scriptpubkey: String::new(),
+                    scriptpubkey_asm: String::new(),
+                    scriptpubkey_type: String::new(),
+                    scriptpubkey_address: String::new(),
+                    value: 0,
+                },
+                scriptsig: None,
+                scriptsig_asm: None,
+                witness: None,
+                is_coinbase: false,
+                sequence: 0xffffffff,
+                inner_redeemscript_asm: None,
+            }],
+            vout: Vec::new(),
+        }
+    }
+
+    fn run(script_hex: &str) -> std::result::Result<Vec<Vec<u8>>, ScriptError> {
+        run_with(&dummy_tx(), script_hex)
+    }
+
+    fn run_with(tx: &Transaction, script_hex: &str) -> std::result::Result<Vec<Vec<u8>>, ScriptError> {
+        run_flags(tx, script_hex, SigVersion::Base, ScriptFlags::CONSENSUS)
+    }
+
+    fn run_flags(
+        tx: &Transaction,
+        script_hex: &str,
+        sig_version: SigVersion,
+        flags: ScriptFlags,
+    ) -> std::result::Result<Vec<Vec<u8>>, ScriptError> {
+        let ctx = ScriptContext { tx, input_index: 0, sig_version, tapscript: None, flags };
+        let mut stack = Vec::new();
+        Script::from_hex(script_hex).unwrap().execute(&mut stack, &ctx)?;
+        Ok(stack)
+    }
+
+    #[test]
+    fn test_arithmetic_and_stack_ops() {
+        // 2 3 OP_ADD 5 OP_EQUAL
+        assert_eq!(run("5253935587").unwrap(), vec![vec![1u8]]);
+        // 1 OP_TOALTSTACK 7 OP_FROMALTSTACK OP_SWAP
+        assert_eq!(run("516b576c7c").unwrap(), vec![vec![1u8], vec![7u8]]);
+        // 1 2 3 OP_ROT
+        assert_eq!(run("5152537b").unwrap(), vec![vec![2u8], vec![3u8], vec![1u8]]);
+        // OP_1NEGATE OP_ABS
+        assert_eq!(run("4f90").unwrap(), vec![vec![1u8]]);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        // 0 OP_IF 2 OP_ELSE 3 OP_ENDIF
+        assert_eq!(run("006352675368").unwrap(), vec![vec![3u8]]);
+        // NESTED: 1 OP_IF 0 OP_IF 2 OP_ELSE 4 OP_ENDIF OP_ENDIF
+        assert_eq!(run("516300635267546868").unwrap(), vec![vec![4u8]]);
+        // A DISABLED OPCODE FAILS EVEN IN AN UNEXECUTED BRANCH
+        assert_eq!(run("00637e68").unwrap_err().kind, ScriptErrorKind::DisabledOpcode);
+        // MISSING OP_ENDIF
+        assert_eq!(run("5163").unwrap_err().kind, ScriptErrorKind::UnbalancedConditional);
+    }
+
+    #[test]
+    fn test_failures_report_opcode() {
+        let error = run("515288").unwrap_err();
+        assert_eq!(error.kind, ScriptErrorKind::EqualVerify);
+        assert_eq!(error.opcode, Some(OP_EQUALVERIFY));
+
+        assert_eq!(run("76").unwrap_err().kind, ScriptErrorKind::InvalidStackOperation);
+        assert_eq!(run("6a").unwrap_err().kind, ScriptErrorKind::OpReturn);
+        assert_eq!(run("4c05aabb").unwrap_err().kind, ScriptErrorKind::BadPush);
+
+        // PUSHES COUNT TOWARDS THE STACK SIZE TOO, 1000 ITEMS ARE THE MOST A SCRIPT CAN LEAVE
+        assert_eq!(run(&"00".repeat(MAX_STACK_SIZE)).unwrap().len(), MAX_STACK_SIZE);
+        assert_eq!(run(&"00".repeat(MAX_STACK_SIZE + 1)).unwrap_err().kind, ScriptErrorKind::StackSize);
+    }
+
+    #[test]
+    fn test_check_lock_time_verify() {
+        let mut tx = dummy_tx();
+        tx.locktime = 600;
+        tx.vin[0].sequence = 0xfffffffe;
+
+        // <500> OP_CLTV LEAVES THE ARGUMENT ON THE STACK
+        assert_eq!(run_with(&tx, "02f401b1").unwrap(), vec![vec![0xf4, 0x01]]);
+        tx.locktime = 499;
+        assert_eq!(run_with(&tx, "02f401b1").unwrap_err().kind, ScriptErrorKind::UnsatisfiedLockTime);
+
+        // A TIME IN THE TRANSACTION CANNOT SATISFY A HEIGHT IN THE SCRIPT, AND A FINAL SEQUENCE NOTHING
+        tx.locktime = LOCKTIME_THRESHOLD + 1;
+        assert_eq!(run_with(&tx, "02f401b1").unwrap_err().kind, ScriptErrorKind::UnsatisfiedLockTime);
+        tx.locktime = 600;
+        tx.vin[0].sequence = SEQUENCE_FINAL;
+        assert_eq!(run_with(&tx, "02f401b1").unwrap_err().kind, ScriptErrorKind::UnsatisfiedLockTime);
+
+        // FIVE BYTE ARGUMENTS, NEGATIVE ONES AND AN EMPTY STACK
+        tx.locktime = u32::MAX;
+        tx.vin[0].sequence = 0;
+        assert!(run_with(&tx, "05ffffffff00b1").is_ok());
+        assert_eq!(run_with(&tx, "4fb1").unwrap_err().kind, ScriptErrorKind::NegativeLockTime);
+        assert_eq!(run_with(&tx, "b1").unwrap_err().kind, ScriptErrorKind::InvalidStackOperation);
+        assert_eq!(run_with(&tx, "06000000000000b1").unwrap_err().kind, ScriptErrorKind::NumOverflow);
+    }
+
+    #[test]
+    fn test_check_sequence_verify() {
+        let mut tx = dummy_tx();
+        tx.vin[0].sequence = 10;
+
+        // <10> OP_CSV
+        assert_eq!(run_with(&tx, "5ab2").unwrap(), vec![vec![10u8]]);
+        tx.vin[0].sequence = 9;
+        assert_eq!(run_with(&tx, "5ab2").unwrap_err().kind, ScriptErrorKind::UnsatisfiedLockTime);
+
+        // <10 UNITS OF 512 SECONDS> NEEDS A TIME LOCK ON THE INPUT, NOT A HEIGHT LOCK
+        tx.vin[0].sequence = 10;
+        assert_eq!(run_with(&tx, "040a004000b2").unwrap_err().kind, ScriptErrorKind::UnsatisfiedLockTime);
+        tx.vin[0].sequence = SEQUENCE_LOCKTIME_TYPE_FLAG | 10;
+        assert!(run_with(&tx, "040a004000b2").is_ok());
+        assert_eq!(run_with(&tx, "5ab2").unwrap_err().kind, ScriptErrorKind::UnsatisfiedLockTime);
+
+        // BIP68 OFF FOR THE INPUT: VERSION 1 OR THE DISABLE FLAG
+        tx.vin[0].sequence = 10;
+        tx.version = 1;
+        assert_eq!(run_with(&tx, "5ab2").unwrap_err().kind, ScriptErrorKind::UnsatisfiedLockTime);
+        tx.version = 2;
+        tx.vin[0].sequence = SEQUENCE_LOCKTIME_DISABLE_FLAG | 10;
+        assert_eq!(run_with(&tx, "5ab2").unwrap_err().kind, ScriptErrorKind::UnsatisfiedLockTime);
+
+        // THE DISABLE FLAG IN THE SCRIPT'S ARGUMENT MAKES IT A NOP, WHATEVER THE TRANSACTION
+        tx.version = 1;
+        assert!(run_with(&tx, "050000008000b2").is_ok());
+        assert_eq!(run_with(&tx, "4fb2").unwrap_err().kind, ScriptErrorKind::NegativeLockTime);
+    }
+
+    #[test]
+    fn test_standard_flags() {
+        let tx = dummy_tx();
+        let standard = |script_hex: &str, sig_version| run_flags(&tx, script_hex, sig_version, ScriptFlags::STANDARD);
+        let consensus = |script_hex: &str, sig_version| run_flags(&tx, script_hex, sig_version, ScriptFlags::CONSENSUS);
+
+        // THE GENERATOR POINT, COMPRESSED AND UNCOMPRESSED
+        let pubkey = "210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        let uncompressed = "410479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798\
+                            483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+
+        // <R=1 S=1 SIGHASH_ALL> <PUBKEY> OP_CHECKSIG: A WRONG SIGNATURE IS FALSE, OR NULLFAIL WHEN STRICT
+        let wrong = format!("09300602010102010101{}ac", pubkey);
+        assert_eq!(consensus(&wrong, SigVersion::Base).unwrap(), vec![Vec::<u8>::new()]);
+        assert_eq!(standard(&wrong, SigVersion::Base).unwrap_err().kind, ScriptErrorKind::NullFail);
+        assert_eq!(standard(&format!("00{}ac", pubkey), SigVersion::Base).unwrap(), vec![Vec::<u8>::new()]);
+
+        // S = N - 1, THE HIGH HALF
+        let high_s = format!("293026020101022100{}01{}ac", "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364140", pubkey);
+        assert_eq!(consensus(&high_s, SigVersion::Base).unwrap(), vec![Vec::<u8>::new()]);
+        assert_eq!(standard(&high_s, SigVersion::Base).unwrap_err().kind, ScriptErrorKind::SigHighS);
+
+        // AN UNDEFINED HASH TYPE, AND AN UNCOMPRESSED KEY UNDER SEGWIT V0
+        let hashtype = format!("09300602010102010105{}ac", pubkey);
+        assert_eq!(standard(&hashtype, SigVersion::Base).unwrap_err().kind, ScriptErrorKind::SigHashType);
+        let segwit_key = format!("00{}ac", uncompressed);
+        assert!(standard(&segwit_key, SigVersion::Base).is_ok());
+        assert_eq!(standard(&segwit_key, SigVersion::WitnessV0).unwrap_err().kind, ScriptErrorKind::PubkeyType);
+
+        // <2> OP_IF: ANY TRUE VALUE FOR CONSENSUS, ONLY EXACTLY 1 UNDER MINIMALIF IN SEGWIT V0
+        assert_eq!(consensus("0102635168", SigVersion::WitnessV0).unwrap(), vec![vec![1u8]]);
+        assert!(standard("0102635168", SigVersion::Base).is_ok());
+        assert_eq!(standard("0102635168", SigVersion::WitnessV0).unwrap_err().kind, ScriptErrorKind::MinimalIf);
+        assert_eq!(standard("51635168", SigVersion::WitnessV0).unwrap(), vec![vec![1u8]]);
+    }
+
+    #[test]
+    fn test_number_encoding() {
+        for value in [0i64, 1, -1, 127, 128, -128, 255, 256, 0x7fffffff, -0x7fffffff] {
+            assert_eq!(decode_num(&encode_num(value), 4).unwrap(), value);
+        }
+        assert_eq!(encode_num(128), vec![0x80, 0x00]);
+        assert_eq!(encode_num(-1), vec![0x81]);
+        assert!(decode_num(&[1, 2, 3, 4, 5], 4).is_err());
+        assert!(!cast_to_bool(&[0x00, 0x80]));
+        assert!(cast_to_bool(&[0x80, 0x00]));
+    }
+
+    #[test]
+    fn test_asm_and_type_match_mempool() -> Result<()> {
+        for entry in std::fs::read_dir("./mempool")? {
+            let transaction: Transaction = serde_json::from_str(&std::fs::read_to_string(entry?.path())?)?;
+            for output in transaction.vout.iter() {
+                let script = Script::from_hex(&output.scriptpubkey)?;
+                assert_eq!(script.to_asm(), output.scriptpubkey_asm);
+                assert_eq!(script.script_type(), output.scriptpubkey_type);
+            }
+            for input in transaction.vin.iter() {
+                let scriptsig = Script::from_hex(input.scriptsig.as_deref().unwrap_or_default())?;
+                assert_eq!(Some(scriptsig.to_asm()), input.scriptsig_asm);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_and_delete() {
+        // <02aabb> OP_CHECKSIG, DELETING THE PUSH OF aabb
+        assert_eq!(find_and_delete(&hex::decode("02aabbac").unwrap(), &push_data(&[0xaa, 0xbb])), vec![OP_CHECKSIG]);
+        // A MATCH INSIDE ANOTHER PUSH IS NOT REMOVED
+        let script = hex::decode("0302aabbac").unwrap();
+        assert_eq!(find_and_delete(&script, &push_data(&[0xaa, 0xbb])), script);
+    }
+
+    #[test]
+    fn test_remove_codeseparators() {
+        // OP_CODESEPARATOR <ab> OP_CHECKSIG OP_CODESEPARATOR, THE PUSHED ab IS DATA AND STAYS
+        assert_eq!(remove_codeseparators(&hex::decode("ab01abacab").unwrap()), hex::decode("01abac").unwrap());
+        // A BROKEN PUSH ENDS THE PARSE, EVERYTHING FROM THERE IS KEPT
+        assert_eq!(remove_codeseparators(&hex::decode("ab4c05abab").unwrap()), hex::decode("4c05abab").unwrap());
+    }
+
+    #[test]
+    fn test_legacy_script_code_skips_codeseparators() {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[0x11; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&secp, &secret_key).serialize();
+        let tx = dummy_tx();
+
+        // SIGNED THE WAY BITCOIN CORE HASHES <PUBKEY> OP_CHECKSIG OP_CODESEPARATOR: THE UNEXECUTED
+        // SEPARATOR IS NOT PART OF THE SCRIPT CODE
+        let script_code = [push_data(&pubkey), vec![OP_CHECKSIG]].concat();
+        let sighash = signature_hash(&tx, 0, &script_code, SigVersion::Base, SIGHASH_ALL).unwrap();
+        let message = Message::from_digest_slice(&sighash).unwrap();
+        let mut signature = secp.sign_ecdsa(&message, &secret_key).serialize_der().to_vec();
+        signature.push(SIGHASH_ALL as u8);
+
+        let script = [push_data(&signature), script_code, vec![OP_CODESEPARATOR]].concat();
+        assert_eq!(run_with(&tx, &hex::encode(script)).unwrap(), vec![vec![1u8]]);
+    }
+}
@@ -0,0 +1,126 @@
+// SIGNATURE OPERATION COST, THE SECOND BUDGET A BLOCK HAS TO STAY UNDER (BIP141)
+//
+// LEGACY SIGOPS IN SCRIPT SIGS AND OUTPUTS AND THOSE IN P2SH REDEEM SCRIPTS COUNT FOUR TIMES, THOSE
+// IN WITNESS SCRIPTS ONCE. TAPSCRIPT HAS ITS OWN PER INPUT BUDGET AND COSTS NOTHING HERE.
+use crate::transaction::{Input, Transaction}; // Importing the Transaction struct
+
+use super::script::Script; // Importing the script parser
+
+pub const MAX_BLOCK_SIGOPS_COST: usize = 80_000;
+pub const WITNESS_SCALE_FACTOR: usize = 4;
+
+// Every sigop in the script sigs and output scripts, multisig always counts 20
+pub fn legacy_sigop_count(tx: &Transaction) -> usize {
+    let inputs: usize = tx.vin.iter().map(|input| script_sig(input).sigop_count(false)).sum();
+    let outputs: usize = tx.vout.iter().map(|output| script(&output.scriptpubkey).sigop_count(false)).sum();
+    inputs + outputs
+}
+
+// The sigops in the redeem scripts of P2SH inputs, multisig counts its keys
+pub fn p2sh_sigop_count(tx: &Transaction) -> usize {
+    tx.vin.iter().filter_map(redeem_script).map(|redeem_script| redeem_script.sigop_count(true)).sum()
+}
+
+// The sigops of native and P2SH wrapped v0 witness programs
+pub fn witness_sigop_count(tx: &Transaction) -> usize {
+    tx.vin.iter().map(input_witness_sigops).sum()
+}
+
+// Same as Bitcoin Core's `GetTransactionSigOpCost`, a coinbase only has legacy sigops
+pub fn transaction_sigop_cost(tx: &Transaction) -> usize {
+    let legacy = legacy_sigop_count(tx) * WITNESS_SCALE_FACTOR;
+    if tx.vin.iter().any(|input| input.is_coinbase) {
+        return legacy;
+    }
+    legacy + p2sh_sigop_count(tx) * WITNESS_SCALE_FACTOR + witness_sigop_count(tx)
+}
+
+fn script(script_hex: &str) -> Script {
+    Script::from_hex(script_hex).unwrap_or_default() // Undecodable hex has nothing to count
+}
+
+fn script_sig(input: &Input) -> Script {
+    script(input.scriptsig.as_deref().unwrap_or_default())
+}
+
+// THE LAST PUSH OF A PUSH ONLY SCRIPT SIG SPENDING A P2SH OUTPUT
+fn redeem_script(input: &Input) -> Option<Script> {
+    if script(&input.prevout.scriptpubkey).script_type() != "p2sh" {
+        return None;
+    }
+    let script_sig = script_sig(input);
+    if !script_sig.is_push_only() {
+        return None;
+    }
+    let last_push = script_sig.instructions().filter_map(|instruction| instruction.ok()?.push_data).last()?;
+    Some(Script::new(last_push.to_vec()))
+}
+
+fn input_witness_sigops(input: &Input) -> usize {
+    let prevout_script = script(&input.prevout.scriptpubkey);
+    let program_script = match prevout_script.witness_program() {
+        Some(_) => prevout_script,
+        None => match redeem_script(input) {
+            Some(redeem_script) if redeem_script.witness_program().is_some() => redeem_script,
+            _ => return 0,
+        },
+    };
+
+    match program_script.witness_program() {
+        Some((0, program)) if program.len() == 20 => 1, // P2WPKH is a single OP_CHECKSIG
+        Some((0, program)) if program.len() == 32 => {
+            let witness_script = input.witness.as_ref().and_then(|witness| witness.last());
+            witness_script.map_or(0, |witness_script| script(witness_script).sigop_count(true))
+        }
+        _ => 0,
+    }
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::Result;
+    use crate::fixtures::load;
+
+    #[test]
+    fn test_script_sigop_count() -> Result<()> {
+        // OP_CHECKSIG OP_CHECKSIGVERIFY, THEN <2> <KEY> <KEY> <KEY> <3> OP_CHECKMULTISIG
+        let key = format!("21{}", "02".repeat(33));
+        let multisig = Script::from_hex(&format!("acad52{}{}{}53ae", key, key, key))?;
+        assert_eq!(multisig.sigop_count(true), 5);
+        assert_eq!(multisig.sigop_count(false), 22);
+
+        // A PUSH THAT RUNS PAST THE END STOPS THE COUNT, PUSHED BYTES ARE NOT OPCODES
+        assert_eq!(Script::from_hex("ac4cffac")?.sigop_count(true), 1);
+        assert_eq!(Script::from_hex("01acac")?.sigop_count(true), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_sigop_cost() -> Result<()> {
+        // P2WSH 2-OF-2 AND A P2PKH OUTPUT
+        let tx = load("0136f8e20b42cf02779feef9f0f2925b5006c9b5d73df15bcbc054e6310cde27")?;
+        assert_eq!((legacy_sigop_count(&tx), p2sh_sigop_count(&tx), witness_sigop_count(&tx)), (1, 0, 2));
+        assert_eq!(transaction_sigop_cost(&tx), 6);
+
+        // P2SH 2-OF-3 AND A P2PKH OUTPUT, THE REDEEM SCRIPT IS DATA TO THE LEGACY COUNT
+        let tx = load("19175e830784abf518b3ca20319177fcdff20b0bc5253b984a4515e85ec116b4")?;
+        assert_eq!((legacy_sigop_count(&tx), p2sh_sigop_count(&tx), witness_sigop_count(&tx)), (1, 3, 0));
+        assert_eq!(transaction_sigop_cost(&tx), 16);
+
+        // P2SH WRAPPED P2WSH 2-OF-3
+        let tx = load("1e3e911e4c25ae4b420ea062f238156d5243cc21d01d6ff81f248c944b8d58a4")?;
+        assert_eq!(transaction_sigop_cost(&tx), 3);
+
+        // TWO BARE MULTISIG OUTPUTS COUNT 20 EACH, PLUS TWO P2WPKH INPUTS
+        let tx = load("0bfa0482c989e84f5d83e338cff2adaa9bd0bb99e4e276ed7dc913be76f8c543")?;
+        assert_eq!(transaction_sigop_cost(&tx), 2 * 20 * WITNESS_SCALE_FACTOR + 2);
+
+        // A COINBASE ONLY COUNTS ITS OUTPUTS
+        let mut coinbase = load("0136f8e20b42cf02779feef9f0f2925b5006c9b5d73df15bcbc054e6310cde27")?;
+        coinbase.vin[0].is_coinbase = true;
+        assert_eq!(transaction_sigop_cost(&coinbase), 4);
+        Ok(())
+    }
+}
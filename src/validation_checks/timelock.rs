@@ -0,0 +1,185 @@
+// FINALITY: NLOCKTIME, BIP68 RELATIVE LOCKS AND BIP113 MEDIAN TIME PAST
+//
+// A TRANSACTION CAN ONLY GO INTO A BLOCK ONCE EVERY LOCK IT SETS HAS EXPIRED. TIME BASED LOCKS
+// ARE COMPARED AGAINST THE MEDIAN TIME PAST OF THE PREVIOUS BLOCK (BIP113), NOT THE HEADER TIME.
+use crate::transaction::Transaction; // Importing the Transaction struct
+
+use super::rejection::Rejection; // Importing the rejection reasons
+
+// BELOW THIS NLOCKTIME IS A BLOCK HEIGHT, FROM IT ON A UNIX TIME
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+// BIP68 SEQUENCE FIELD
+pub const SEQUENCE_FINAL: u32 = 0xffffffff;
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000ffff;
+pub const SEQUENCE_LOCKTIME_GRANULARITY: u32 = 9; // Time locks count in units of 512 seconds
+
+// THE BLOCK BEING BUILT: ITS HEIGHT AND THE MEDIAN TIME PAST OF THE BLOCK BEFORE IT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainTip {
+    pub height: u32,
+    pub median_time_past: u32,
+}
+
+// WHEN THE OUTPUT AN INPUT SPENDS WAS CONFIRMED: ITS BLOCK HEIGHT AND THE MEDIAN TIME PAST OF THE
+// BLOCK BEFORE THAT ONE. AN UNCONFIRMED PARENT CONFIRMS IN THE BLOCK BEING BUILT AT THE EARLIEST.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoinAge {
+    pub height: u32,
+    pub median_time_past: u32,
+}
+
+impl CoinAge {
+    pub fn unconfirmed(tip: &ChainTip) -> CoinAge {
+        CoinAge { height: tip.height, median_time_past: tip.median_time_past }
+    }
+}
+
+// Same rule as Bitcoin Core's `IsFinalTx`, `block_time` is the median time past under BIP113
+pub fn is_final_tx(tx: &Transaction, height: u32, block_time: u32) -> bool {
+    if tx.locktime == 0 {
+        return true;
+    }
+    let reached = if tx.locktime < LOCKTIME_THRESHOLD { height } else { block_time };
+    if tx.locktime < reached {
+        return true;
+    }
+
+    // A LOCKTIME IN THE FUTURE IS IGNORED ONCE EVERY INPUT OPTS OUT
+    tx.vin.iter().all(|input| input.sequence == SEQUENCE_FINAL)
+}
+
+// Checks the absolute lock, then every input's BIP68 relative lock. `coins` has one entry per input,
+// None when it is unknown when the spent output confirmed, such an input is treated as mature.
+pub fn check_final(tx: &Transaction, tip: &ChainTip, coins: &[Option<CoinAge>]) -> Result<(), Rejection> {
+    if !is_final_tx(tx, tip.height, tip.median_time_past) {
+        return Err(Rejection::NonFinalLockTime {
+            locktime: tx.locktime,
+            height: tip.height,
+            median_time_past: tip.median_time_past,
+        });
+    }
+
+    // BIP68 ONLY APPLIES FROM VERSION 2 ON, AND A COINBASE SPENDS NOTHING
+    if tx.version < 2 || tx.vin.iter().any(|input| input.is_coinbase) {
+        return Ok(());
+    }
+
+    for (input_index, input) in tx.vin.iter().enumerate() {
+        if input.sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            continue;
+        }
+        let Some(coin) = coins.get(input_index).copied().flatten() else {
+            continue;
+        };
+        let value = input.sequence & SEQUENCE_LOCKTIME_MASK;
+
+        // BIP68 LOCKS THE INPUT UP TO COIN + VALUE - 1, SO IT IS FREE ONCE THE TIP REACHES COIN + VALUE
+        if input.sequence & SEQUENCE_LOCKTIME_TYPE_FLAG != 0 {
+            let locked_until = coin.median_time_past as u64 + ((value as u64) << SEQUENCE_LOCKTIME_GRANULARITY);
+            if locked_until > tip.median_time_past as u64 {
+                return Err(Rejection::RelativeTimeLock {
+                    input: input_index,
+                    unlocks_after: locked_until.saturating_sub(1),
+                    median_time_past: tip.median_time_past,
+                });
+            }
+        } else {
+            let locked_until = coin.height as u64 + value as u64;
+            if locked_until > tip.height as u64 {
+                return Err(Rejection::RelativeHeightLock {
+                    input: input_index,
+                    unlocks_at: locked_until,
+                    height: tip.height,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+// TO TEST MY CODE DURING DEVELOPMENT
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::Result;
+    use crate::fixtures::load;
+
+    // ONE P2WPKH INPUT, VERSION 2, NO LOCKTIME AND A FINAL SEQUENCE
+    const P2WPKH: &str = "4e8b1cc2c9ed315153b2457dec94128db0ba0a1dcebc136e187d0c4a0a81ce15";
+
+    const TIP: ChainTip = ChainTip { height: 900_000, median_time_past: 1_750_000_000 };
+
+    #[test]
+    fn test_absolute_locktime() -> Result<()> {
+        let mut tx = load(P2WPKH)?;
+        tx.vin[0].sequence = 0xfffffffe;
+
+        // A HEIGHT LOCK IS FINAL FROM THE NEXT HEIGHT ON
+        tx.locktime = 899_999;
+        assert_eq!(check_final(&tx, &TIP, &[None]), Ok(()));
+        tx.locktime = 900_000;
+        assert_eq!(
+            check_final(&tx, &TIP, &[None]),
+            Err(Rejection::NonFinalLockTime { locktime: 900_000, height: 900_000, median_time_past: TIP.median_time_past })
+        );
+
+        // A TIME LOCK IS COMPARED AGAINST THE MEDIAN TIME PAST
+        tx.locktime = TIP.median_time_past - 1;
+        assert!(is_final_tx(&tx, TIP.height, TIP.median_time_past));
+        tx.locktime = TIP.median_time_past;
+        assert!(!is_final_tx(&tx, TIP.height, TIP.median_time_past));
+        assert!(is_final_tx(&tx, TIP.height, TIP.median_time_past + 1));
+
+        // EVERY INPUT AT SEQUENCE_FINAL DISABLES THE LOCK
+        tx.vin[0].sequence = SEQUENCE_FINAL;
+        assert!(is_final_tx(&tx, TIP.height, TIP.median_time_past));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_relative_locks() -> Result<()> {
+        let mut tx = load(P2WPKH)?;
+        let coin = CoinAge { height: 899_990, median_time_past: TIP.median_time_past - 5 * 512 };
+
+        // TEN BLOCKS OLD: A LOCK OF 10 IS MET, 11 IS NOT
+        tx.vin[0].sequence = 10;
+        assert_eq!(check_final(&tx, &TIP, &[Some(coin)]), Ok(()));
+        tx.vin[0].sequence = 11;
+        assert_eq!(
+            check_final(&tx, &TIP, &[Some(coin)]),
+            Err(Rejection::RelativeHeightLock { input: 0, unlocks_at: 900_001, height: 900_000 })
+        );
+
+        // AN UNCONFIRMED PARENT ONLY ALLOWS A ZERO LOCK, UNKNOWN COINS COUNT AS MATURE
+        assert!(check_final(&tx, &TIP, &[Some(CoinAge::unconfirmed(&TIP))]).is_err());
+        assert_eq!(check_final(&tx, &TIP, &[None]), Ok(()));
+        tx.vin[0].sequence = 0;
+        assert_eq!(check_final(&tx, &TIP, &[Some(CoinAge::unconfirmed(&TIP))]), Ok(()));
+
+        // FIVE UNITS OF 512 SECONDS
+        tx.vin[0].sequence = SEQUENCE_LOCKTIME_TYPE_FLAG | 5;
+        assert_eq!(check_final(&tx, &TIP, &[Some(coin)]), Ok(()));
+        tx.vin[0].sequence = SEQUENCE_LOCKTIME_TYPE_FLAG | 6;
+        assert_eq!(
+            check_final(&tx, &TIP, &[Some(coin)]),
+            Err(Rejection::RelativeTimeLock {
+                input: 0,
+                unlocks_after: TIP.median_time_past as u64 + 511,
+                median_time_past: TIP.median_time_past
+            })
+        );
+
+        // THE DISABLE FLAG AND VERSION 1 BOTH TURN BIP68 OFF
+        tx.vin[0].sequence = SEQUENCE_LOCKTIME_DISABLE_FLAG | 11;
+        assert_eq!(check_final(&tx, &TIP, &[Some(coin)]), Ok(()));
+        tx.vin[0].sequence = 11;
+        tx.version = 1;
+        assert_eq!(check_final(&tx, &TIP, &[Some(coin)]), Ok(()));
+
+        Ok(())
+    }
+}